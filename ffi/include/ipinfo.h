@@ -0,0 +1,53 @@
+/*
+ *   Copyright 2019 IPinfo library developers
+ *
+ *   Licensed under the Apache License, Version 2.0 (the "License");
+ *   you may not use this file except in compliance with the License.
+ *   You may obtain a copy of the License at
+ *
+ *       http://www.apache.org/licenses/LICENSE-2.0
+ *
+ *   Unless required by applicable law or agreed to in writing, software
+ *   distributed under the License is distributed on an "AS IS" BASIS,
+ *   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ *   See the License for the specific language governing permissions and
+ *   limitations under the License.
+ */
+
+/* C API of the IPinfo client, built by the ipinfo-ffi crate. */
+
+#ifndef IPINFO_H
+#define IPINFO_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct IpInfo IpInfo;
+
+/* Construct a new client, authenticated with token unless it is NULL. Returns NULL on failure. */
+IpInfo *ipinfo_new(const char *token);
+
+/* Lookup a single IP address, returning its details as a JSON object, or NULL on failure. The
+ * result must be freed with ipinfo_string_free. */
+char *ipinfo_lookup(const IpInfo *ipinfo, const char *ip);
+
+/* Lookup a list of IP addresses, given as a JSON array of strings, returning their details as a
+ * JSON object keyed by IP, or NULL on failure. IPs the API reports an error for are missing from
+ * the object. The result must be freed with ipinfo_string_free. */
+char *ipinfo_lookup_batch(const IpInfo *ipinfo, const char *ips);
+
+/* Get the error message of the last failed call on this thread, or NULL. Must not be freed. */
+const char *ipinfo_last_error(void);
+
+/* Free a string returned by the library. NULL is ignored. */
+void ipinfo_string_free(char *value);
+
+/* Free a client returned by ipinfo_new. NULL is ignored. */
+void ipinfo_free(IpInfo *ipinfo);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* IPINFO_H */
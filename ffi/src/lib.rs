@@ -0,0 +1,244 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! C API of the blocking IPinfo client, declared in `include/ipinfo.h`.
+//!
+//! Lookups go through the client's cache, so a client should be kept for the lifetime of the
+//! application rather than created per lookup. Strings returned by the library must be freed
+//! with [`ipinfo_string_free`]. Panics are caught at the boundary and reported as errors, rather
+//! than unwinding into the calling application, when built with the unwinding `bindings` profile.
+
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use ipinfo::{IpInfo, IpInfoConfig};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Record the error of the last failed call on this thread.
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Run the body of an entry point, returning `failed` and recording the error if it fails or
+/// panics.
+fn guard<T>(failed: T, body: impl FnOnce() -> Result<T, String>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_last_error(message);
+            failed
+        }
+        Err(panic) => {
+            set_last_error(format!("panicked: {}", panic_message(&*panic)));
+            failed
+        }
+    }
+}
+
+/// Get the message of a panic payload.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Convert a C string argument.
+///
+/// # Safety
+///
+/// `value` must be null or a valid nul-terminated string.
+unsafe fn to_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, String> {
+    if value.is_null() {
+        return Err(format!("{} is null", name));
+    }
+    CStr::from_ptr(value)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+/// Convert a JSON response to a C string owned by the caller.
+fn to_json(value: &impl serde::Serialize) -> Result<*mut c_char, String> {
+    let json = serde_json::to_string(value).map_err(|e| e.to_string())?;
+    Ok(CString::new(json).map_err(|e| e.to_string())?.into_raw())
+}
+
+/// Construct a new client, authenticated with `token` unless it is null.
+///
+/// Returns null on failure, see [`ipinfo_last_error`]. The client must be freed with
+/// [`ipinfo_free`].
+///
+/// # Safety
+///
+/// `token` must be null or a valid nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ipinfo_new(token: *const c_char) -> *mut IpInfo {
+    guard(ptr::null_mut(), || {
+        let mut config = IpInfoConfig::default();
+        if !token.is_null() {
+            config.token = Some(to_str(token, "token")?.into());
+        }
+
+        let ipinfo = IpInfo::new(config).map_err(|e| e.to_string())?;
+        Ok(Box::into_raw(Box::new(ipinfo)))
+    })
+}
+
+/// Lookup a single IP address, returning its details as a JSON object.
+///
+/// Returns null on failure, see [`ipinfo_last_error`]. The returned string must be freed with
+/// [`ipinfo_string_free`].
+///
+/// # Safety
+///
+/// `ipinfo` must be a client returned by [`ipinfo_new`] and not yet freed, and `ip` a valid
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ipinfo_lookup(ipinfo: *const IpInfo, ip: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let ipinfo = ipinfo.as_ref().ok_or("ipinfo is null")?;
+        let ip = to_str(ip, "ip")?;
+
+        to_json(&ipinfo.lookup_one(ip).map_err(|e| e.to_string())?)
+    })
+}
+
+/// Lookup a list of IP addresses, given as a JSON array of strings, returning their details as a
+/// JSON object keyed by IP. IPs the API reports an error for are missing from the object.
+///
+/// Returns null on failure, see [`ipinfo_last_error`]. The returned string must be freed with
+/// [`ipinfo_string_free`].
+///
+/// # Safety
+///
+/// `ipinfo` must be a client returned by [`ipinfo_new`] and not yet freed, and `ips` a valid
+/// nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn ipinfo_lookup_batch(
+    ipinfo: *const IpInfo,
+    ips: *const c_char,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let ipinfo = ipinfo.as_ref().ok_or("ipinfo is null")?;
+        let ips: Vec<&str> = serde_json::from_str(to_str(ips, "ips")?)
+            .map_err(|e| format!("ips is not a JSON array of strings: {}", e))?;
+
+        to_json(&ipinfo.lookup(&ips).map_err(|e| e.to_string())?)
+    })
+}
+
+/// Get the error message of the last failed call on this thread, or null if none failed.
+///
+/// The message is owned by the library and valid until the next call on this thread, so it
+/// must not be freed.
+#[no_mangle]
+pub extern "C" fn ipinfo_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Free a string returned by the library. Null is ignored.
+///
+/// # Safety
+///
+/// `value` must be null or a string returned by the library and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipinfo_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+/// Free a client returned by [`ipinfo_new`]. Null is ignored.
+///
+/// # Safety
+///
+/// `ipinfo` must be null or a client returned by [`ipinfo_new`] and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn ipinfo_free(ipinfo: *mut IpInfo) {
+    if !ipinfo.is_null() {
+        guard((), || {
+            drop(Box::from_raw(ipinfo));
+            Ok(())
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(ipinfo_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    #[test]
+    fn lookups_return_json() {
+        unsafe {
+            let ipinfo = ipinfo_new(c"my token".as_ptr());
+            assert!(!ipinfo.is_null());
+
+            let json = ipinfo_lookup(ipinfo, c"10.0.0.1".as_ptr());
+            assert!(!json.is_null());
+            let details: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            assert_eq!(details["ip"], "10.0.0.1");
+            assert_eq!(details["bogon"], true);
+            ipinfo_string_free(json);
+
+            assert!(ipinfo_lookup(ipinfo, c"not an ip".as_ptr()).is_null());
+            assert!(last_error().contains("not an ip"));
+
+            assert!(ipinfo_lookup(ipinfo, ptr::null()).is_null());
+            assert!(last_error().contains("ip is null"));
+
+            let json = ipinfo_lookup_batch(ipinfo, cr#"["10.0.0.1", "::1"]"#.as_ptr());
+            assert!(!json.is_null());
+            let details: serde_json::Value =
+                serde_json::from_str(CStr::from_ptr(json).to_str().unwrap()).unwrap();
+            assert_eq!(details["::1"]["bogon"], true);
+            assert_eq!(details.as_object().unwrap().len(), 2);
+            ipinfo_string_free(json);
+
+            assert!(ipinfo_lookup_batch(ipinfo, c"10.0.0.1".as_ptr()).is_null());
+            assert!(last_error().contains("JSON array"));
+
+            ipinfo_free(ipinfo);
+        }
+    }
+
+    #[test]
+    fn panics_are_reported_as_errors() {
+        let result = guard(ptr::null_mut::<c_char>(), || panic!("boom"));
+
+        assert!(result.is_null());
+        assert_eq!(last_error(), "panicked: boom");
+    }
+}
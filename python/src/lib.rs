@@ -0,0 +1,120 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Python bindings of the blocking IPinfo client, as the `ipinfo` module.
+//!
+//! ```python
+//! import ipinfo
+//!
+//! client = ipinfo.IpInfo("my token")
+//! details = client.lookup("8.8.8.8")
+//! batch = client.lookup_batch(["8.8.8.8", "4.2.2.4"])
+//! ```
+//!
+//! Details are returned as dicts, with the fields of the JSON responses of the API. Lookups go
+//! through the client's cache and release the GIL while they wait for the API, so a client
+//! should be shared by the threads of the application rather than created per lookup.
+
+use ipinfo::{IpError, IpInfo, IpInfoConfig};
+
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+
+create_exception!(ipinfo, IpInfoError, PyException, "A failed IPinfo lookup.");
+
+/// Convert an error of the client to a Python exception.
+fn to_py_err(e: IpError) -> PyErr {
+    IpInfoError::new_err(e.to_string())
+}
+
+/// Convert a response to Python objects, through its JSON representation.
+fn to_py(py: Python<'_>, value: &impl serde::Serialize) -> PyResult<Py<PyAny>> {
+    let json = serde_json::to_string(value).map_err(|e| IpInfoError::new_err(e.to_string()))?;
+    Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+}
+
+/// An IPinfo client, authenticated with `token` unless it is None.
+#[pyclass(name = "IpInfo", module = "ipinfo", frozen)]
+struct PyIpInfo(IpInfo);
+
+#[pymethods]
+impl PyIpInfo {
+    #[new]
+    #[pyo3(signature = (token=None))]
+    fn new(token: Option<String>) -> PyResult<Self> {
+        let config = IpInfoConfig {
+            token: token.map(Into::into),
+            ..Default::default()
+        };
+
+        IpInfo::new(config).map(Self).map_err(to_py_err)
+    }
+
+    /// Lookup a single IP address, returning its details as a dict.
+    fn lookup(&self, py: Python<'_>, ip: &str) -> PyResult<Py<PyAny>> {
+        let details = py.detach(|| self.0.lookup_one(ip)).map_err(to_py_err)?;
+        to_py(py, &details)
+    }
+
+    /// Lookup a list of IP addresses, returning their details as a dict keyed by IP.
+    ///
+    /// IPs the API reports an error for are missing from the dict.
+    fn lookup_batch(&self, py: Python<'_>, ips: Vec<String>) -> PyResult<Py<PyAny>> {
+        let ips: Vec<&str> = ips.iter().map(String::as_str).collect();
+        let details = py.detach(|| self.0.lookup(&ips)).map_err(to_py_err)?;
+        to_py(py, &details)
+    }
+}
+
+#[pymodule(name = "ipinfo")]
+fn ipinfo_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyIpInfo>()?;
+    m.add("IpInfoError", m.py().get_type::<IpInfoError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pyo3::types::PyDict;
+
+    #[test]
+    fn lookups_return_dicts() {
+        Python::initialize();
+        Python::attach(|py| {
+            let client = PyIpInfo::new(Some("my token".to_owned())).unwrap();
+
+            let details = client.lookup(py, "10.0.0.1").unwrap();
+            let details = details.bind(py).cast::<PyDict>().unwrap();
+            let ip: String = details.get_item("ip").unwrap().unwrap().extract().unwrap();
+            assert_eq!(ip, "10.0.0.1");
+            let bogon: bool = details
+                .get_item("bogon")
+                .unwrap()
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert!(bogon);
+
+            let err = client.lookup(py, "not an ip").err().unwrap();
+            assert!(err.is_instance_of::<IpInfoError>(py));
+
+            let ips = vec!["10.0.0.1".to_owned(), "::1".to_owned()];
+            let details = client.lookup_batch(py, ips).unwrap();
+            let details = details.bind(py).cast::<PyDict>().unwrap();
+            assert_eq!(details.len(), 2);
+            assert!(details.contains("::1").unwrap());
+        });
+    }
+}
@@ -0,0 +1,217 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Actix-web middleware enriching requests with the details of their client IP.
+
+use std::{
+    future::{ready, Ready},
+    rc::Rc,
+};
+
+use crate::{forwarded, AsyncIpInfo, MiddlewareConfig};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorInternalServerError,
+    Error, HttpMessage,
+};
+use futures::future::LocalBoxFuture;
+
+/// An actix-web middleware looking up the client IP of every request, and inserting its
+/// [`IpDetails`](crate::IpDetails) into the request extensions.
+///
+/// Without trusted proxies, the client IP is the peer address of the connection.
+/// Lookups go through the client's cache, which every worker shares as they clone the client.
+/// Failed lookups leave the request without details, unless
+/// [`MiddlewareConfig::fail_on_lookup_error`] is set.
+///
+/// Handlers extract the details with `web::ReqData<IpDetails>`, or
+/// `Option<web::ReqData<IpDetails>>` when lookups may fail.
+///
+/// # Examples
+///
+/// ```no_run
+/// use actix_web::{web, App};
+/// use ipinfo::{AsyncIpInfo, IpDetails, IpInfoMiddleware};
+///
+/// async fn country(details: Option<web::ReqData<IpDetails>>) -> String {
+///     details.map(|details| details.country.clone()).unwrap_or_default()
+/// }
+///
+/// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+/// let app = App::new()
+///     .wrap(IpInfoMiddleware::new(ipinfo, Default::default()))
+///     .route("/country", web::get().to(country));
+/// ```
+#[derive(Clone)]
+pub struct IpInfoMiddleware {
+    client: AsyncIpInfo,
+    config: MiddlewareConfig,
+}
+
+impl IpInfoMiddleware {
+    /// Construct a new middleware performing its lookups with a client.
+    pub fn new(client: AsyncIpInfo, config: MiddlewareConfig) -> Self {
+        Self { client, config }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for IpInfoMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = IpInfoMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(IpInfoMiddlewareService {
+            service: Rc::new(service),
+            client: self.client.clone(),
+            config: self.config.clone(),
+        }))
+    }
+}
+
+/// The service wrapped by an [`IpInfoMiddleware`].
+pub struct IpInfoMiddlewareService<S> {
+    service: Rc<S>,
+    client: AsyncIpInfo,
+    config: MiddlewareConfig,
+}
+
+impl<S, B> Service<ServiceRequest> for IpInfoMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let client = self.client.clone();
+        let fail_on_lookup_error = self.config.fail_on_lookup_error;
+        let ip = forwarded::client_ip(
+            self.config.trusted_proxies,
+            |name| {
+                let values = request.headers().get_all(name);
+                values.filter_map(|value| value.to_str().ok())
+            },
+            request.peer_addr().map(|peer| peer.ip()),
+        );
+
+        Box::pin(async move {
+            if let Some(ip) = ip {
+                match client.lookup_addr(ip).await {
+                    Ok(details) => {
+                        request.extensions_mut().insert(details);
+                    }
+                    Err(e) => {
+                        trace_event!(warn, ip = %ip, error = %e, "client ip lookup failed");
+                        if fail_on_lookup_error {
+                            return Err(ErrorInternalServerError(e));
+                        }
+                    }
+                }
+            }
+            service.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{net::SocketAddr, sync::Arc};
+
+    use crate::{
+        testing::{details, MockTransport},
+        IpDetails, IpInfoConfig,
+    };
+
+    use actix_web::{http::StatusCode, test, web, App};
+
+    async fn ip(details: Option<web::ReqData<IpDetails>>) -> String {
+        details
+            .map(|details| details.ip.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the client IP seen by an app, from a request forwarded for 1.1.1.1 by a peer.
+    ///
+    /// The lookups of 4.2.2.4 fail.
+    async fn get(config: MiddlewareConfig, peer: [u8; 4]) -> (StatusCode, String) {
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new()
+                    .with_details(details("8.8.8.8", "US"))
+                    .with_details(details("1.1.1.1", "US")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let app = test::init_service(
+            App::new()
+                .wrap(IpInfoMiddleware::new(ipinfo, config))
+                .route("/", web::get().to(ip)),
+        )
+        .await;
+
+        let request = test::TestRequest::get()
+            .peer_addr(SocketAddr::from((peer, 443)))
+            .insert_header(("x-forwarded-for", "1.1.1.1"))
+            .to_request();
+        match test::try_call_service(&app, request).await {
+            Ok(response) => {
+                let status = response.status();
+                let body = test::read_body(response).await;
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+            Err(e) => (e.as_response_error().status_code(), String::new()),
+        }
+    }
+
+    #[actix_web::test]
+    async fn details_are_inserted_into_extensions() {
+        let config = |trusted_proxies, fail_on_lookup_error| MiddlewareConfig {
+            trusted_proxies,
+            fail_on_lookup_error,
+        };
+
+        assert_eq!(
+            get(config(0, false), [8, 8, 8, 8]).await,
+            (StatusCode::OK, "8.8.8.8".to_owned())
+        );
+        assert_eq!(
+            get(config(1, false), [8, 8, 8, 8]).await,
+            (StatusCode::OK, "1.1.1.1".to_owned())
+        );
+        assert_eq!(
+            get(config(0, false), [4, 2, 2, 4]).await,
+            (StatusCode::OK, String::new())
+        );
+        assert_eq!(
+            get(config(0, true), [4, 2, 2, 4]).await.0,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
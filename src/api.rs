@@ -14,9 +14,9 @@
 
 //! IPinfo API data structures.
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
 /// IP address lookup details.
@@ -28,13 +28,16 @@ pub struct IpDetails {
     /// The reverse DNS lookup hostname of the IP address.
     pub hostname: Option<String>,
 
-    /// The city for the IP address.
+    /// The city for the IP address. (empty for bogons)
+    #[serde(default)]
     pub city: String,
 
-    /// The region for the IP address.
+    /// The region for the IP address. (empty for bogons)
+    #[serde(default)]
     pub region: String,
 
-    /// The country for the IP address.
+    /// The country for the IP address. (empty for bogons)
+    #[serde(default)]
     pub country: String,
 
     /// The countryname for the IP address.
@@ -46,13 +49,17 @@ pub struct IpDetails {
     /// Flag and unicode of the country.
     pub country_flag: Option<CountryFlag>,
 
+    /// The URL of the SVG flag image of the country.
+    pub country_flag_url: Option<String>,
+
     /// Code and symbol of the country's currency.
     pub country_currency: Option<CountryCurrency>,
 
     /// Code and name of the continent.
     pub continent: Option<Continent>,
 
-    /// The geographical location for the IP address.
+    /// The geographical location for the IP address. (empty for bogons)
+    #[serde(default)]
     pub loc: String,
 
     /// The organization for the IP address.
@@ -61,7 +68,9 @@ pub struct IpDetails {
     /// The postal code for the IP address.
     pub postal: Option<String>,
 
-    /// The timezone for the IP address.
+    /// The timezone for the IP address, as an IANA time zone name. (i.e., America/Los_Angeles)
+    ///
+    /// With the `chrono-tz` feature, `IpDetails::tz` parses it into a typed time zone.
     pub timezone: Option<String>,
 
     /// The AS details the IP address is part of.
@@ -82,8 +91,126 @@ pub struct IpDetails {
     /// The hosted domains details for the IP address.
     pub domains: Option<DomainsDetails>,
 
+    /// Whether the IP address is a bogon (private, reserved or otherwise unroutable).
+    pub bogon: Option<bool>,
+
+    /// Whether the IP address is anycast, announced from many locations. (i.e., 8.8.8.8)
+    pub anycast: Option<bool>,
+
+    /// Whether the details come from an expired cache entry, served while revalidating or
+    /// because the API was unavailable.
+    #[serde(skip)]
+    pub stale: bool,
+
+    /// The response fields not modeled by this struct, such as fields added to the API or to
+    /// a plan after this crate was released.
     #[serde(flatten)]
     pub extra: HashMap<String, Value>,
+
+    /// The response exactly as answered by the API, kept when
+    /// [`IpInfoConfig::keep_raw`](crate::IpInfoConfig::keep_raw) is enabled. It is not
+    /// serialized, so it is not persisted by the file or Redis caches.
+    #[serde(skip)]
+    pub raw: Option<Value>,
+}
+
+impl IpDetails {
+    /// Get the latitude and longitude parsed from `loc`.
+    ///
+    /// Returns `None` if `loc` is empty (as for bogons) or malformed, including coordinates out
+    /// of range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpDetails;
+    ///
+    /// let details: IpDetails =
+    ///     serde_json::from_str(r#"{"ip": "8.8.8.8", "loc": "37.4056,-122.0775"}"#).unwrap();
+    /// assert_eq!(details.coordinates(), Some((37.4056, -122.0775)));
+    /// ```
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        let (lat, lon) = self.loc.split_once(',')?;
+        let lat: f64 = lat.trim().parse().ok()?;
+        let lon: f64 = lon.trim().parse().ok()?;
+
+        ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then_some((lat, lon))
+    }
+
+    /// Get the latitude parsed from `loc`, if valid.
+    pub fn latitude(&self) -> Option<f64> {
+        self.coordinates().map(|(lat, _)| lat)
+    }
+
+    /// Get the longitude parsed from `loc`, if valid.
+    pub fn longitude(&self) -> Option<f64> {
+        self.coordinates().map(|(_, lon)| lon)
+    }
+
+    /// Get the great-circle distance in kilometers to the location of other IP details.
+    ///
+    /// Returns `None` if either location is missing or malformed. See [`distance`](crate::distance).
+    pub fn distance_to(&self, other: &IpDetails) -> Option<f64> {
+        Some(crate::distance(self.coordinates()?, other.coordinates()?))
+    }
+
+    /// Get the time zone parsed from `timezone`, for local time arithmetic with chrono.
+    ///
+    /// Returns `None` if `timezone` is missing or isn't a known IANA time zone name.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpDetails;
+    ///
+    /// let details: IpDetails =
+    ///     serde_json::from_str(r#"{"ip": "8.8.8.8", "timezone": "America/Los_Angeles"}"#).unwrap();
+    /// assert_eq!(details.tz(), Some(chrono_tz::America::Los_Angeles));
+    /// ```
+    #[cfg(feature = "chrono-tz")]
+    pub fn tz(&self) -> Option<chrono_tz::Tz> {
+        self.timezone.as_deref()?.parse().ok()
+    }
+}
+
+/// IPinfo Lite API details, limited to the country and ASN of the IP address.
+///
+/// Fields missing from the response, such as for bogons, default to empty strings.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct LiteDetails {
+    /// The IP address.
+    pub ip: String,
+
+    /// The AS number. (i.e., AS15169)
+    #[serde(default)]
+    pub asn: String,
+
+    /// The name of the entity that owns the AS.
+    #[serde(default)]
+    pub as_name: String,
+
+    /// The domain of the entity that owns the AS.
+    #[serde(default)]
+    pub as_domain: String,
+
+    /// The country code. (i.e., US)
+    #[serde(default)]
+    pub country_code: String,
+
+    /// The country name.
+    #[serde(default)]
+    pub country: String,
+
+    /// The continent code. (i.e., NA)
+    #[serde(default)]
+    pub continent_code: String,
+
+    /// The continent name.
+    #[serde(default)]
+    pub continent: String,
+
+    /// Whether the IP address is a bogon (private, reserved or otherwise unroutable).
+    pub bogon: Option<bool>,
 }
 
 /// ASN details.
@@ -107,7 +234,10 @@ pub struct AsnDetails {
 }
 
 /// Company details.
-#[derive(Debug, Deserialize, Serialize, Clone)]
+///
+/// Fields missing from the response default to empty strings.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
 pub struct CompanyDetails {
     /// The name of the entity that owns the IP address.
     pub name: String,
@@ -121,7 +251,10 @@ pub struct CompanyDetails {
 }
 
 /// Mobile carrier details.
-#[derive(Debug, Deserialize, Serialize, Clone)]
+///
+/// Fields missing from the response default to empty strings.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
 pub struct CarrierDetails {
     /// The name of the carrier ISP that owns that mobile IP address.
     pub name: String,
@@ -134,7 +267,10 @@ pub struct CarrierDetails {
 }
 
 /// Privacy details.
-#[derive(Debug, Deserialize, Serialize, Clone)]
+///
+/// Flags missing from the response default to `false`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
 pub struct PrivacyDetails {
     /// Whether this IP address belongs to a VPN.
     pub vpn: bool,
@@ -156,7 +292,10 @@ pub struct PrivacyDetails {
 }
 
 /// Abuse details.
-#[derive(Debug, Deserialize, Serialize, Clone)]
+///
+/// Fields missing from the response default to empty strings.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+#[serde(default)]
 pub struct AbuseDetails {
     /// The abuse contact's address.
     pub address: String,
@@ -183,6 +322,9 @@ pub struct DomainsDetails {
     /// The IP address associated with these hosted domains details.
     pub ip: Option<String>,
 
+    /// The page of the hosted domains, when requested from the domains endpoint.
+    pub page: Option<u64>,
+
     /// The actual total number of domains hosted on this IP address.
     pub total: u64,
 
@@ -210,3 +352,405 @@ pub struct Continent {
     pub code: String,
     pub name: String,
 }
+
+/// A single field of the IP address details which can be requested on its own.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum IpField {
+    /// The IP address.
+    Ip,
+
+    /// The reverse DNS lookup hostname of the IP address.
+    Hostname,
+
+    /// The city for the IP address.
+    City,
+
+    /// The region for the IP address.
+    Region,
+
+    /// The country for the IP address.
+    Country,
+
+    /// The geographical location for the IP address.
+    Loc,
+
+    /// The organization for the IP address.
+    Org,
+
+    /// The postal code for the IP address.
+    Postal,
+
+    /// The timezone for the IP address.
+    Timezone,
+}
+
+impl IpField {
+    /// Get the API path segment of the field.
+    pub fn as_str(&self) -> &str {
+        match self {
+            IpField::Ip => "ip",
+            IpField::Hostname => "hostname",
+            IpField::City => "city",
+            IpField::Region => "region",
+            IpField::Country => "country",
+            IpField::Loc => "loc",
+            IpField::Org => "org",
+            IpField::Postal => "postal",
+            IpField::Timezone => "timezone",
+        }
+    }
+}
+
+impl fmt::Display for IpField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// ASN endpoint lookup details.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AsnResponse {
+    /// The AS number.
+    pub asn: String,
+
+    /// The name of the entity that owns this AS.
+    pub name: String,
+
+    /// The country code of the entity that owns this AS.
+    pub country: String,
+
+    /// The date this AS was allocated.
+    pub allocated: Option<String>,
+
+    /// The regional internet registry this AS is registered with.
+    pub registry: Option<String>,
+
+    /// The domain for the entity that owns this AS.
+    pub domain: Option<String>,
+
+    /// The number of IP addresses announced by this AS.
+    pub num_ips: Option<u64>,
+
+    /// The entity type that owns this AS. (i.e., business, education, hosting, isp)
+    #[serde(rename = "type")]
+    pub asn_type: Option<String>,
+
+    /// The IPv4 prefixes announced by this AS.
+    #[serde(default)]
+    pub prefixes: Vec<AsnPrefix>,
+
+    /// The IPv6 prefixes announced by this AS.
+    #[serde(default)]
+    pub prefixes6: Vec<AsnPrefix>,
+
+    /// The peers of this AS.
+    pub peers: Option<Vec<String>>,
+
+    /// The upstreams of this AS.
+    pub upstreams: Option<Vec<String>>,
+
+    /// The downstreams of this AS.
+    pub downstreams: Option<Vec<String>>,
+}
+
+/// A prefix announced by an AS.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AsnPrefix {
+    /// The announced network block.
+    pub netblock: String,
+
+    /// The identifier of the network block.
+    #[serde(default)]
+    pub id: String,
+
+    /// The name of the network block.
+    #[serde(default)]
+    pub name: String,
+
+    /// The country of the network block.
+    #[serde(default)]
+    pub country: String,
+
+    /// The size of the network block.
+    pub size: Option<String>,
+
+    /// The status of the network block.
+    pub status: Option<String>,
+
+    /// The domain of the network block.
+    pub domain: Option<String>,
+}
+
+/// Ranges endpoint lookup details.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RangesResponse {
+    /// The domain the ranges belong to.
+    pub domain: String,
+
+    /// The number of ranges announced for the domain.
+    #[serde(deserialize_with = "number_from_string")]
+    pub num_ranges: u64,
+
+    /// The CIDR ranges announced for the domain.
+    pub ranges: Vec<String>,
+}
+
+/// Map endpoint report details.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct MapResponse {
+    /// The status of the report generation. (i.e., Report Generated)
+    #[serde(default)]
+    pub status: String,
+
+    /// The shareable URL of the map of the IP addresses.
+    #[serde(rename = "reportUrl")]
+    pub report_url: String,
+}
+
+/// Details of the configured token, as reported by the `/me` endpoint.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TokenDetails {
+    /// The request usage of the token.
+    #[serde(default)]
+    pub requests: TokenRequests,
+
+    /// The plan features available to the token, keyed by name. (i.e., core, asn, ranges)
+    #[serde(default)]
+    pub features: HashMap<String, Value>,
+}
+
+impl TokenDetails {
+    /// Check whether a plan feature is available to the token.
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains_key(feature)
+    }
+}
+
+/// Request usage of a token.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct TokenRequests {
+    /// The number of requests made today.
+    #[serde(default)]
+    pub day: u64,
+
+    /// The number of requests made this month.
+    #[serde(default)]
+    pub month: u64,
+
+    /// The monthly request limit of the plan.
+    #[serde(default)]
+    pub limit: u64,
+
+    /// The number of requests remaining this month.
+    #[serde(default)]
+    pub remaining: u64,
+}
+
+/// Deserialize a number the API may encode as a string.
+fn number_from_string<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s.parse().map_err(de::Error::custom),
+        StringOrNumber::Number(n) => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::google_dns_with as details;
+
+    #[test]
+    fn details_serialize_round_trip() {
+        let mut details = details(serde_json::json!({ "residential": true }));
+        details.country_flag = Some(CountryFlag {
+            emoji: "🇺🇸".to_owned(),
+            unicode: "U+1F1FA U+1F1F8".to_owned(),
+        });
+        details.country_currency = Some(CountryCurrency {
+            code: "USD".to_owned(),
+            symbol: "$".to_owned(),
+        });
+        details.continent = Some(Continent {
+            code: "NA".to_owned(),
+            name: "North America".to_owned(),
+        });
+
+        let json = serde_json::to_value(&details).expect("should serialize");
+        assert_eq!(json["continent"]["name"], "North America");
+        assert_eq!(json["residential"], true);
+
+        let parsed: IpDetails = serde_json::from_value(json).expect("should parse");
+        assert_eq!(parsed.country_flag, details.country_flag);
+        assert_eq!(parsed.country_currency, details.country_currency);
+        assert_eq!(parsed.continent, details.continent);
+        assert_eq!(parsed.extra, details.extra);
+    }
+
+    #[test]
+    fn coordinates_are_parsed_from_loc() {
+        let details = |loc: &str| self::details(serde_json::json!({ "loc": loc }));
+
+        assert_eq!(details("37.4056,-122.0775").latitude(), Some(37.4056));
+        assert_eq!(details("37.4056,-122.0775").longitude(), Some(-122.0775));
+        assert_eq!(
+            details(" -33.8688, 151.2093").coordinates(),
+            Some((-33.8688, 151.2093))
+        );
+        assert_eq!(details("").coordinates(), None);
+        assert_eq!(details("37.4056").coordinates(), None);
+        assert_eq!(details("north,west").coordinates(), None);
+        assert_eq!(details("91.0,0.0").coordinates(), None);
+        assert_eq!(details("0.0,NaN").coordinates(), None);
+    }
+
+    #[test]
+    fn distance_to_requires_both_locations() {
+        let mountain_view = details(serde_json::json!({}));
+        let sydney = details(serde_json::json!({ "loc": "-33.8688,151.2093" }));
+        let bogon = details(serde_json::json!({ "loc": "" }));
+
+        assert_eq!(
+            mountain_view.distance_to(&sydney).map(f64::round),
+            Some(11_954.0)
+        );
+        assert_eq!(mountain_view.distance_to(&mountain_view), Some(0.0));
+        assert_eq!(mountain_view.distance_to(&bogon), None);
+        assert_eq!(bogon.distance_to(&sydney), None);
+    }
+
+    #[test]
+    fn lite_details_are_parsed() {
+        let details: LiteDetails = serde_json::from_value(serde_json::json!({
+            "ip": "8.8.8.8",
+            "asn": "AS15169",
+            "as_name": "Google LLC",
+            "as_domain": "google.com",
+            "country_code": "US",
+            "country": "United States",
+            "continent_code": "NA",
+            "continent": "North America"
+        }))
+        .expect("should parse");
+        assert_eq!(details.asn, "AS15169");
+        assert_eq!(details.continent, "North America");
+
+        let details: LiteDetails =
+            serde_json::from_value(serde_json::json!({ "ip": "10.0.0.1", "bogon": true }))
+                .expect("should parse");
+        assert_eq!(details.country_code, "");
+        assert_eq!(details.bogon, Some(true));
+    }
+
+    #[test]
+    fn unknown_fields_are_kept() {
+        let details = details(serde_json::json!({
+            "residential": { "is_residential": true }
+        }));
+
+        assert_eq!(details.extra["residential"]["is_residential"], true);
+        let json = serde_json::to_value(&details).unwrap();
+        assert_eq!(json["residential"]["is_residential"], true);
+    }
+
+    #[test]
+    fn token_details_are_parsed() {
+        let details: TokenDetails = serde_json::from_value(serde_json::json!({
+            "token": "secret",
+            "requests": { "day": 1, "month": 5, "limit": 50000, "remaining": 49995 },
+            "features": { "core": { "daily": 50000, "monthly": 50000 } }
+        }))
+        .expect("should parse");
+
+        assert_eq!(details.requests.remaining, 49995);
+        assert!(details.has_feature("core"));
+        assert!(!details.has_feature("ranges"));
+    }
+
+    #[test]
+    fn privacy_details_tolerate_missing_flags() {
+        let details = details(serde_json::json!({
+            "privacy": { "vpn": true, "hosting": true }
+        }));
+
+        let privacy = details.privacy.expect("should have privacy");
+        assert!(privacy.vpn);
+        assert!(privacy.hosting);
+        assert!(!privacy.tor);
+        assert_eq!(privacy.service, "");
+    }
+
+    #[test]
+    fn company_details_tolerate_missing_fields() {
+        let details = details(serde_json::json!({
+            "company": { "name": "Google LLC", "type": "hosting" }
+        }));
+
+        let company = details.company.expect("should have company");
+        assert_eq!(company.name, "Google LLC");
+        assert_eq!(company.company_type, "hosting");
+        assert_eq!(company.domain, "");
+    }
+
+    #[test]
+    fn carrier_details_tolerate_missing_fields() {
+        let details = details(serde_json::json!({
+            "carrier": { "name": "Sprint", "mcc": "310" }
+        }));
+
+        let carrier = details.carrier.expect("should have carrier");
+        assert_eq!(carrier.name, "Sprint");
+        assert_eq!(carrier.mcc, "310");
+        assert_eq!(carrier.mnc, "");
+    }
+
+    #[test]
+    fn abuse_details_tolerate_missing_fields() {
+        let details = details(serde_json::json!({
+            "abuse": {
+                "address": "US, CA, Mountain View, 1600 Amphitheatre Parkway, 94043",
+                "country": "US",
+                "email": "network-abuse@google.com",
+                "network": "8.8.8.0/24"
+            }
+        }));
+
+        let abuse = details.abuse.expect("should have abuse");
+        assert_eq!(abuse.email, "network-abuse@google.com");
+        assert_eq!(abuse.network, "8.8.8.0/24");
+        assert_eq!(abuse.name, "");
+        assert_eq!(abuse.phone, "");
+    }
+
+    #[test]
+    fn bogon_response_is_parsed() {
+        let details: IpDetails =
+            serde_json::from_str(r#"{"ip": "10.0.0.1", "bogon": true}"#).expect("should parse");
+
+        assert_eq!(details.ip, "10.0.0.1");
+        assert_eq!(details.bogon, Some(true));
+        assert!(details.country.is_empty());
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    #[test]
+    fn time_zone_is_parsed_from_timezone() {
+        let details = |timezone: Value| self::details(serde_json::json!({ "timezone": timezone }));
+
+        assert_eq!(
+            details("Europe/Paris".into()).tz(),
+            Some(chrono_tz::Europe::Paris)
+        );
+        assert_eq!(details("Mars/Olympus_Mons".into()).tz(), None);
+        assert_eq!(details(Value::Null).tz(), None);
+    }
+}
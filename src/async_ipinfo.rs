@@ -0,0 +1,1289 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Async IPinfo client.
+//!
+//! Retries and hostname resolution don't depend on a runtime, so lookups can be awaited on any
+//! executor given an [`AsyncTransport`] that can. The default reqwest transport and
+//! [`AsyncIpInfo::prefetch`] need a tokio runtime, except on WASM where requests go through the
+//! browser's fetch API.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use crate::{
+    bogon::bogon_details,
+    client,
+    context::{
+        expand_cidr, key_by_addr, normalize_asn, parse_response, validate_domain, validate_ip,
+        validate_ips, Attempts, Context, Next, BATCH_MAX_SIZE,
+    },
+    flight::Flight,
+    AsnResponse, AsyncReqwestTransport, AsyncTransport, CacheStats, Continent, CountryCurrency,
+    CountryData, CountryFlag, DomainsDetails, HttpRequest, IpDetails, IpError, IpField,
+    IpInfoConfig, LiteDetails, MapResponse, RangesResponse, RateLimit, SecretToken, TokenDetails,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::context::resolve_host;
+
+#[cfg(not(target_arch = "wasm32"))]
+use futures::channel::oneshot;
+use futures::{stream, Stream, StreamExt};
+use futures_timer::Delay;
+use serde_json::json;
+use web_time::Instant;
+
+/// Async IPinfo requests context structure.
+///
+/// Behaves exactly like [`IpInfo`](crate::IpInfo), but performs HTTP requests with the
+/// async reqwest client so lookups can be awaited without blocking the runtime. Lookups take
+/// `&self`, so they can be awaited concurrently on a shared client, and cloning is cheap.
+#[derive(Clone)]
+pub struct AsyncIpInfo {
+    transport: Arc<dyn AsyncTransport>,
+    context: Arc<Context>,
+}
+
+impl AsyncIpInfo {
+    /// Construct a new AsyncIpInfo structure.
+    ///
+    /// Fails with an `InvalidConfigError` if `cache_size` is 0, or an `AssetLoadError` if the
+    /// country data files can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// ```
+    pub fn new(config: IpInfoConfig) -> Result<Self, IpError> {
+        let transport = match &config.async_transport {
+            Some(transport) => transport.clone(),
+            None => {
+                let builder = client::configure(reqwest::Client::builder(), &config)?;
+                Arc::new(AsyncReqwestTransport::new(builder.build()?))
+            }
+        };
+
+        Ok(Self {
+            transport,
+            context: Arc::new(Context::new(config)?),
+        })
+    }
+
+    /// Construct a new AsyncIpInfo structure with the default configuration and the token read from
+    /// the `IPINFO_TOKEN` environment variable, if set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::from_env().expect("should construct");
+    /// ```
+    pub fn from_env() -> Result<Self, IpError> {
+        Self::new(IpInfoConfig {
+            token_from_env: true,
+            ..Default::default()
+        })
+    }
+
+    /// Lookup a list of one or more IP addresses.
+    ///
+    /// Lists larger than the batch API limit of 1000 IPs are split into multiple requests. IPs
+    /// the API reports an error for are left out of the result, with or without negative
+    /// caching, and so are IPs whose error is still negatively cached: the result can miss
+    /// requested IPs, so get them rather than index it. See [`lookup_each`](AsyncIpInfo::lookup_each)
+    /// for their errors.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup(&["8.8.8.8"]).await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub async fn lookup(&self, ips: &[&str]) -> Result<HashMap<String, IpDetails>, IpError> {
+        // Leave out the IPs with a recently reported error
+        let ips = self.context.check_ips(ips)?;
+
+        // Check for cache hits
+        let (hits, misses) = self.context.check_cache(&ips);
+        self.revalidate();
+
+        self.lookup_misses(hits, &misses).await
+    }
+
+    /// Lookup a list of one or more IP addresses, authenticating with another token than the
+    /// configured one.
+    ///
+    /// This lets multi-tenant services use the token of each customer while sharing a single
+    /// client and its cache.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo
+    ///     .lookup_with_token(&["8.8.8.8"], "customer token")
+    ///     .await
+    ///     .expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub async fn lookup_with_token(
+        &self,
+        ips: &[&str],
+        token: impl Into<SecretToken>,
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        let tenant = AsyncIpInfo {
+            transport: self.transport.clone(),
+            context: Arc::new(self.context.with_token(token.into())?),
+        };
+        let result = tenant.lookup(ips).await;
+        self.context.merge_stats(&tenant.context);
+
+        result
+    }
+
+    /// Lookup a list of one or more IP addresses, ignoring cached entries.
+    ///
+    /// Every IP except bogons is queried from the API, and the cache is updated with the
+    /// results.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_fresh(&["8.8.8.8"]).await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub async fn lookup_fresh(&self, ips: &[&str]) -> Result<HashMap<String, IpDetails>, IpError> {
+        validate_ips(ips)?;
+
+        // Only answer bogons locally
+        let (bogons, misses) = self.context.check_bogons(ips);
+
+        self.lookup_misses(bogons, &misses).await
+    }
+
+    /// Query the API for the IPs missing from the cache, then merge in the cache hits.
+    async fn lookup_misses(
+        &self,
+        mut hits: Vec<IpDetails>,
+        misses: &[&str],
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        // Return directly from the cache when there is nothing to query
+        if misses.is_empty() {
+            return Ok(hits.into_iter().map(|x| (x.ip.clone(), x)).collect());
+        }
+
+        // Only query the IPs not already being looked up by another task
+        let (claim, owned, joined) = self.context.flights.claim(misses);
+
+        // Lookup cache misses
+        let mut details = HashMap::new();
+        for (chunk, raw_resp) in self.send_batches(&owned).await {
+            details.extend(self.context.process_batch_or_stale(chunk, raw_resp)?);
+        }
+        drop(claim);
+
+        // Share the results of the joined lookups, querying again the IPs they failed
+        let (shared, failed) = self.join_flights(joined).await;
+        hits.extend(shared);
+        for (chunk, raw_resp) in self.send_batches(&failed).await {
+            details.extend(self.context.process_batch_or_stale(chunk, raw_resp)?);
+        }
+
+        // Add cache hits to the result
+        hits.into_iter().for_each(|x| {
+            details.insert(x.ip.clone(), x);
+        });
+
+        Ok(details)
+    }
+
+    /// Lookup a single IP address using the single IP endpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_one("8.8.8.8").await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn lookup_one(&self, ip: &str) -> Result<IpDetails, IpError> {
+        // Fail with a recently reported error
+        self.context.check_ip(ip)?;
+
+        // Check for a cache hit
+        if let Some(detail) = self.context.get_cached(ip) {
+            self.revalidate();
+            return Ok(detail);
+        }
+
+        // Share the result of an identical lookup by another task
+        let (_claim, _, joined) = self.context.flights.claim(&[ip]);
+        if let Some((_, flight)) = joined.first() {
+            flight.wait_async().await;
+            if let Some(detail) = self.context.get_joined(ip) {
+                return Ok(detail);
+            }
+        }
+
+        let request = self.context.get(format!("{}/{}", self.context.url, ip));
+        let raw_resp = match self.send(request).await {
+            Ok(raw_resp) => raw_resp,
+            Err(e) => return self.context.stale_or(ip, e),
+        };
+
+        let details = self.context.process_single(&raw_resp);
+        if let Err(e) = &details {
+            self.context.cache_negative(ip, e);
+        }
+        details
+    }
+
+    /// Lookup a list of one or more IP addresses, with a separate result for each IP.
+    ///
+    /// Unlike [`lookup`](AsyncIpInfo::lookup), an invalid IP or a failed batch request only produces
+    /// errors for the IPs concerned, and the results for every other IP are still returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_each(&["8.8.8.8", "foo"]).await;
+    /// assert!(res["foo"].is_err());
+    /// # }
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub async fn lookup_each(&self, ips: &[&str]) -> HashMap<String, Result<IpDetails, IpError>> {
+        let mut results = HashMap::new();
+
+        // Reject invalid IPs and recently reported errors individually
+        let mut valid = vec![];
+        for ip in ips {
+            match self.context.check_ip(ip) {
+                Ok(()) => valid.push(*ip),
+                Err(e) => {
+                    results.insert(ip.to_string(), Err(e));
+                }
+            }
+        }
+
+        // Check for cache hits
+        let (hits, misses) = self.context.check_cache(&valid);
+        self.revalidate();
+
+        // Only query the IPs not already being looked up by another task
+        let (claim, owned, joined) = self.context.flights.claim(&misses);
+
+        // Lookup cache misses
+        for (chunk, raw_resp) in self.send_batches(&owned).await {
+            results.extend(self.context.process_batch_each(chunk, raw_resp));
+        }
+        drop(claim);
+
+        // Share the results of the joined lookups, querying again the IPs they failed
+        let (shared, failed) = self.join_flights(joined).await;
+        shared.into_iter().for_each(|x| {
+            results.insert(x.ip.clone(), Ok(x));
+        });
+        for (chunk, raw_resp) in self.send_batches(&failed).await {
+            results.extend(self.context.process_batch_each(chunk, raw_resp));
+        }
+
+        // Add cache hits to the result
+        hits.into_iter().for_each(|x| {
+            results.insert(x.ip.clone(), Ok(x));
+        });
+
+        results
+    }
+
+    /// Lookup a list of IP addresses, yielding the result of each IP as soon as its batch
+    /// request completes.
+    ///
+    /// The list is split into chunks of up to 1000 IPs, each looked up like
+    /// [`lookup_each`](AsyncIpInfo::lookup_each), so large inputs can be processed before every
+    /// batch request completes. Results are yielded in completion order.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let mut results = ipinfo.lookup_stream(&["8.8.8.8", "4.2.2.4"]);
+    /// while let Some((ip, res)) = results.next().await {
+    ///     println!("{}: {:?}", ip, res.map(|details| details.country));
+    /// }
+    /// # }
+    /// ```
+    pub fn lookup_stream<'a>(
+        &'a self,
+        ips: &'a [&'a str],
+    ) -> impl Stream<Item = (String, Result<IpDetails, IpError>)> + 'a {
+        stream::iter(ips.chunks(BATCH_MAX_SIZE))
+            .map(move |chunk| self.lookup_each(chunk))
+            .buffer_unordered(self.context.max_concurrency)
+            .flat_map(stream::iter)
+    }
+
+    /// Lookup a list of one or more parsed IP addresses.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::Ipv4Addr;
+    ///
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo
+    ///     .lookup_addrs(&[Ipv4Addr::new(8, 8, 8, 8)])
+    ///     .await
+    ///     .expect("should run");
+    /// # }
+    /// ```
+    pub async fn lookup_addrs<T: Into<IpAddr> + Copy>(
+        &self,
+        ips: &[T],
+    ) -> Result<HashMap<IpAddr, IpDetails>, IpError> {
+        let ips: Vec<String> = ips.iter().map(|ip| (*ip).into().to_string()).collect();
+        let ips: Vec<&str> = ips.iter().map(String::as_str).collect();
+
+        Ok(key_by_addr(self.lookup(&ips).await?))
+    }
+
+    /// Resolve a hostname to its A and AAAA records, then lookup all the resulting IP addresses.
+    ///
+    /// The hostname is resolved on a thread of its own, so the runtime is never blocked. Fails
+    /// with a `DnsError` if it can't be resolved.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_host("example.com").await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn lookup_host(&self, host: &str) -> Result<HashMap<IpAddr, IpDetails>, IpError> {
+        let (sender, receiver) = oneshot::channel();
+        let owned = host.to_owned();
+        thread::spawn(move || sender.send(resolve_host(&owned)));
+        let ips = receiver
+            .await
+            .map_err(|e| err!(DnsError, "cannot resolve {}: {}", host, e))??;
+
+        self.lookup_addrs(&ips).await
+    }
+
+    /// Lookup every address of a CIDR range, such as `203.0.113.0/28`, in batch requests.
+    ///
+    /// Fails with an `InvalidIpError` if the range is malformed or has more addresses than
+    /// `max_cidr_hosts`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_cidr("8.8.8.0/28").await.expect("should run");
+    /// # }
+    /// ```
+    pub async fn lookup_cidr(&self, cidr: &str) -> Result<HashMap<IpAddr, IpDetails>, IpError> {
+        self.lookup_addrs(&expand_cidr(cidr, self.context.max_cidr_hosts)?)
+            .await
+    }
+
+    /// Lookup a single parsed IP address using the single IP endpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::Ipv4Addr;
+    ///
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_addr(Ipv4Addr::new(8, 8, 8, 8)).await.expect("should run");
+    /// # }
+    /// ```
+    pub async fn lookup_addr(&self, ip: impl Into<IpAddr>) -> Result<IpDetails, IpError> {
+        self.lookup_one(&ip.into().to_string()).await
+    }
+
+    /// Lookup the country and ASN of a single IP address using the free IPinfo Lite API.
+    ///
+    /// Bogons are answered locally. Lite responses are not cached.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{AsyncIpInfo, IpInfoConfig};
+    ///
+    /// # async fn run() {
+    /// let config = IpInfoConfig {
+    ///     token: Some("my token".into()),
+    ///     ..Default::default()
+    /// };
+    /// let ipinfo = AsyncIpInfo::new(config).expect("should construct");
+    /// let res = ipinfo.lookup_lite("8.8.8.8").await.expect("should run");
+    /// println!("{} ({})", res.country, res.as_name);
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn lookup_lite(&self, ip: &str) -> Result<LiteDetails, IpError> {
+        validate_ip(ip)?;
+
+        if let Some(details) = bogon_details(ip) {
+            return Ok(LiteDetails {
+                ip: details.ip,
+                bogon: details.bogon,
+                ..Default::default()
+            });
+        }
+
+        let request = self
+            .context
+            .get(format!("{}/{}", self.context.lite_url, ip));
+        let raw_resp = self.send(request).await?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Lookup the details of the IP address making the request.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_self().await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn lookup_self(&self) -> Result<IpDetails, IpError> {
+        let request = self.context.get(format!("{}/json", self.context.url));
+        let raw_resp = self.send(request).await?;
+
+        self.context.process_single(&raw_resp)
+    }
+
+    /// Lookup a single field of the details of an IP address.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{AsyncIpInfo, IpField};
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let country = ipinfo.lookup_field("8.8.8.8", IpField::Country).await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn lookup_field(&self, ip: &str, field: IpField) -> Result<String, IpError> {
+        validate_ips(&[ip])?;
+
+        let request = self
+            .context
+            .get(format!("{}/{}/{}", self.context.url, ip, field));
+        let raw_resp = self.send(request).await?;
+
+        Ok(raw_resp.trim().to_owned())
+    }
+
+    /// Lookup the details of an autonomous system.
+    ///
+    /// The AS number may be given with or without its `AS` prefix. Fails with a `ParseError` if it
+    /// isn't an AS number.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_asn("AS7922").await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn lookup_asn(&self, asn: &str) -> Result<AsnResponse, IpError> {
+        let asn = normalize_asn(asn)?;
+
+        // Check for a cache hit
+        if let Some(detail) = self.context.get_cached_asn(&asn) {
+            return Ok(detail);
+        }
+
+        let request = self
+            .context
+            .get(format!("{}/{}/json", self.context.url, asn));
+        let raw_resp = self.send(request).await?;
+
+        self.context.process_asn(&raw_resp)
+    }
+
+    /// Get the IP ranges announced for a domain.
+    ///
+    /// Fails with a `ParseError` if the domain isn't a hostname.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.get_ranges("comcast.net").await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn get_ranges(&self, domain: &str) -> Result<RangesResponse, IpError> {
+        validate_domain(domain)?;
+
+        let request = self
+            .context
+            .get(format!("{}/ranges/{}", self.context.url, domain));
+        let raw_resp = self.send(request).await?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Get a page of the domains hosted on an IP address.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.get_domains("1.1.1.1", 0, 100).await.expect("should run");
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn get_domains(
+        &self,
+        ip: &str,
+        page: u32,
+        limit: u32,
+    ) -> Result<DomainsDetails, IpError> {
+        validate_ips(&[ip])?;
+
+        let request = self.context.get(format!(
+            "{}/domains/{}?page={}&limit={}",
+            self.context.url, ip, page, limit
+        ));
+        let raw_resp = self.send(request).await?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Create a map of IP addresses, getting the URL of a shareable report visualizing them.
+    ///
+    /// Fails with an `InvalidIpError` if any of the IP addresses is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let map = ipinfo.create_map(&["8.8.8.8", "1.1.1.1"]).await.expect("should run");
+    /// println!("{}", map.report_url);
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn create_map(&self, ips: &[&str]) -> Result<MapResponse, IpError> {
+        validate_ips(ips)?;
+
+        let request = self.context.post(
+            format!("{}/tools/map?cli=1", self.context.url),
+            json!(ips).to_string(),
+        );
+        let raw_resp = self.send(request).await?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Verify the configured token, getting its usage and the plan features available to it.
+    ///
+    /// Fails with an `UnauthorizedError` if no token is configured or the API rejects it, so
+    /// services can check their configuration at startup.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{AsyncIpInfo, IpInfoConfig};
+    ///
+    /// # async fn run() {
+    /// let config = IpInfoConfig {
+    ///     token: Some("my token".into()),
+    ///     ..Default::default()
+    /// };
+    /// let ipinfo = AsyncIpInfo::new(config).expect("should construct");
+    /// let details = ipinfo.verify_token().await.expect("token should be valid");
+    /// println!("{} requests remaining", details.requests.remaining);
+    /// # }
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub async fn verify_token(&self) -> Result<TokenDetails, IpError> {
+        let request = self.context.token_request()?;
+        let raw_resp = self.send(request).await?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Populate the cache with the details of IP addresses in a background task.
+    ///
+    /// Cached IPs and bogons are skipped. The returned handle can be awaited for the outcome,
+    /// or dropped to let the prefetch run detached.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime. Not available on WASM, which has no tokio
+    /// runtime; lookups there are awaited directly.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// # async fn run() {
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let handle = ipinfo.prefetch(&["8.8.8.8", "1.1.1.1"]);
+    /// # }
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn prefetch(&self, ips: &[&str]) -> tokio::task::JoinHandle<Result<(), IpError>> {
+        self.spawn_lookup(ips, false)
+    }
+
+    /// Get the rate limit reported by the last API response that had one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// if let Some(rate_limit) = ipinfo.last_rate_limit() {
+    ///     println!("{:?} requests remaining", rate_limit.remaining);
+    /// }
+    /// ```
+    pub fn last_rate_limit(&self) -> Option<RateLimit> {
+        self.context.last_rate_limit()
+    }
+
+    /// Get the usage statistics of the IP details cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let stats = ipinfo.cache_stats();
+    /// assert_eq!(stats.hits, 0);
+    /// ```
+    pub fn cache_stats(&self) -> CacheStats {
+        self.context.cache_stats()
+    }
+
+    /// Get the country data used to enrich IP details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let countries = ipinfo.country_data();
+    /// let eu = countries.is_eu("DE");
+    /// ```
+    pub fn country_data(&self) -> CountryData {
+        self.context.countries()
+    }
+
+    /// Get the name of a country from its code, in the configured locale.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let name = ipinfo.get_country_name("DE");
+    /// ```
+    pub fn get_country_name(&self, code: &str) -> Option<String> {
+        self.context
+            .countries()
+            .country_name(code)
+            .map(str::to_owned)
+    }
+
+    /// Check whether a country is a member of the EU from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let eu = ipinfo.is_eu("DE");
+    /// ```
+    pub fn is_eu(&self, code: &str) -> bool {
+        self.context.countries().is_eu(code)
+    }
+
+    /// Get the flag of a country from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let flag = ipinfo.get_country_flag("DE");
+    /// ```
+    pub fn get_country_flag(&self, code: &str) -> Option<CountryFlag> {
+        self.context.countries().country_flag(code).cloned()
+    }
+
+    /// Get the currency of a country from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let currency = ipinfo.get_currency("DE");
+    /// ```
+    pub fn get_currency(&self, code: &str) -> Option<CountryCurrency> {
+        self.context.countries().country_currency(code).cloned()
+    }
+
+    /// Get the continent of a country from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// let continent = ipinfo.get_continent("DE");
+    /// ```
+    pub fn get_continent(&self, code: &str) -> Option<Continent> {
+        self.context.countries().continent(code).cloned()
+    }
+
+    /// Read the country data files set by the `*_file_path` options again, so updated data is
+    /// used without restarting.
+    ///
+    /// The files are read synchronously. The country data is shared with clones of this client.
+    /// It is left unchanged if any file can't be loaded, failing with an `AssetLoadError`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{AsyncIpInfo, IpInfoConfig};
+    ///
+    /// let config = IpInfoConfig {
+    ///     countries_file_path: Some("countries.json".to_owned()),
+    ///     ..Default::default()
+    /// };
+    /// let ipinfo = AsyncIpInfo::new(config).expect("should construct");
+    /// ipinfo.reload_assets().expect("should reload");
+    /// ```
+    pub fn reload_assets(&self) -> Result<(), IpError> {
+        self.context.reload_assets()
+    }
+
+    /// Remove the cached details of an IP address, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// ipinfo.cache_remove("8.8.8.8");
+    /// ```
+    pub fn cache_remove(&self, ip: &str) {
+        self.context.cache_remove(ip);
+    }
+
+    /// Remove every cached IP address and ASN.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// ipinfo.cache_clear();
+    /// ```
+    pub fn cache_clear(&self) {
+        self.context.cache_clear();
+    }
+
+    /// Write the cached IPs to storage, such as the snapshot of the
+    /// [`cache_file_path`](IpInfoConfig::cache_file_path) file.
+    ///
+    /// Does nothing for caches that don't persist their entries lazily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::AsyncIpInfo;
+    ///
+    /// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+    /// ipinfo.cache_flush().expect("should flush");
+    /// ```
+    pub fn cache_flush(&self) -> Result<(), IpError> {
+        self.context.cache_flush()
+    }
+
+    /// Wait for the joined lookups, splitting their IPs into shared results and failures.
+    async fn join_flights<'a>(
+        &self,
+        joined: Vec<(&'a str, Arc<Flight>)>,
+    ) -> (Vec<IpDetails>, Vec<&'a str>) {
+        let mut shared = vec![];
+        let mut failed = vec![];
+
+        for (ip, flight) in joined {
+            flight.wait_async().await;
+            match self.context.get_joined(ip) {
+                Some(detail) => shared.push(detail),
+                None => failed.push(ip),
+            }
+        }
+
+        (shared, failed)
+    }
+
+    /// Refresh the stale cache entries served meanwhile in the background.
+    ///
+    /// Outside of a tokio runtime, such as on another executor, the refresh runs on a thread
+    /// with its own runtime instead.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn revalidate(&self) {
+        let stale = self.context.take_stale();
+        if stale.is_empty() {
+            return;
+        }
+
+        if tokio::runtime::Handle::try_current().is_ok() {
+            let ips: Vec<&str> = stale.iter().map(String::as_str).collect();
+            self.spawn_lookup(&ips, true);
+            return;
+        }
+
+        let worker = self.worker();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build();
+            match runtime {
+                Ok(runtime) => {
+                    let _ = runtime.block_on(worker.background_lookup(stale, true));
+                }
+                Err(_e) => {
+                    trace_event!(warn, error = %_e, "stale entries not revalidated");
+                    let ips: Vec<&str> = stale.iter().map(String::as_str).collect();
+                    worker.context.finish_revalidation(&ips);
+                }
+            }
+        });
+    }
+
+    /// Refresh the stale cache entries served meanwhile in the background, as a task of the
+    /// browser's event loop.
+    #[cfg(target_arch = "wasm32")]
+    fn revalidate(&self) {
+        let stale = self.context.take_stale();
+        if !stale.is_empty() {
+            let worker = self.worker();
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = worker.background_lookup(stale, true).await;
+            });
+        }
+    }
+
+    /// Lookup IPs in the background with a client sharing the cache, bypassing cached entries
+    /// when `fresh`.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn spawn_lookup(
+        &self,
+        ips: &[&str],
+        fresh: bool,
+    ) -> tokio::task::JoinHandle<Result<(), IpError>> {
+        let worker = self.worker();
+        let ips: Vec<String> = ips.iter().map(|ip| ip.to_string()).collect();
+
+        tokio::spawn(worker.background_lookup(ips, fresh))
+    }
+
+    /// Construct a client sharing the cache, for background lookups.
+    fn worker(&self) -> AsyncIpInfo {
+        AsyncIpInfo {
+            transport: self.transport.clone(),
+            context: Arc::new(self.context.fork()),
+        }
+    }
+
+    /// Perform a background lookup, bypassing cached entries when `fresh`.
+    async fn background_lookup(self, ips: Vec<String>, fresh: bool) -> Result<(), IpError> {
+        let ips: Vec<&str> = ips.iter().map(String::as_str).collect();
+        if !fresh {
+            return self.lookup(&ips).await.map(|_| ());
+        }
+
+        let result = self.lookup_fresh(&ips).await.map(|_| ());
+        self.context.finish_revalidation(&ips);
+        result
+    }
+
+    /// Send batch requests for the IPs in chunks the batch API accepts, a few chunks at a time.
+    async fn send_batches<'a>(
+        &self,
+        misses: &'a [&'a str],
+    ) -> Vec<(&'a [&'a str], Result<String, IpError>)> {
+        // Refuse to send unauthenticated batch requests if a token is required
+        if let Err(e) = self.context.check_token() {
+            return misses
+                .chunks(BATCH_MAX_SIZE)
+                .map(|chunk| (chunk, Err(e.clone())))
+                .collect();
+        }
+
+        // Collect the requests first, keeping the closure out of the stream so lookups can be
+        // spawned on tokio (rustc fails to prove the closure general over lifetimes)
+        let requests: Vec<_> = misses
+            .chunks(BATCH_MAX_SIZE)
+            .map(|chunk| async move { (chunk, self.send(self.batch_request(chunk)).await) })
+            .collect();
+
+        stream::iter(requests)
+            .buffer_unordered(self.context.max_concurrency)
+            .collect()
+            .await
+    }
+
+    /// Construct a batch API request for a chunk of IPs.
+    fn batch_request(&self, chunk: &[&str]) -> HttpRequest {
+        self.context.post(
+            format!("{}/batch", self.context.url),
+            json!(chunk).to_string(),
+        )
+    }
+
+    /// Send an API request and acquire the raw response body, retrying transient failures.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(method = %request.method, url = %request.url)
+        )
+    )]
+    async fn send(&self, request: HttpRequest) -> Result<String, IpError> {
+        let mut attempts = Attempts::new(request);
+        loop {
+            let request = self.context.start_attempt(&attempts)?;
+            let start = Instant::now();
+            let response = self.transport.send(request.clone()).await;
+
+            match self
+                .context
+                .finish_attempt(&mut attempts, &request, response, start.elapsed())
+            {
+                Next::Done(result) => return result,
+                Next::Retry(Some(wait)) => Delay::new(wait).await,
+                Next::Retry(None) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{details, MockTransport};
+
+    use std::time::Duration;
+
+    fn get_ipinfo_client() -> AsyncIpInfo {
+        dotenv::dotenv().ok();
+        AsyncIpInfo::new(IpInfoConfig {
+            token: Some(std::env::var("IPINFO_TOKEN").unwrap().into()),
+            timeout: Duration::from_secs(3),
+            cache_size: 100,
+            ..Default::default()
+        })
+        .expect("should construct")
+    }
+
+    #[tokio::test]
+    async fn request_single_ip() {
+        let ipinfo = get_ipinfo_client();
+
+        let details = ipinfo
+            .lookup(&["66.87.125.72"])
+            .await
+            .expect("should lookup");
+
+        assert!(details.contains_key("66.87.125.72"));
+        assert_eq!(details.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn lookup_stream_yields_every_result() {
+        let ips: Vec<String> = (0..2500)
+            .map(|i| format!("8.8.{}.{}", i / 256, i % 256))
+            .collect();
+        let transport = ips.iter().fold(MockTransport::new(), |transport, ip| {
+            transport.with_details(details(ip, "US"))
+        });
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(transport)),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let ips: Vec<&str> = ips.iter().map(String::as_str).chain(["foo"]).collect();
+        let results: HashMap<_, _> = ipinfo.lookup_stream(&ips).collect().await;
+
+        assert_eq!(results.len(), 2501);
+        assert_eq!(results["8.8.9.195"].as_ref().unwrap().country, "US");
+        assert!(results["foo"].is_err());
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        requests: std::sync::atomic::AtomicUsize,
+    }
+
+    impl AsyncTransport for Counter {
+        fn send(
+            &self,
+            request: HttpRequest,
+        ) -> futures::future::BoxFuture<'_, Result<crate::HttpResponse, IpError>> {
+            self.requests
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let details = json!({ "ip": "8.8.8.8", "country": "US" });
+            let body = if request.url.ends_with("/batch") {
+                json!({ "8.8.8.8": details })
+            } else {
+                details
+            };
+            Box::pin(async move {
+                Ok(crate::HttpResponse {
+                    status: reqwest::StatusCode::OK,
+                    headers: Default::default(),
+                    body: body.to_string(),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn stale_entries_are_revalidated_outside_tokio() {
+        let transport = Arc::new(Counter::default());
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(transport.clone()),
+            cache_ttl: Some(Duration::ZERO),
+            stale_while_revalidate: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let lookup = || futures::executor::block_on(ipinfo.lookup_one("8.8.8.8"));
+        lookup().expect("should lookup");
+        std::thread::sleep(Duration::from_millis(1));
+
+        // The stale entry is served, and refreshed on a thread of its own
+        assert_eq!(lookup().expect("should be served").country, "US");
+        for _ in 0..100 {
+            if transport.requests.load(std::sync::atomic::Ordering::SeqCst) == 2 {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("stale entry should be revalidated");
+    }
+
+    /// A transport failing every other request with a server error.
+    #[derive(Default)]
+    struct Flaky {
+        requests: std::sync::atomic::AtomicUsize,
+    }
+
+    impl AsyncTransport for Flaky {
+        fn send(
+            &self,
+            _request: HttpRequest,
+        ) -> futures::future::BoxFuture<'_, Result<crate::HttpResponse, IpError>> {
+            let n = self
+                .requests
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let status = if n.is_multiple_of(2) {
+                reqwest::StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                reqwest::StatusCode::OK
+            };
+            Box::pin(async move {
+                Ok(crate::HttpResponse {
+                    status,
+                    headers: Default::default(),
+                    body: r#"{"ip": "8.8.8.8", "country": "US"}"#.to_owned(),
+                })
+            })
+        }
+    }
+
+    #[test]
+    fn retries_and_host_lookups_run_outside_tokio() {
+        let transport = Arc::new(Flaky::default());
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(transport.clone()),
+            retry: crate::RetryPolicy {
+                max_attempts: 2,
+                base_delay: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = futures::executor::block_on(ipinfo.lookup_one("8.8.8.8"))
+            .expect("should lookup after a retry");
+        assert_eq!(details.country, "US");
+        assert_eq!(
+            transport.requests.load(std::sync::atomic::Ordering::SeqCst),
+            2
+        );
+
+        let details =
+            futures::executor::block_on(ipinfo.lookup_host("127.0.0.1")).expect("should resolve");
+        assert!(details.contains_key(&"127.0.0.1".parse::<IpAddr>().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn async_transport_is_used() {
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new().with_details(details("8.8.8.8", "US")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo.lookup_one("8.8.8.8").await.expect("should lookup");
+        assert_eq!(details.country, "US");
+        #[cfg(feature = "bundled-assets")]
+        assert_eq!(details.country_name, Some("United States".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn lookups_run_concurrently_from_shared_client() {
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new().with_details(details("8.8.8.8", "US")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let (a, b) = futures::join!(ipinfo.lookup_one("8.8.8.8"), ipinfo.lookup_one("8.8.8.8"));
+        assert!(a.is_ok() && b.is_ok());
+
+        let stats = ipinfo.cache_stats();
+        assert_eq!(stats.hits + stats.misses, 2);
+    }
+
+    #[tokio::test]
+    async fn prefetch_runs_in_background() {
+        let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+
+        let handle = ipinfo.prefetch(&["10.0.0.1"]);
+        assert!(handle.await.unwrap().is_ok());
+
+        let handle = ipinfo.prefetch(&["8.8.8.256"]);
+        assert!(handle.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn request_single_ip_no_token() {
+        let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+
+        assert_eq!(
+            ipinfo.lookup(&["8.8.8.8"]).await.err().unwrap().kind(),
+            crate::IpErrorKind::IpRequestError
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Micro-batching of single IP lookups into batch requests.
+
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{IpDetails, IpError, IpInfo, IpInfoConfig};
+
+use tokio::sync::oneshot;
+
+/// Batcher configuration.
+///
+/// A batch request is sent once `max_batch_size` IPs have been collected, or `max_wait` after
+/// the first IP of the batch was submitted.
+#[derive(Debug, Clone)]
+pub struct BatcherConfig {
+    /// The maximum number of IPs per batch request. (default: 100 IPs)
+    pub max_batch_size: usize,
+
+    /// The maximum time an IP waits for its batch to fill. (default: 50 milliseconds)
+    pub max_wait: Duration,
+}
+
+impl Default for BatcherConfig {
+    fn default() -> Self {
+        Self {
+            max_batch_size: 100,
+            max_wait: Duration::from_millis(50),
+        }
+    }
+}
+
+type Request = (String, oneshot::Sender<Result<IpDetails, IpError>>);
+
+/// Aggregates single IP lookups from many threads or tasks into batch requests.
+///
+/// The lookups are performed by a background thread owning an [`IpInfo`] client, which stops
+/// once every clone of the batcher is dropped.
+#[derive(Clone)]
+pub struct Batcher {
+    sender: Sender<Request>,
+}
+
+impl Batcher {
+    /// Construct a new batcher, and the client performing its lookups.
+    ///
+    /// The client is constructed on the background thread, so the batcher can be constructed
+    /// from async contexts as well.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::Batcher;
+    ///
+    /// let batcher = Batcher::new(Default::default(), Default::default()).expect("should construct");
+    /// let res = batcher.lookup("8.8.8.8").expect("should run");
+    /// ```
+    pub fn new(ipinfo: IpInfoConfig, config: BatcherConfig) -> Result<Self, IpError> {
+        let (sender, receiver) = mpsc::channel();
+        let (ready, constructed) = mpsc::channel();
+
+        thread::spawn(move || match IpInfo::new(ipinfo) {
+            Ok(ipinfo) => {
+                let _ = ready.send(Ok(()));
+                run(ipinfo, config, receiver);
+            }
+            Err(e) => {
+                let _ = ready.send(Err(e));
+            }
+        });
+
+        constructed.recv().unwrap_or_else(|_| Err(stopped()))?;
+        Ok(Self { sender })
+    }
+
+    /// Lookup a single IP address, blocking until its batch is answered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from an async context, use [`lookup_async`](Batcher::lookup_async)
+    /// there instead.
+    pub fn lookup(&self, ip: &str) -> Result<IpDetails, IpError> {
+        let receiver = self.submit(ip);
+        receiver.blocking_recv().unwrap_or_else(|_| Err(stopped()))
+    }
+
+    /// Lookup a single IP address, waiting until its batch is answered.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::Batcher;
+    ///
+    /// # async fn run() {
+    /// let batcher = Batcher::new(Default::default(), Default::default()).expect("should construct");
+    /// let res = batcher.lookup_async("8.8.8.8").await.expect("should run");
+    /// # }
+    /// ```
+    pub async fn lookup_async(&self, ip: &str) -> Result<IpDetails, IpError> {
+        let receiver = self.submit(ip);
+        receiver.await.unwrap_or_else(|_| Err(stopped()))
+    }
+
+    /// Submit an IP to the next batch.
+    fn submit(&self, ip: &str) -> oneshot::Receiver<Result<IpDetails, IpError>> {
+        let (sender, receiver) = oneshot::channel();
+        // A stopped worker drops the reply sender, which is reported by the receiver
+        let _ = self.sender.send((ip.to_owned(), sender));
+        receiver
+    }
+}
+
+/// Collect the submitted IPs into batches and answer them, until every batcher is dropped.
+fn run(ipinfo: IpInfo, config: BatcherConfig, receiver: Receiver<Request>) {
+    let max_batch_size = config.max_batch_size.max(1);
+
+    while let Ok(first) = receiver.recv() {
+        let deadline = Instant::now() + config.max_wait;
+        let mut batch = vec![first];
+
+        while batch.len() < max_batch_size {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match receiver.recv_timeout(timeout) {
+                Ok(request) => batch.push(request),
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        let ips: Vec<&str> = batch.iter().map(|(ip, _)| ip.as_str()).collect();
+        let results = ipinfo.lookup_each(&ips);
+
+        for (ip, reply) in batch {
+            let result = results
+                .get(&ip)
+                .cloned()
+                .unwrap_or_else(|| Err(err!(IpRequestError, "no result returned for {}", ip)));
+            let _ = reply.send(result);
+        }
+    }
+}
+
+fn stopped() -> IpError {
+    err!(IpRequestError, "batcher stopped")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batcher() -> Batcher {
+        Batcher::new(Default::default(), Default::default()).expect("should construct")
+    }
+
+    #[test]
+    fn batcher_answers_each_lookup() {
+        let batcher = batcher();
+
+        let handles: Vec<_> = ["10.0.0.1", "192.168.1.1", "8.8.8.256"]
+            .into_iter()
+            .map(|ip| {
+                let batcher = batcher.clone();
+                thread::spawn(move || batcher.lookup(ip))
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        assert_eq!(results[0].as_ref().unwrap().ip, "10.0.0.1");
+        assert_eq!(results[1].as_ref().unwrap().ip, "192.168.1.1");
+        assert_eq!(
+            results[2].as_ref().unwrap_err().kind(),
+            crate::IpErrorKind::InvalidIpError
+        );
+    }
+
+    #[tokio::test]
+    async fn batcher_can_be_awaited() {
+        let batcher = batcher();
+
+        let details = batcher
+            .lookup_async("10.0.0.1")
+            .await
+            .expect("should lookup");
+
+        assert_eq!(details.bogon, Some(true));
+    }
+}
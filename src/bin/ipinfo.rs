@@ -0,0 +1,301 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Command line interface to the IPinfo API.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{Parser, Subcommand};
+use ipinfo::{export, summarize, IpError, IpInfo, IpInfoConfig};
+
+/// Command line interface to the IPinfo API.
+#[derive(Debug, Parser)]
+#[command(
+    name = "ipinfo",
+    version,
+    after_help = "The token is read from the IPINFO_TOKEN environment variable unless given."
+)]
+struct Args {
+    /// The IPinfo access token.
+    #[arg(long, global = true)]
+    token: Option<String>,
+
+    /// The configuration file of the client.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, PartialEq, Subcommand)]
+enum Command {
+    /// Look up IP addresses.
+    Lookup {
+        #[arg(required = true, value_name = "IP")]
+        ips: Vec<String>,
+    },
+
+    /// Look up the IP addresses of FILE or stdin, one per line.
+    Batch {
+        /// The file of IP addresses, read from stdin if omitted.
+        file: Option<PathBuf>,
+
+        /// Write the details as CSV rather than JSON.
+        #[arg(long)]
+        csv: bool,
+    },
+
+    /// Look up an ASN.
+    Asn {
+        /// The ASN, such as AS15169.
+        asn: String,
+    },
+
+    /// Summarize the IP addresses of FILE or stdin, one per line.
+    Summarize {
+        /// The file of IP addresses, read from stdin if omitted.
+        file: Option<PathBuf>,
+    },
+}
+
+/// Read the IP addresses of a file or stdin, one per line, skipping blank lines and comments.
+fn read_ips(file: Option<&Path>) -> io::Result<Vec<String>> {
+    let lines: Vec<String> = match file {
+        Some(path) => fs::read_to_string(path)?
+            .lines()
+            .map(str::to_owned)
+            .collect(),
+        None => io::stdin().lock().lines().collect::<io::Result<_>>()?,
+    };
+
+    Ok(lines
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+fn config(args: &Args) -> Result<IpInfoConfig, IpError> {
+    let mut config = match &args.config {
+        Some(path) => IpInfoConfig::from_file(path)?,
+        None => Default::default(),
+    };
+    if let Some(token) = &args.token {
+        config.token = Some(token.as_str().into());
+    }
+
+    // Read IPINFO_TOKEN when neither the file nor the arguments give a token
+    config.token_from_env = true;
+
+    Ok(config)
+}
+
+fn client(args: &Args) -> Result<IpInfo, IpError> {
+    IpInfo::new(config(args)?)
+}
+
+fn lookup(ipinfo: &IpInfo, ips: &[String]) -> Result<HashMap<String, ipinfo::IpDetails>, IpError> {
+    let ips: Vec<&str> = ips.iter().map(String::as_str).collect();
+    ipinfo.lookup(&ips)
+}
+
+fn run(args: Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut out = io::stdout().lock();
+
+    match &args.command {
+        Command::Lookup { ips } => {
+            let details = lookup(&client(&args)?, ips)?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&details)?)?;
+        }
+        Command::Batch { file, csv } => {
+            let details = lookup(&client(&args)?, &read_ips(file.as_deref())?)?;
+            if *csv {
+                export::to_csv(&mut out, &details)?;
+            } else {
+                writeln!(out, "{}", serde_json::to_string_pretty(&details)?)?;
+            }
+        }
+        Command::Asn { asn } => {
+            let details = client(&args)?.lookup_asn(asn)?;
+            writeln!(out, "{}", serde_json::to_string_pretty(&details)?)?;
+        }
+        Command::Summarize { file } => {
+            let details = lookup(&client(&args)?, &read_ips(file.as_deref())?)?;
+            writeln!(
+                out,
+                "{}",
+                serde_json::to_string_pretty(&summarize(&details))?
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(["ipinfo"].iter().chain(args))
+    }
+
+    #[test]
+    fn cli_is_consistent() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        let args = parse(&["--token", "secret", "lookup", "8.8.8.8", "1.1.1.1"]).unwrap();
+        assert_eq!(args.token.as_deref(), Some("secret"));
+        assert_eq!(
+            args.command,
+            Command::Lookup {
+                ips: vec!["8.8.8.8".to_owned(), "1.1.1.1".to_owned()]
+            }
+        );
+
+        let args = parse(&["batch", "ips.txt", "--csv", "--config", "ipinfo.toml"]).unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("ipinfo.toml")));
+        assert_eq!(
+            args.command,
+            Command::Batch {
+                file: Some(PathBuf::from("ips.txt")),
+                csv: true
+            }
+        );
+        assert_eq!(
+            parse(&["asn", "AS15169"]).unwrap().command,
+            Command::Asn {
+                asn: "AS15169".to_owned()
+            }
+        );
+        assert_eq!(
+            parse(&["summarize"]).unwrap().command,
+            Command::Summarize { file: None }
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["lookup"]).is_err());
+        assert!(parse(&["asn"]).is_err());
+        assert!(parse(&["asn", "AS1", "AS2"]).is_err());
+        assert!(parse(&["--token"]).is_err());
+        assert!(parse(&["--verbose", "lookup", "8.8.8.8"]).is_err());
+        assert!(parse(&["whois"]).is_err());
+    }
+
+    /// Transport answering every request with a 500 response, recording the `Authorization`
+    /// header.
+    #[derive(Default)]
+    struct Recorder {
+        authorization: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl ipinfo::Transport for Recorder {
+        fn send(&self, request: ipinfo::HttpRequest) -> Result<ipinfo::HttpResponse, IpError> {
+            let authorization = &request.headers["authorization"];
+            self.authorization
+                .lock()
+                .unwrap()
+                .push(authorization.to_str().unwrap().to_owned());
+            Ok(ipinfo::HttpResponse {
+                status: reqwest::StatusCode::INTERNAL_SERVER_ERROR,
+                headers: Default::default(),
+                body: String::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn token_falls_back_to_env() {
+        let path = std::env::temp_dir().join(format!("ipinfo-cli-{}.toml", std::process::id()));
+        fs::write(&path, "timeout = 5\n").unwrap();
+        std::env::set_var("IPINFO_TOKEN", "env token");
+
+        let authorization = |args: &[&str]| {
+            let transport = Arc::new(Recorder::default());
+            let ipinfo = IpInfo::new(IpInfoConfig {
+                transport: Some(transport.clone()),
+                ..config(&parse(args).unwrap()).unwrap()
+            })
+            .unwrap();
+            let _ = ipinfo.lookup_one("8.8.8.8");
+            let authorization = transport.authorization.lock().unwrap()[0].clone();
+            authorization
+        };
+        let config_path = path.to_str().unwrap();
+
+        assert_eq!(
+            authorization(&["--config", config_path, "lookup", "8.8.8.8"]),
+            "Bearer env token"
+        );
+        assert_eq!(authorization(&["lookup", "8.8.8.8"]), "Bearer env token");
+        assert_eq!(
+            authorization(&[
+                "--config",
+                config_path,
+                "--token",
+                "arg",
+                "lookup",
+                "8.8.8.8"
+            ]),
+            "Bearer arg"
+        );
+
+        fs::write(&path, "token = \"file token\"\n").unwrap();
+        assert_eq!(
+            authorization(&["--config", config_path, "lookup", "8.8.8.8"]),
+            "Bearer file token"
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ips_are_read_one_per_line() {
+        let path = std::env::temp_dir().join(format!("ipinfo-cli-{}.txt", std::process::id()));
+        fs::write(&path, "# resolvers\n8.8.8.8\n\n  1.1.1.1  \n").unwrap();
+
+        let ips = read_ips(Some(&path)).unwrap();
+        assert_eq!(ips, ["8.8.8.8", "1.1.1.1"]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
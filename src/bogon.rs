@@ -0,0 +1,187 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Bogon (private, reserved and otherwise unroutable) IP address detection.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::IpDetails;
+
+/// Reserved IPv4 networks as (network, prefix length).
+const BOGON_V4: &[(Ipv4Addr, u32)] = &[
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 0, 0, 0), 24),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// Reserved IPv6 networks as (network, prefix length).
+const BOGON_V6: &[(Ipv6Addr, u32)] = &[
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0), 128),
+    (Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1), 128),
+    (Ipv6Addr::new(0x100, 0, 0, 0, 0, 0, 0, 0), 64),
+    (Ipv6Addr::new(0x2001, 0x10, 0, 0, 0, 0, 0, 0), 28),
+    (Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0), 32),
+    (Ipv6Addr::new(0x3fff, 0, 0, 0, 0, 0, 0, 0), 20),
+    (Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0), 7),
+    (Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), 10),
+    (Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 0), 10),
+    (Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0), 8),
+];
+
+/// Check whether an IP address is a bogon.
+///
+/// IPv4-mapped and IPv4-compatible IPv6 addresses are checked against the IPv4 ranges.
+///
+/// # Examples
+///
+/// ```
+/// use ipinfo::is_bogon;
+///
+/// assert!(is_bogon(&"192.168.1.1".parse().unwrap()));
+/// assert!(!is_bogon(&"8.8.8.8".parse().unwrap()));
+/// ```
+pub fn is_bogon(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_bogon_v4(ip),
+        IpAddr::V6(ip) => is_bogon_v6(ip),
+    }
+}
+
+fn is_bogon_v4(ip: &Ipv4Addr) -> bool {
+    let ip = u32::from(*ip);
+    BOGON_V4.iter().any(|(network, prefix)| {
+        let mask = u32::MAX << (32 - prefix);
+        ip & mask == u32::from(*network)
+    })
+}
+
+fn is_bogon_v6(ip: &Ipv6Addr) -> bool {
+    // Check embedded IPv4 addresses (::ffff:0:0/96 and ::/96)
+    let segments = ip.segments();
+    if segments[..5].iter().all(|s| *s == 0) && (segments[5] == 0xffff || segments[5] == 0) {
+        let v4 = Ipv4Addr::from((u32::from(segments[6]) << 16) | u32::from(segments[7]));
+        if is_bogon_v4(&v4) {
+            return true;
+        }
+    }
+
+    let ip = u128::from(*ip);
+    BOGON_V6.iter().any(|(network, prefix)| {
+        let mask = u128::MAX << (128 - prefix);
+        ip & mask == u128::from(*network)
+    })
+}
+
+/// Synthesize the details of an IP address if it is a bogon.
+pub(crate) fn bogon_details(ip: &str) -> Option<IpDetails> {
+    let addr: IpAddr = ip.parse().ok()?;
+    if !is_bogon(&addr) {
+        return None;
+    }
+
+    Some(IpDetails {
+        ip: ip.to_owned(),
+        hostname: None,
+        city: String::new(),
+        region: String::new(),
+        country: String::new(),
+        country_name: None,
+        is_eu: None,
+        country_flag: None,
+        country_flag_url: None,
+        country_currency: None,
+        continent: None,
+        loc: String::new(),
+        org: None,
+        postal: None,
+        timezone: None,
+        asn: None,
+        company: None,
+        carrier: None,
+        privacy: None,
+        abuse: None,
+        domains: None,
+        bogon: Some(true),
+        anycast: None,
+        stale: false,
+        extra: HashMap::new(),
+        raw: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bogon(ip: &str) -> bool {
+        is_bogon(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn private_v4_ranges_are_bogons() {
+        assert!(bogon("10.1.2.3"));
+        assert!(bogon("172.16.0.1"));
+        assert!(bogon("172.31.255.255"));
+        assert!(bogon("192.168.0.1"));
+        assert!(bogon("127.0.0.1"));
+        assert!(bogon("169.254.10.10"));
+        assert!(bogon("100.64.0.1"));
+        assert!(bogon("255.255.255.255"));
+    }
+
+    #[test]
+    fn public_v4_addresses_are_not_bogons() {
+        assert!(!bogon("8.8.8.8"));
+        assert!(!bogon("172.32.0.1"));
+        assert!(!bogon("100.128.0.1"));
+        assert!(!bogon("1.1.1.1"));
+    }
+
+    #[test]
+    fn reserved_v6_ranges_are_bogons() {
+        assert!(bogon("::1"));
+        assert!(bogon("::"));
+        assert!(bogon("fe80::1"));
+        assert!(bogon("fd00::1"));
+        assert!(bogon("2001:db8::1"));
+        assert!(bogon("ff02::1"));
+        assert!(bogon("::ffff:192.168.1.1"));
+        assert!(!bogon("2001:4860:4860::8888"));
+        assert!(!bogon("::ffff:8.8.8.8"));
+    }
+
+    #[test]
+    fn bogon_details_are_synthesized() {
+        let details = bogon_details("10.0.0.1").expect("should be a bogon");
+
+        assert_eq!(details.ip, "10.0.0.1");
+        assert_eq!(details.bogon, Some(true));
+        assert!(bogon_details("8.8.8.8").is_none());
+        assert!(bogon_details("not an ip").is_none());
+    }
+}
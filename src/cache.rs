@@ -0,0 +1,227 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Pluggable cache of IP address details.
+
+mod file;
+#[cfg(feature = "moka")]
+mod moka;
+#[cfg(feature = "redis")]
+mod redis;
+
+pub use self::file::FileCache;
+#[cfg(feature = "moka")]
+pub use self::moka::MokaCache;
+#[cfg(feature = "redis")]
+pub use self::redis::RedisCache;
+
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::{clock, IpDetails, IpError};
+
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+
+/// A cached value and when it was fetched from the API.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CacheEntry<T = IpDetails> {
+    /// The cached value.
+    pub value: T,
+
+    /// When the value was fetched from the API.
+    pub fetched_at: SystemTime,
+}
+
+impl<T> CacheEntry<T> {
+    /// Construct a new cache entry for a value fetched now.
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            fetched_at: clock::now(),
+        }
+    }
+
+    /// Get how long ago the value was fetched.
+    pub fn age(&self) -> Duration {
+        clock::now()
+            .duration_since(self.fetched_at)
+            .unwrap_or_default()
+    }
+}
+
+/// A cache of IP address details, keyed by IP.
+///
+/// Expiry is handled by the client using [`CacheEntry::fetched_at`], so implementations only
+/// need to store entries. Cache failures should be treated as misses rather than reported.
+pub trait Cache: Send + Sync {
+    /// Get the entry for a key, if any.
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    /// Insert or replace the entry for a key.
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    /// Remove the entry for a key, if any.
+    fn remove(&self, key: &str);
+
+    /// Remove every entry.
+    fn clear(&self);
+
+    /// Get the number of entries, if the backend tracks it.
+    fn size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Get the number of entries evicted to make room, if the backend tracks it.
+    fn evictions(&self) -> Option<u64> {
+        None
+    }
+
+    /// Write the pending entries to storage, if the backend persists them lazily.
+    fn flush(&self) -> Result<(), IpError> {
+        Ok(())
+    }
+}
+
+/// Cache usage statistics of a client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of lookups answered from the cache.
+    pub hits: u64,
+
+    /// The number of lookups that missed the cache or found an expired entry.
+    pub misses: u64,
+
+    /// The number of entries evicted by the cache, if tracked by its backend.
+    pub evictions: Option<u64>,
+
+    /// The current number of entries, if tracked by the cache backend.
+    pub size: Option<usize>,
+}
+
+/// In-memory LRU cache, used by default.
+pub struct MemoryCache {
+    cache: Mutex<LruCache<String, CacheEntry>>,
+    evictions: AtomicU64,
+}
+
+impl MemoryCache {
+    /// Construct a new in-memory cache holding up to `size` entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::num::NonZeroUsize;
+    ///
+    /// use ipinfo::MemoryCache;
+    ///
+    /// let cache = MemoryCache::new(NonZeroUsize::new(100).unwrap());
+    /// ```
+    pub fn new(size: NonZeroUsize) -> Self {
+        Self {
+            cache: Mutex::new(LruCache::new(size)),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Serialize the entries from least to most recently used, so that putting them back in
+    /// order restores their recency.
+    pub(crate) fn snapshot(&self) -> serde_json::Result<String> {
+        let cache = self.cache.lock().unwrap();
+        let entries: Vec<(&String, &CacheEntry)> = cache.iter().rev().collect();
+        serde_json::to_string(&entries)
+    }
+}
+
+impl Cache for MemoryCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.cache.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let evicted = self.cache.lock().unwrap().push(key.to_owned(), entry);
+        if evicted.is_some_and(|(evicted, _)| evicted != key) {
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.cache.lock().unwrap().pop(key);
+    }
+
+    fn clear(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+
+    fn size(&self) -> Option<usize> {
+        Some(self.cache.lock().unwrap().len())
+    }
+
+    fn evictions(&self) -> Option<u64> {
+        Some(self.evictions.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ip: &str) -> CacheEntry {
+        CacheEntry::new(
+            serde_json::from_value(serde_json::json!({ "ip": ip })).expect("should parse"),
+        )
+    }
+
+    #[test]
+    fn memory_cache_put_get_remove() {
+        let cache = MemoryCache::new(NonZeroUsize::new(10).unwrap());
+
+        cache.put("8.8.8.8", entry("8.8.8.8"));
+        assert_eq!(cache.get("8.8.8.8").unwrap().value.ip, "8.8.8.8");
+
+        cache.remove("8.8.8.8");
+        assert!(cache.get("8.8.8.8").is_none());
+    }
+
+    #[test]
+    fn memory_cache_evicts_least_recently_used() {
+        let cache = MemoryCache::new(NonZeroUsize::new(2).unwrap());
+
+        cache.put("1.1.1.1", entry("1.1.1.1"));
+        cache.put("8.8.8.8", entry("8.8.8.8"));
+        cache.get("1.1.1.1");
+        cache.put("4.2.2.4", entry("4.2.2.4"));
+
+        assert!(cache.get("1.1.1.1").is_some());
+        assert!(cache.get("8.8.8.8").is_none());
+        assert_eq!(cache.size(), Some(2));
+        assert_eq!(cache.evictions(), Some(1));
+    }
+
+    #[test]
+    fn memory_cache_clear() {
+        let cache = MemoryCache::new(NonZeroUsize::new(10).unwrap());
+        cache.put("8.8.8.8", entry("8.8.8.8"));
+
+        cache.clear();
+
+        assert!(cache.get("8.8.8.8").is_none());
+    }
+}
@@ -0,0 +1,194 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! File cache backend, persisted across process restarts.
+
+use std::{
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{Cache, CacheEntry, IpError, MemoryCache};
+
+use web_time::Instant;
+
+/// How often insertions write the snapshot.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// In-memory LRU cache, snapshotted to a JSON file and reloaded when opened.
+///
+/// The snapshot is written by [`FileCache::flush`], when the cache is dropped, and on insertion
+/// at most once a minute, so that a killed process loses at most the last minute of entries.
+pub struct FileCache {
+    cache: MemoryCache,
+    path: PathBuf,
+    last_snapshot: Mutex<Instant>,
+}
+
+impl FileCache {
+    /// Open a cache holding up to `size` entries, reloading the snapshot at `path` if any.
+    ///
+    /// A missing or unreadable snapshot starts an empty cache.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::num::NonZeroUsize;
+    ///
+    /// use ipinfo::FileCache;
+    ///
+    /// let cache = FileCache::open("ipinfo-cache.json", NonZeroUsize::new(1000).unwrap());
+    /// ```
+    pub fn open(path: impl AsRef<Path>, size: NonZeroUsize) -> Self {
+        let path = path.as_ref().to_owned();
+        let cache = MemoryCache::new(size);
+
+        // Entries are stored from least to most recently used
+        let entries: Vec<(String, CacheEntry)> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default();
+        let skipped = entries.len().saturating_sub(size.get());
+        entries.into_iter().skip(skipped).for_each(|(key, entry)| {
+            cache.put(&key, entry);
+        });
+
+        Self {
+            cache,
+            path,
+            last_snapshot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Write the snapshot of the cache to its file.
+    pub fn flush(&self) -> Result<(), IpError> {
+        let mut last_snapshot = self.last_snapshot.lock().unwrap();
+        self.write_snapshot()?;
+        *last_snapshot = Instant::now();
+
+        Ok(())
+    }
+
+    /// Write the snapshot to a temporary file first, so an interrupted write keeps the previous
+    /// snapshot.
+    fn write_snapshot(&self) -> Result<(), IpError> {
+        let raw = self.cache.snapshot()?;
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, raw)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| err!(CacheError, &e.to_string()))
+    }
+}
+
+impl Cache for FileCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.cache.get(key)
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        self.cache.put(key, entry);
+
+        // Skip the snapshot while another thread is writing it
+        let Ok(mut last_snapshot) = self.last_snapshot.try_lock() else {
+            return;
+        };
+        if last_snapshot.elapsed() >= SNAPSHOT_INTERVAL {
+            if let Err(_e) = self.write_snapshot() {
+                trace_event!(warn, error = %_e, "failed to write the cache snapshot");
+            }
+            *last_snapshot = Instant::now();
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.cache.remove(key);
+    }
+
+    fn clear(&self) {
+        self.cache.clear();
+    }
+
+    fn size(&self) -> Option<usize> {
+        self.cache.size()
+    }
+
+    fn evictions(&self) -> Option<u64> {
+        self.cache.evictions()
+    }
+
+    fn flush(&self) -> Result<(), IpError> {
+        FileCache::flush(self)
+    }
+}
+
+impl Drop for FileCache {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ip: &str) -> CacheEntry {
+        CacheEntry::new(
+            serde_json::from_value(serde_json::json!({ "ip": ip, "anycast": true }))
+                .expect("should parse"),
+        )
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ipinfo-{}-{}.json", name, std::process::id()))
+    }
+
+    #[test]
+    fn file_cache_reloads_snapshot() {
+        let path = path("reload");
+        let size = NonZeroUsize::new(2).unwrap();
+
+        let cache = FileCache::open(&path, size);
+        cache.put("1.1.1.1", entry("1.1.1.1"));
+        cache.put("8.8.8.8", entry("8.8.8.8"));
+        cache.get("1.1.1.1");
+        drop(cache);
+
+        let cache = FileCache::open(&path, size);
+        let reloaded = cache.get("8.8.8.8").unwrap().value;
+        assert_eq!(reloaded.ip, "8.8.8.8");
+        assert_eq!(reloaded.anycast, Some(true));
+
+        // Recency survives the reload
+        cache.put("4.2.2.4", entry("4.2.2.4"));
+        assert!(cache.get("1.1.1.1").is_none());
+        assert!(cache.get("8.8.8.8").is_some());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_cache_ignores_unreadable_snapshot() {
+        let path = path("corrupt");
+        fs::write(&path, "not json").unwrap();
+
+        let cache = FileCache::open(&path, NonZeroUsize::new(2).unwrap());
+        assert!(cache.get("8.8.8.8").is_none());
+        drop(cache);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
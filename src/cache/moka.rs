@@ -0,0 +1,132 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! moka cache backend, for high-throughput multi-threaded services.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{Cache, CacheEntry};
+
+/// Concurrent cache backed by moka, with optional TTL and TTI.
+///
+/// Unlike the default [`MemoryCache`](crate::MemoryCache), reads and writes don't contend
+/// on a single lock.
+pub struct MokaCache {
+    cache: ::moka::sync::Cache<String, CacheEntry>,
+    evictions: Arc<AtomicU64>,
+}
+
+impl MokaCache {
+    /// Construct a new moka cache holding up to `max_capacity` entries.
+    ///
+    /// Entries are evicted `ttl` after being inserted and `tti` after being last read, if given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use ipinfo::MokaCache;
+    ///
+    /// let cache = MokaCache::new(10_000, Some(Duration::from_secs(86400)), None);
+    /// ```
+    pub fn new(max_capacity: u64, ttl: Option<Duration>, tti: Option<Duration>) -> Self {
+        let evictions = Arc::new(AtomicU64::new(0));
+        let counter = evictions.clone();
+        let mut builder = ::moka::sync::Cache::builder()
+            .max_capacity(max_capacity)
+            .eviction_listener(move |_, _, cause| {
+                if cause.was_evicted() {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        if let Some(ttl) = ttl {
+            builder = builder.time_to_live(ttl);
+        }
+        if let Some(tti) = tti {
+            builder = builder.time_to_idle(tti);
+        }
+
+        Self {
+            cache: builder.build(),
+            evictions,
+        }
+    }
+}
+
+impl Cache for MokaCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.cache.get(key)
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        self.cache.insert(key.to_owned(), entry);
+    }
+
+    fn remove(&self, key: &str) {
+        self.cache.invalidate(key);
+    }
+
+    fn clear(&self) {
+        self.cache.invalidate_all();
+    }
+
+    fn size(&self) -> Option<usize> {
+        // Run pending maintenance so the count is up to date
+        self.cache.run_pending_tasks();
+        Some(self.cache.entry_count() as usize)
+    }
+
+    fn evictions(&self) -> Option<u64> {
+        Some(self.evictions.load(Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ip: &str) -> CacheEntry {
+        CacheEntry::new(
+            serde_json::from_value(serde_json::json!({ "ip": ip })).expect("should parse"),
+        )
+    }
+
+    #[test]
+    fn moka_cache_put_get_remove() {
+        let cache = MokaCache::new(10, None, None);
+
+        cache.put("8.8.8.8", entry("8.8.8.8"));
+        assert_eq!(cache.get("8.8.8.8").unwrap().value.ip, "8.8.8.8");
+
+        cache.remove("8.8.8.8");
+        assert!(cache.get("8.8.8.8").is_none());
+    }
+
+    #[test]
+    fn moka_cache_expires_entries() {
+        let cache = MokaCache::new(10, Some(Duration::from_millis(10)), None);
+
+        cache.put("8.8.8.8", entry("8.8.8.8"));
+        std::thread::sleep(Duration::from_millis(50));
+
+        assert!(cache.get("8.8.8.8").is_none());
+    }
+}
@@ -0,0 +1,238 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Redis cache backend, shareable between service instances.
+
+use std::{sync::Mutex, time::Duration};
+
+use crate::{Cache, CacheEntry, IpError};
+
+use ::redis::{Client, Cmd, Connection, FromRedisValue, RedisError};
+
+/// The maximum number of idle connections kept for reuse.
+const MAX_IDLE: usize = 16;
+
+/// Redis cache, storing entries as JSON under a key prefix.
+///
+/// Redis errors are treated as cache misses, so an unavailable Redis server only costs
+/// API requests. Connections are pooled, so concurrent lookups don't wait for each other, and
+/// are replaced after connection errors, so the cache recovers once Redis is back.
+pub struct RedisCache {
+    client: Client,
+    idle: Mutex<Vec<Connection>>,
+    prefix: String,
+    expiry: Option<Duration>,
+    timeout: Duration,
+}
+
+impl RedisCache {
+    /// Connect to a Redis server.
+    ///
+    /// Entries are stored under `prefix`, which must not be empty so that
+    /// [`clear`](Cache::clear) only deletes the keys of the cache, and, if `expiry` is given,
+    /// expire in Redis after that long. `timeout` bounds connecting and every command, so a
+    /// stalled server turns into cache misses rather than blocked lookups.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ipinfo::RedisCache;
+    ///
+    /// let cache = RedisCache::new(
+    ///     "redis://127.0.0.1/",
+    ///     "ipinfo:",
+    ///     Some(Duration::from_secs(86400)),
+    ///     Duration::from_millis(500),
+    /// )
+    /// .expect("should connect");
+    /// ```
+    pub fn new(
+        url: &str,
+        prefix: &str,
+        expiry: Option<Duration>,
+        timeout: Duration,
+    ) -> Result<Self, IpError> {
+        if prefix.is_empty() {
+            return Err(err!(
+                InvalidConfigError,
+                "the Redis key prefix must not be empty"
+            ));
+        }
+
+        let client = Client::open(url).map_err(|e| err!(CacheError, &e.to_string()))?;
+        let cache = Self {
+            client,
+            idle: Mutex::new(vec![]),
+            prefix: prefix.to_owned(),
+            expiry,
+            timeout,
+        };
+
+        // Fail early if the server is unreachable
+        let connection = cache
+            .connect()
+            .map_err(|e| err!(CacheError, &e.to_string()))?;
+        cache.idle.lock().unwrap().push(connection);
+        Ok(cache)
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Open a new connection, with the timeout applied to every command.
+    fn connect(&self) -> Result<Connection, RedisError> {
+        let connection = self.client.get_connection_with_timeout(self.timeout)?;
+        connection.set_read_timeout(Some(self.timeout))?;
+        connection.set_write_timeout(Some(self.timeout))?;
+        Ok(connection)
+    }
+
+    /// Run a command on an idle connection, or a new one if none is idle.
+    ///
+    /// Connections failing with a connection error are dropped rather than reused, and a
+    /// command failing on an idle connection, which may have been closed by a restart of the
+    /// server meanwhile, is retried once on a new connection.
+    fn query<T: FromRedisValue>(&self, cmd: &Cmd) -> Option<T> {
+        let idle = self.idle.lock().unwrap().pop();
+        let reused = idle.is_some();
+        let mut connection = match idle {
+            Some(connection) => connection,
+            None => self.connect().ok()?,
+        };
+
+        match cmd.query(&mut connection) {
+            Ok(value) => {
+                self.release(connection);
+                Some(value)
+            }
+            Err(e) if is_connection_error(&e) => {
+                drop(connection);
+                if !reused {
+                    return None;
+                }
+                let mut connection = self.connect().ok()?;
+                let value = cmd.query(&mut connection);
+                if !value.as_ref().is_err_and(is_connection_error) {
+                    self.release(connection);
+                }
+                value.ok()
+            }
+            Err(_) => {
+                self.release(connection);
+                None
+            }
+        }
+    }
+
+    /// Return a healthy connection to the idle ones.
+    fn release(&self, connection: Connection) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < MAX_IDLE {
+            idle.push(connection);
+        }
+    }
+}
+
+/// Check whether an error leaves its connection unusable.
+fn is_connection_error(error: &RedisError) -> bool {
+    error.is_io_error()
+        || error.is_timeout()
+        || error.is_connection_dropped()
+        || error.is_unrecoverable_error()
+}
+
+impl Cache for RedisCache {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let raw: Option<String> = self.query(::redis::cmd("GET").arg(self.key(key)))?;
+
+        serde_json::from_str(&raw?).ok()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let Ok(raw) = serde_json::to_string(&entry) else {
+            return;
+        };
+
+        let mut cmd = ::redis::cmd("SET");
+        cmd.arg(self.key(key)).arg(raw);
+        if let Some(expiry) = self.expiry {
+            cmd.arg("EX").arg(expiry.as_secs().max(1));
+        }
+
+        let _: Option<()> = self.query(&cmd);
+    }
+
+    fn remove(&self, key: &str) {
+        let _: Option<()> = self.query(::redis::cmd("DEL").arg(self.key(key)));
+    }
+
+    fn clear(&self) {
+        let pattern = format!("{}*", self.prefix);
+
+        // Delete the prefixed keys batch by batch, without blocking the server with KEYS
+        let mut cursor: u64 = 0;
+        loop {
+            let Some((next, keys)): Option<(u64, Vec<String>)> =
+                self.query(::redis::cmd("SCAN").arg(cursor).arg("MATCH").arg(&pattern))
+            else {
+                return;
+            };
+
+            if !keys.is_empty() {
+                let _: Option<()> = self.query(::redis::cmd("DEL").arg(keys));
+            }
+
+            if next == 0 {
+                return;
+            }
+            cursor = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_prefix_is_rejected() {
+        let error = RedisCache::new("redis://127.0.0.1/", "", None, Duration::from_millis(100))
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind(), crate::IpErrorKind::InvalidConfigError);
+    }
+
+    #[test]
+    fn unreachable_server_fails_within_timeout() {
+        // A local port nothing listens on once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let started = std::time::Instant::now();
+        let url = format!("redis://127.0.0.1:{port}/");
+        let error = RedisCache::new(&url, "ipinfo:", None, Duration::from_millis(100))
+            .err()
+            .unwrap();
+
+        assert_eq!(error.kind(), crate::IpErrorKind::CacheError);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
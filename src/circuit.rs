@@ -0,0 +1,209 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Circuit breaker failing fast while the API is unavailable.
+
+use std::{sync::Mutex, time::Duration};
+
+use crate::IpError;
+
+use web_time::Instant;
+
+/// Circuit breaker configuration.
+///
+/// After `failure_threshold` consecutive network errors or 5xx responses, requests fail
+/// immediately with a `CircuitOpenError` until `cooldown` has passed. The circuit is then
+/// half-open: a single request is let through as a probe while the others keep failing fast,
+/// and it closes the circuit if it succeeds, or opens it for another `cooldown` if it fails. A
+/// probe without either outcome, such as a 4xx response, is replaced by another one after
+/// `cooldown`.
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failures opening the circuit. (default: 5 failures)
+    pub failure_threshold: u32,
+
+    /// How long the circuit stays open before letting a request through. (default: 30 seconds)
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Circuit breaker state shared by every request of a client.
+pub(crate) struct CircuitBreaker {
+    config: Option<CircuitBreakerConfig>,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    failures: u32,
+    open_until: Option<Instant>,
+
+    /// When the probe request of the half-open circuit was let through, if any.
+    probe_started: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// Construct a new circuit breaker, disabled if no configuration is given.
+    pub(crate) fn new(config: Option<CircuitBreakerConfig>) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Return an error if the circuit is open, or half-open with a probe request in flight.
+    pub(crate) fn check(&self) -> Result<(), IpError> {
+        let Some(config) = &self.config else {
+            return Ok(());
+        };
+        let mut state = self.state.lock().unwrap();
+        let Some(open_until) = state.open_until else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        if now < open_until {
+            return Err(err!(
+                CircuitOpenError,
+                "retry in {}s",
+                (open_until - now).as_secs()
+            ));
+        }
+
+        // Let a single probe through, unless the previous one is still in flight
+        match state.probe_started {
+            Some(started) if now.duration_since(started) < config.cooldown => {
+                Err(err!(CircuitOpenError, "probe request in flight"))
+            }
+            _ => {
+                state.probe_started = Some(now);
+                Ok(())
+            }
+        }
+    }
+
+    /// Record a successful request, closing the circuit.
+    pub(crate) fn record_success(&self) {
+        if self.config.is_some() {
+            *self.state.lock().unwrap() = State::default();
+        }
+    }
+
+    /// Record a request failing because the API is unavailable.
+    pub(crate) fn record_failure(&self) {
+        if let Some(config) = &self.config {
+            let mut state = self.state.lock().unwrap();
+            state.failures = state.failures.saturating_add(1);
+            if state.failures >= config.failure_threshold {
+                state.open_until = Some(Instant::now() + config.cooldown);
+                state.probe_started = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker::new(Some(CircuitBreakerConfig {
+            failure_threshold: 2,
+            cooldown,
+        }))
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = breaker(Duration::from_secs(30));
+
+        breaker.record_failure();
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure();
+        assert_eq!(
+            breaker.check().err().unwrap().kind(),
+            crate::IpErrorKind::CircuitOpenError
+        );
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let breaker = breaker(Duration::from_secs(30));
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn lets_requests_through_after_cooldown() {
+        let breaker = breaker(Duration::ZERO);
+
+        breaker.record_failure();
+        breaker.record_failure();
+
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn half_open_circuit_lets_a_single_probe_through() {
+        let breaker = breaker(Duration::from_millis(50));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(60));
+
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_err());
+
+        breaker.record_success();
+        assert!(breaker.check().is_ok());
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn failed_probe_reopens_circuit() {
+        let breaker = breaker(Duration::from_millis(50));
+
+        breaker.record_failure();
+        breaker.record_failure();
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.check().is_ok());
+
+        breaker.record_failure();
+        assert!(breaker.check().is_err());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn disabled_breaker_never_opens() {
+        let breaker = CircuitBreaker::new(None);
+
+        (0..100).for_each(|_| breaker.record_failure());
+
+        assert!(breaker.check().is_ok());
+    }
+}
@@ -0,0 +1,259 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! HTTP client settings shared by the blocking and async clients.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+use crate::{IpError, IpInfoConfig};
+
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::Proxy;
+
+/// A TLS protocol version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    /// TLS 1.0
+    Tls1_0,
+
+    /// TLS 1.1
+    Tls1_1,
+
+    /// TLS 1.2
+    Tls1_2,
+
+    /// TLS 1.3
+    Tls1_3,
+}
+
+/// The settings shared by the builders of the blocking and async reqwest clients.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) trait ClientBuilder: Sized {
+    fn timeout(self, timeout: Duration) -> Self;
+
+    fn proxy(self, proxy: Proxy) -> Self;
+
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    fn add_root_certificate(self, certificate: reqwest::Certificate) -> Self;
+
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    fn min_tls_version(self, version: reqwest::tls::Version) -> Self;
+
+    #[cfg(any(feature = "rustls", feature = "native-tls"))]
+    fn danger_accept_invalid_certs(self, accept: bool) -> Self;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! impl_client_builder {
+    ($builder:ty) => {
+        impl ClientBuilder for $builder {
+            fn timeout(self, timeout: Duration) -> Self {
+                <$builder>::timeout(self, timeout)
+            }
+
+            fn proxy(self, proxy: Proxy) -> Self {
+                <$builder>::proxy(self, proxy)
+            }
+
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            fn add_root_certificate(self, certificate: reqwest::Certificate) -> Self {
+                <$builder>::add_root_certificate(self, certificate)
+            }
+
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            fn min_tls_version(self, version: reqwest::tls::Version) -> Self {
+                <$builder>::min_tls_version(self, version)
+            }
+
+            #[cfg(any(feature = "rustls", feature = "native-tls"))]
+            fn danger_accept_invalid_certs(self, accept: bool) -> Self {
+                <$builder>::danger_accept_invalid_certs(self, accept)
+            }
+        }
+    };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl_client_builder!(reqwest::ClientBuilder);
+#[cfg(not(target_arch = "wasm32"))]
+impl_client_builder!(reqwest::blocking::ClientBuilder);
+
+/// Apply the timeout, proxy and TLS settings of the configuration to a client builder.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn configure<B: ClientBuilder>(builder: B, config: &IpInfoConfig) -> Result<B, IpError> {
+    let mut builder = builder.timeout(config.timeout);
+    if let Some(proxy) = proxy(config)? {
+        builder = builder.proxy(proxy);
+    }
+    tls(builder, config)
+}
+
+/// Build the proxy every request is sent through, if one is configured.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn proxy(config: &IpInfoConfig) -> Result<Option<Proxy>, IpError> {
+    let Some(url) = &config.proxy_url else {
+        return Ok(None);
+    };
+    if cfg!(not(feature = "socks")) && url.starts_with("socks") {
+        return Err(err!(
+            InvalidConfigError,
+            "SOCKS proxies require the `socks` feature"
+        ));
+    }
+
+    let proxy =
+        Proxy::all(url).map_err(|e| err!(InvalidConfigError, "invalid proxy_url: {}", e))?;
+    Ok(Some(match &config.proxy_credentials {
+        Some((username, password)) => proxy.basic_auth(username, password),
+        None => proxy,
+    }))
+}
+
+#[cfg(all(
+    any(feature = "rustls", feature = "native-tls"),
+    not(target_arch = "wasm32")
+))]
+fn tls<B: ClientBuilder>(mut builder: B, config: &IpInfoConfig) -> Result<B, IpError> {
+    use reqwest::{tls::Version, Certificate};
+
+    for pem in &config.root_certificates {
+        let certificates = Certificate::from_pem_bundle(pem)
+            .map_err(|e| err!(InvalidConfigError, "invalid root certificate: {}", e))?;
+        if certificates.is_empty() {
+            return Err(err!(
+                InvalidConfigError,
+                "invalid root certificate: no PEM certificate found"
+            ));
+        }
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(version) = config.min_tls_version {
+        builder = builder.min_tls_version(match version {
+            TlsVersion::Tls1_0 => Version::TLS_1_0,
+            TlsVersion::Tls1_1 => Version::TLS_1_1,
+            TlsVersion::Tls1_2 => Version::TLS_1_2,
+            TlsVersion::Tls1_3 => Version::TLS_1_3,
+        });
+    }
+
+    Ok(builder.danger_accept_invalid_certs(config.danger_accept_invalid_certs))
+}
+
+#[cfg(all(
+    not(any(feature = "rustls", feature = "native-tls")),
+    not(target_arch = "wasm32")
+))]
+fn tls<B: ClientBuilder>(builder: B, config: &IpInfoConfig) -> Result<B, IpError> {
+    if !config.root_certificates.is_empty()
+        || config.min_tls_version.is_some()
+        || config.danger_accept_invalid_certs
+    {
+        return Err(err!(
+            InvalidConfigError,
+            "TLS settings require the `rustls` or `native-tls` feature"
+        ));
+    }
+    Ok(builder)
+}
+
+/// Check that the configuration has no proxy or TLS settings, which are left to the browser on
+/// WASM.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn configure(
+    builder: reqwest::ClientBuilder,
+    config: &IpInfoConfig,
+) -> Result<reqwest::ClientBuilder, IpError> {
+    if config.proxy_url.is_some()
+        || !config.root_certificates.is_empty()
+        || config.min_tls_version.is_some()
+        || config.danger_accept_invalid_certs
+    {
+        return Err(err!(
+            InvalidConfigError,
+            "proxy and TLS settings are not supported on WASM"
+        ));
+    }
+    Ok(builder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_is_built_from_config() {
+        assert!(proxy(&Default::default()).unwrap().is_none());
+
+        let config = IpInfoConfig {
+            proxy_url: Some("http://proxy.internal:3128".to_owned()),
+            proxy_credentials: Some(("user".to_owned(), "pass".to_owned())),
+            ..Default::default()
+        };
+        assert!(proxy(&config).unwrap().is_some());
+
+        let config = IpInfoConfig {
+            proxy_url: Some("not a url".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            proxy(&config).err().unwrap().kind(),
+            crate::IpErrorKind::InvalidConfigError
+        );
+    }
+
+    #[test]
+    fn socks_proxy_depends_on_feature() {
+        let config = IpInfoConfig {
+            proxy_url: Some("socks5h://127.0.0.1:9050".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(proxy(&config).is_ok(), cfg!(feature = "socks"));
+    }
+
+    #[test]
+    fn invalid_root_certificate_is_rejected() {
+        let config = IpInfoConfig {
+            root_certificates: vec![b"not a certificate".to_vec()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            configure(reqwest::blocking::Client::builder(), &config)
+                .err()
+                .unwrap()
+                .kind(),
+            crate::IpErrorKind::InvalidConfigError
+        );
+    }
+
+    #[test]
+    fn tls_settings_are_applied() {
+        let config = IpInfoConfig {
+            min_tls_version: Some(TlsVersion::Tls1_2),
+            danger_accept_invalid_certs: true,
+            ..Default::default()
+        };
+
+        let builder = configure(reqwest::Client::builder(), &config);
+        assert_eq!(
+            builder.is_ok(),
+            cfg!(any(feature = "rustls", feature = "native-tls"))
+        );
+    }
+}
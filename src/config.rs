@@ -0,0 +1,274 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Configuration of the blocking and async clients.
+
+use std::{collections::HashMap, fs, path::Path, sync::Arc, time::Duration};
+
+use crate::{
+    config_file, AsyncTransport, Cache, CircuitBreakerConfig, Continent, CountryCurrency,
+    CountryFlag, Enricher, Interceptor, IpError, Metrics, RetryPolicy, SecretToken, TlsVersion,
+    Transport,
+};
+
+use reqwest::header::HeaderMap;
+
+/// IpInfo structure configuration.
+pub struct IpInfoConfig {
+    /// IPinfo access token.
+    pub token: Option<SecretToken>,
+
+    /// Additional tokens used in turn with `token`, one per request. Requests rate limited on
+    /// one token are retried with the next one. (default: none)
+    pub token_pool: Vec<SecretToken>,
+
+    /// Whether the token is read from the `IPINFO_TOKEN` environment variable when `token` is
+    /// not set. (default: false)
+    pub token_from_env: bool,
+
+    /// Whether batch requests fail with an `UnauthorizedError` instead of being sent without a
+    /// token. (default: false)
+    pub require_token: bool,
+
+    /// The base URL of the API, such as an API gateway or a mock server.
+    /// (default: `https://ipinfo.io`)
+    pub base_url: String,
+
+    /// The base URL of the free IPinfo Lite API, used by `lookup_lite`.
+    /// (default: `https://api.ipinfo.io/lite`)
+    pub lite_url: String,
+
+    /// The HTTP transport of the blocking client, replacing reqwest. (default: none)
+    pub transport: Option<Arc<dyn Transport>>,
+
+    /// The HTTP transport of the async client, replacing reqwest. (default: none)
+    pub async_transport: Option<Arc<dyn AsyncTransport>>,
+
+    /// A product identifier appended to the `User-Agent` header, such as `myapp/2.1`.
+    /// (default: none)
+    pub user_agent_suffix: Option<String>,
+
+    /// Headers added to every request, replacing the default ones of the same name, such as
+    /// the key of an API gateway. (default: none)
+    pub extra_headers: HeaderMap,
+
+    /// The hooks called around every HTTP request, in order. (default: none)
+    pub interceptors: Vec<Arc<dyn Interceptor>>,
+
+    /// The recorder of requests, latencies, cache usage and errors. (default: none)
+    pub metrics: Option<Arc<dyn Metrics>>,
+
+    /// The timeout of HTTP requests, left to the browser on WASM. (default: 3 seconds)
+    pub timeout: Duration,
+
+    /// The URL of the proxy HTTP requests are sent through, such as `http://proxy:3128`, or
+    /// `socks5://127.0.0.1:9050` with the `socks` feature. (default: none)
+    pub proxy_url: Option<String>,
+
+    /// The username and password of the proxy. (default: none)
+    pub proxy_credentials: Option<(String, String)>,
+
+    /// Extra PEM encoded root certificates to trust, such as the CA of a corporate proxy.
+    /// (default: none)
+    pub root_certificates: Vec<Vec<u8>>,
+
+    /// The minimum TLS version accepted. (default: the TLS backend's)
+    pub min_tls_version: Option<TlsVersion>,
+
+    /// Whether invalid TLS certificates are accepted. This disables certificate verification
+    /// entirely, and should only be used for testing. (default: false)
+    pub danger_accept_invalid_certs: bool,
+
+    /// The size of the LRU cache. (default: 100 IPs)
+    pub cache_size: usize,
+
+    /// The cache of IP details, replacing the LRU cache. (default: none)
+    pub cache: Option<Arc<dyn Cache>>,
+
+    /// The file path where the LRU cache is persisted across restarts, written at most once a
+    /// minute, on [`IpInfo::cache_flush`](crate::IpInfo::cache_flush) and when the client is
+    /// dropped. (default: none)
+    pub cache_file_path: Option<String>,
+
+    /// How long cached entries stay valid. (default: forever)
+    pub cache_ttl: Option<Duration>,
+
+    /// Whether expired entries are still returned while being refreshed in the background.
+    /// (default: false)
+    pub stale_while_revalidate: bool,
+
+    /// Whether expired entries are returned, flagged as stale, when the API is unavailable.
+    /// (default: false)
+    pub fallback_to_stale: bool,
+
+    /// How long errors reported by the API for an IP, and invalid IPs, are cached, so the IP
+    /// isn't queried or validated again meanwhile. (default: not cached)
+    pub negative_cache_ttl: Option<Duration>,
+
+    /// The maximum number of batch requests sent concurrently. (default: 4 requests)
+    pub max_concurrency: usize,
+
+    /// The maximum number of addresses of a CIDR range expanded by `lookup_cidr`.
+    /// (default: 256 addresses)
+    pub max_cidr_hosts: usize,
+
+    /// The retry policy of HTTP requests. (default: no retries)
+    pub retry: RetryPolicy,
+
+    /// The circuit breaker of HTTP requests. (default: disabled)
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+
+    /// Whether IP details are enriched with the country name, EU status, flag, currency and
+    /// continent. When disabled, no country data is loaded and IP details are returned as
+    /// answered by the API. (default: true)
+    pub enrich: bool,
+
+    /// The custom enrichers called on the details of every IP address parsed from the API, in
+    /// order, even if `enrich` is disabled. (default: none)
+    pub enrichers: Vec<Arc<dyn Enricher>>,
+
+    /// Whether to keep the untyped JSON answered by the API for each IP address in
+    /// [`IpDetails::raw`](crate::IpDetails::raw), e.g. to store the exact payload.
+    /// (default: false)
+    pub keep_raw: bool,
+
+    /// The file path of `countries.json`
+    pub countries_file_path: Option<String>,
+
+    /// The file path of `eu.json`
+    pub eu_file_path: Option<String>,
+
+    /// The file path of `flags.json`
+    pub country_flags_file_path: Option<String>,
+
+    /// The file path of `currencies.json`
+    pub country_currencies_file_path: Option<String>,
+
+    /// The file path of `continents.json`
+    pub continents_file_path: Option<String>,
+
+    /// Country names keyed by country code, used instead of `countries.json`. (default: none)
+    pub countries: Option<HashMap<String, String>>,
+
+    /// The country codes of EU members, used instead of `eu.json`. (default: none)
+    pub eu: Option<Vec<String>>,
+
+    /// Country flags keyed by country code, used instead of `flags.json`. (default: none)
+    pub country_flags: Option<HashMap<String, CountryFlag>>,
+
+    /// Country currencies keyed by country code, used instead of `currencies.json`.
+    /// (default: none)
+    pub country_currencies: Option<HashMap<String, CountryCurrency>>,
+
+    /// Continents keyed by country code, used instead of `continents.json`. (default: none)
+    pub continents: Option<HashMap<String, Continent>>,
+
+    /// The language of country names, among `en` and the locales of `country_translations`.
+    /// (default: English)
+    pub locale: Option<String>,
+
+    /// Translated country names keyed by locale, then by country code. Countries without a
+    /// translation keep their English name. (default: none)
+    pub country_translations: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for IpInfoConfig {
+    fn default() -> Self {
+        Self {
+            token: None,
+            token_pool: Vec::new(),
+            token_from_env: false,
+            require_token: false,
+            base_url: "https://ipinfo.io".to_owned(),
+            lite_url: "https://api.ipinfo.io/lite".to_owned(),
+            transport: None,
+            async_transport: None,
+            user_agent_suffix: None,
+            extra_headers: HeaderMap::new(),
+            interceptors: Vec::new(),
+            metrics: None,
+            timeout: Duration::from_secs(3),
+            proxy_url: None,
+            proxy_credentials: None,
+            root_certificates: Vec::new(),
+            min_tls_version: None,
+            danger_accept_invalid_certs: false,
+            cache_size: 100,
+            cache: None,
+            cache_file_path: None,
+            cache_ttl: None,
+            stale_while_revalidate: false,
+            fallback_to_stale: false,
+            negative_cache_ttl: None,
+            max_concurrency: 4,
+            max_cidr_hosts: 256,
+            retry: RetryPolicy::default(),
+            circuit_breaker: None,
+            enrich: true,
+            enrichers: Vec::new(),
+            keep_raw: false,
+            countries_file_path: None,
+            eu_file_path: None,
+            country_flags_file_path: None,
+            country_currencies_file_path: None,
+            continents_file_path: None,
+            countries: None,
+            eu: None,
+            country_flags: None,
+            country_currencies: None,
+            continents: None,
+            locale: None,
+            country_translations: HashMap::new(),
+        }
+    }
+}
+
+impl IpInfoConfig {
+    /// Load a configuration from a file, using the defaults for the settings it doesn't set.
+    ///
+    /// The file is in TOML, with durations in seconds:
+    ///
+    /// ```toml
+    /// token = "my token"
+    /// base_url = "https://ipinfo.io"
+    /// timeout = 3
+    /// cache_size = 1000
+    /// cache_ttl = 86400
+    /// proxy_url = "http://proxy:3128"
+    /// ```
+    ///
+    /// The supported keys are `token`, `token_from_env`, `require_token`, `base_url`,
+    /// `lite_url`, `user_agent_suffix`, `timeout`, `proxy_url`, `proxy_username`,
+    /// `proxy_password`, `cache_size`, `cache_file_path`, `cache_ttl`, `stale_while_revalidate`,
+    /// `fallback_to_stale`, `negative_cache_ttl`, `max_concurrency`, `max_cidr_hosts`, `enrich`
+    /// and `keep_raw`.
+    /// Fails with an `InvalidConfigError` if the file can't be read, isn't valid TOML or contains
+    /// anything else.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{IpInfo, IpInfoConfig};
+    ///
+    /// let config = IpInfoConfig::from_file("ipinfo.toml").expect("should load");
+    /// let ipinfo = IpInfo::new(config).expect("should construct");
+    /// ```
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, IpError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)
+            .map_err(|e| err!(InvalidConfigError, "can't read {}: {}", path.display(), e))?;
+
+        config_file::parse(&source)
+    }
+}
@@ -0,0 +1,176 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Client configuration files, in TOML. Durations are given in seconds.
+
+use std::time::Duration;
+
+use crate::{IpError, IpInfoConfig};
+
+use serde::{Deserialize, Deserializer};
+
+/// The settings of a configuration file, overriding those of the default configuration.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    token: Option<String>,
+    token_from_env: Option<bool>,
+    require_token: Option<bool>,
+    base_url: Option<String>,
+    lite_url: Option<String>,
+    user_agent_suffix: Option<String>,
+    #[serde(deserialize_with = "seconds")]
+    timeout: Option<Duration>,
+    proxy_url: Option<String>,
+    proxy_username: Option<String>,
+    proxy_password: Option<String>,
+    cache_size: Option<usize>,
+    cache_file_path: Option<String>,
+    #[serde(deserialize_with = "seconds")]
+    cache_ttl: Option<Duration>,
+    stale_while_revalidate: Option<bool>,
+    fallback_to_stale: Option<bool>,
+    #[serde(deserialize_with = "seconds")]
+    negative_cache_ttl: Option<Duration>,
+    max_concurrency: Option<usize>,
+    max_cidr_hosts: Option<usize>,
+    enrich: Option<bool>,
+    keep_raw: Option<bool>,
+}
+
+/// Parse a configuration file, starting from the default configuration.
+pub(crate) fn parse(source: &str) -> Result<IpInfoConfig, IpError> {
+    let file: ConfigFile = toml::from_str(source).map_err(|e| err!(InvalidConfigError, "{}", e))?;
+    let defaults = IpInfoConfig::default();
+
+    let proxy_credentials = match (file.proxy_username, file.proxy_password) {
+        (Some(username), password) => Some((username, password.unwrap_or_default())),
+        (None, Some(_)) => {
+            return Err(err!(
+                InvalidConfigError,
+                "proxy_password requires proxy_username"
+            ))
+        }
+        (None, None) => None,
+    };
+
+    Ok(IpInfoConfig {
+        token: file.token.map(Into::into),
+        token_from_env: file.token_from_env.unwrap_or(defaults.token_from_env),
+        require_token: file.require_token.unwrap_or(defaults.require_token),
+        base_url: file.base_url.unwrap_or(defaults.base_url),
+        lite_url: file.lite_url.unwrap_or(defaults.lite_url),
+        user_agent_suffix: file.user_agent_suffix,
+        timeout: file.timeout.unwrap_or(defaults.timeout),
+        proxy_url: file.proxy_url,
+        proxy_credentials,
+        cache_size: file.cache_size.unwrap_or(defaults.cache_size),
+        cache_file_path: file.cache_file_path,
+        cache_ttl: file.cache_ttl,
+        stale_while_revalidate: file
+            .stale_while_revalidate
+            .unwrap_or(defaults.stale_while_revalidate),
+        fallback_to_stale: file.fallback_to_stale.unwrap_or(defaults.fallback_to_stale),
+        negative_cache_ttl: file.negative_cache_ttl,
+        max_concurrency: file.max_concurrency.unwrap_or(defaults.max_concurrency),
+        max_cidr_hosts: file.max_cidr_hosts.unwrap_or(defaults.max_cidr_hosts),
+        enrich: file.enrich.unwrap_or(defaults.enrich),
+        keep_raw: file.keep_raw.unwrap_or(defaults.keep_raw),
+        ..defaults
+    })
+}
+
+/// Deserialize a duration given in seconds, as an integer or a float.
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Seconds {
+        Integer(u64),
+        Float(f64),
+    }
+
+    match Seconds::deserialize(deserializer)? {
+        Seconds::Integer(secs) => Ok(Some(Duration::from_secs(secs))),
+        Seconds::Float(secs) => Duration::try_from_secs_f64(secs)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IpErrorKind;
+
+    #[test]
+    fn config_file_is_parsed() {
+        let config = parse(
+            r#"
+            # Production settings
+            token = "my token" # from the dashboard
+            base_url = "https://gateway.internal/ipinfo"
+            lite_url = "https://gateway.internal/lite"
+            timeout = 1.5
+            cache_size = 10_000
+            max_cidr_hosts = 1024
+            cache_ttl = 3600
+            fallback_to_stale = true
+            enrich = false
+            keep_raw = true
+            proxy_url = "http://proxy:3128"
+            proxy_username = "user"
+            proxy_password = 'p#ss"word'
+            user_agent_suffix = "caf\u00e9"
+            "#,
+        )
+        .expect("should parse");
+
+        assert_eq!(config.token.unwrap().expose_secret(), "my token");
+        assert_eq!(config.base_url, "https://gateway.internal/ipinfo");
+        assert_eq!(config.lite_url, "https://gateway.internal/lite");
+        assert_eq!(config.timeout, Duration::from_millis(1500));
+        assert_eq!(config.cache_size, 10_000);
+        assert_eq!(config.max_cidr_hosts, 1024);
+        assert_eq!(config.cache_ttl, Some(Duration::from_secs(3600)));
+        assert!(config.fallback_to_stale);
+        assert!(!config.enrich);
+        assert!(config.keep_raw);
+        assert_eq!(
+            config.proxy_credentials,
+            Some(("user".to_owned(), "p#ss\"word".to_owned()))
+        );
+        assert_eq!(config.user_agent_suffix.as_deref(), Some("café"));
+        assert_eq!(config.max_concurrency, 4);
+        assert_eq!(config.negative_cache_ttl, None);
+    }
+
+    #[test]
+    fn invalid_config_files_are_rejected() {
+        for source in [
+            "token",
+            "token = 42",
+            "tokne = \"typo\"",
+            "timeout = \"3s\"",
+            "cache_size = -1",
+            "base_url = \"unterminated",
+            "proxy_password = \"secret\"",
+        ] {
+            let err = parse(source).err().expect(source);
+            assert_eq!(err.kind(), IpErrorKind::InvalidConfigError);
+        }
+
+        let err = parse("\n\ntokne = 1").err().unwrap().to_string();
+        assert!(err.contains("line 3") && err.contains("tokne"), "{err}");
+    }
+}
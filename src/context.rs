@@ -0,0 +1,1583 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Request context shared by the blocking and async clients.
+
+use std::{
+    collections::{HashMap, HashSet},
+    mem,
+    net::IpAddr,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use crate::{
+    bogon::bogon_details, circuit::CircuitBreaker, flight::Flights, retry::Failure,
+    token::TokenPool, AsnResponse, Cache, CacheEntry, CacheStats, CountryData, Enricher, FileCache,
+    HttpRequest, HttpResponse, Interceptor, IpDetails, IpError, IpErrorKind, IpInfoConfig,
+    MemoryCache, Metrics, RateLimit, RetryPolicy, SecretToken, VERSION,
+};
+
+use lru::LruCache;
+use serde::de::DeserializeOwned;
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
+    Method, StatusCode,
+};
+use zeroize::Zeroizing;
+
+/// The environment variable the token is read from.
+const TOKEN_ENV_VAR: &str = "IPINFO_TOKEN";
+
+/// The maximum number of IPs accepted by a single batch request.
+pub(crate) const BATCH_MAX_SIZE: usize = 1000;
+
+/// The progress of sending an API request over retries and token failovers.
+pub(crate) struct Attempts {
+    request: HttpRequest,
+    attempt: u32,
+    failovers: usize,
+}
+
+impl Attempts {
+    pub(crate) fn new(request: HttpRequest) -> Self {
+        Self {
+            request,
+            attempt: 1,
+            failovers: 0,
+        }
+    }
+}
+
+/// What to do after a request attempt.
+pub(crate) enum Next {
+    /// Return the outcome of the request.
+    Done(Result<String, IpError>),
+    /// Send another attempt, after waiting if any.
+    Retry(Option<Duration>),
+}
+
+/// Cache, country data and credentials used to build requests and process responses.
+pub(crate) struct Context {
+    pub(crate) url: String,
+    pub(crate) lite_url: String,
+    headers: HeaderMap,
+    interceptors: Vec<Arc<dyn Interceptor>>,
+    metrics: Option<Arc<dyn Metrics>>,
+    tokens: Arc<TokenPool>,
+    require_token: bool,
+    pub(crate) max_concurrency: usize,
+    pub(crate) max_cidr_hosts: usize,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) breaker: Arc<CircuitBreaker>,
+    pub(crate) flights: Arc<Flights>,
+    rate_limit: Arc<Mutex<Option<RateLimit>>>,
+    cache: Arc<dyn Cache>,
+    asn_cache: Arc<Mutex<LruCache<String, CacheEntry<AsnResponse>>>>,
+    cache_ttl: Option<Duration>,
+    stale_while_revalidate: bool,
+    fallback_to_stale: bool,
+    revalidating: Arc<Mutex<HashSet<String>>>,
+    stale: Mutex<Vec<String>>,
+    negative_cache: Arc<Mutex<LruCache<String, CacheEntry<IpError>>>>,
+    negative_cache_ttl: Option<Duration>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    countries: Arc<RwLock<CountryData>>,
+    enrich: bool,
+    enrichers: Vec<Arc<dyn Enricher>>,
+    keep_raw: bool,
+}
+
+impl Context {
+    /// Construct a new context from the client configuration.
+    pub(crate) fn new(mut config: IpInfoConfig) -> Result<Self, IpError> {
+        let size = NonZeroUsize::new(config.cache_size)
+            .ok_or_else(|| err!(InvalidConfigError, "cache_size must be greater than 0"))?;
+
+        reqwest::Url::parse(&config.base_url)
+            .map_err(|e| err!(InvalidConfigError, "invalid base_url: {}", e))?;
+        reqwest::Url::parse(&config.lite_url)
+            .map_err(|e| err!(InvalidConfigError, "invalid lite_url: {}", e))?;
+        let token = match config.token.take() {
+            Some(token) => Some(token),
+            None if config.token_from_env => std::env::var(TOKEN_ENV_VAR)
+                .ok()
+                .filter(|t| !t.is_empty())
+                .map(SecretToken::new),
+            None => None,
+        };
+        let tokens: Vec<SecretToken> = token
+            .into_iter()
+            .chain(config.token_pool.drain(..))
+            .collect();
+        for token in &tokens {
+            validate_token(token)?;
+        }
+        let headers = Self::construct_headers(&config)?;
+        let countries = match config.enrich {
+            true => CountryData::from_config(&config)?,
+            false => CountryData::empty(),
+        };
+
+        let cache: Arc<dyn Cache> = match (config.cache, &config.cache_file_path) {
+            (Some(cache), _) => cache,
+            (None, Some(path)) => Arc::new(FileCache::open(path, size)),
+            (None, None) => Arc::new(MemoryCache::new(size)),
+        };
+
+        Ok(Self {
+            url: config.base_url.trim_end_matches('/').to_owned(),
+            lite_url: config.lite_url.trim_end_matches('/').to_owned(),
+            headers,
+            interceptors: config.interceptors,
+            metrics: config.metrics,
+            tokens: Arc::new(TokenPool::new(tokens)),
+            require_token: config.require_token,
+            max_concurrency: config.max_concurrency.max(1),
+            max_cidr_hosts: config.max_cidr_hosts,
+            retry: config.retry,
+            breaker: Arc::new(CircuitBreaker::new(config.circuit_breaker)),
+            flights: Default::default(),
+            rate_limit: Default::default(),
+            cache,
+            asn_cache: Arc::new(Mutex::new(LruCache::new(size))),
+            cache_ttl: config.cache_ttl,
+            stale_while_revalidate: config.stale_while_revalidate,
+            fallback_to_stale: config.fallback_to_stale,
+            revalidating: Default::default(),
+            stale: Default::default(),
+            negative_cache: Arc::new(Mutex::new(LruCache::new(size))),
+            negative_cache_ttl: config.negative_cache_ttl,
+            cache_hits: Default::default(),
+            cache_misses: Default::default(),
+            countries: Arc::new(RwLock::new(countries)),
+            enrich: config.enrich,
+            enrichers: config.enrichers,
+            keep_raw: config.keep_raw,
+        })
+    }
+
+    /// Construct a context sharing the caches, circuit breaker and country data of this one.
+    ///
+    /// The cache statistics are not shared, while identical lookups in flight are coalesced
+    /// across forks.
+    pub(crate) fn fork(&self) -> Self {
+        Self {
+            url: self.url.clone(),
+            lite_url: self.lite_url.clone(),
+            headers: self.headers.clone(),
+            interceptors: self.interceptors.clone(),
+            metrics: self.metrics.clone(),
+            tokens: self.tokens.clone(),
+            require_token: self.require_token,
+            max_concurrency: self.max_concurrency,
+            max_cidr_hosts: self.max_cidr_hosts,
+            retry: self.retry.clone(),
+            breaker: self.breaker.clone(),
+            flights: self.flights.clone(),
+            rate_limit: self.rate_limit.clone(),
+            cache: self.cache.clone(),
+            asn_cache: self.asn_cache.clone(),
+            cache_ttl: self.cache_ttl,
+            stale_while_revalidate: self.stale_while_revalidate,
+            fallback_to_stale: self.fallback_to_stale,
+            revalidating: self.revalidating.clone(),
+            stale: Default::default(),
+            negative_cache: self.negative_cache.clone(),
+            negative_cache_ttl: self.negative_cache_ttl,
+            cache_hits: Default::default(),
+            cache_misses: Default::default(),
+            countries: self.countries.clone(),
+            enrich: self.enrich,
+            enrichers: self.enrichers.clone(),
+            keep_raw: self.keep_raw,
+        }
+    }
+
+    /// Fork the context to authenticate its requests with another token.
+    pub(crate) fn with_token(&self, token: SecretToken) -> Result<Self, IpError> {
+        validate_token(&token)?;
+
+        Ok(Self {
+            tokens: Arc::new(TokenPool::new(vec![token])),
+            ..self.fork()
+        })
+    }
+
+    /// Add the cache statistics of a fork to the ones of this context.
+    pub(crate) fn merge_stats(&self, fork: &Context) {
+        let hits = fork.cache_hits.load(Ordering::Relaxed);
+        let misses = fork.cache_misses.load(Ordering::Relaxed);
+        self.cache_hits.fetch_add(hits, Ordering::Relaxed);
+        self.cache_misses.fetch_add(misses, Ordering::Relaxed);
+    }
+
+    /// Split the requested IPs into cache hits and the IPs that must be queried.
+    ///
+    /// Bogons are answered locally and count as hits.
+    pub(crate) fn check_cache<'a>(&self, ips: &[&'a str]) -> (Vec<IpDetails>, Vec<&'a str>) {
+        let mut hits: Vec<IpDetails> = vec![];
+        let mut misses: Vec<&str> = vec![];
+
+        ips.iter().for_each(|x| match self.get_cached(x) {
+            Some(detail) => hits.push(detail),
+            None => misses.push(*x),
+        });
+
+        (hits, misses)
+    }
+
+    /// Fall back to the expired cached details of an IP if enabled and the API is unavailable,
+    /// or return the error.
+    pub(crate) fn stale_or(&self, ip: &str, error: IpError) -> Result<IpDetails, IpError> {
+        if !self.fallback_to_stale || !error.is_unavailable() {
+            return Err(error);
+        }
+
+        match self.cache.get(ip) {
+            Some(entry) => {
+                trace_event!(warn, ip, %error, "falling back to stale cache entry");
+                Ok(IpDetails {
+                    stale: true,
+                    ..entry.value
+                })
+            }
+            None => Err(error),
+        }
+    }
+
+    /// Fall back to the expired cached details of a chunk of IPs, as for [`Context::stale_or`].
+    pub(crate) fn stale_batch_or(
+        &self,
+        chunk: &[&str],
+        error: IpError,
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        chunk
+            .iter()
+            .map(|ip| Ok((ip.to_string(), self.stale_or(ip, error.clone())?)))
+            .collect()
+    }
+
+    /// Get the details cached by a lookup that was joined, if it succeeded.
+    pub(crate) fn get_joined(&self, ip: &str) -> Option<IpDetails> {
+        self.cache
+            .get(ip)
+            .filter(|entry| !is_expired(entry, self.cache_ttl))
+            .map(|entry| entry.value)
+    }
+
+    /// Split the requested IPs into bogons, answered locally, and the IPs that must be queried.
+    pub(crate) fn check_bogons<'a>(&self, ips: &[&'a str]) -> (Vec<IpDetails>, Vec<&'a str>) {
+        let mut bogons: Vec<IpDetails> = vec![];
+        let mut misses: Vec<&str> = vec![];
+
+        ips.iter().for_each(|x| match bogon_details(x) {
+            Some(detail) => bogons.push(detail),
+            None => misses.push(*x),
+        });
+
+        (bogons, misses)
+    }
+
+    /// Get the cached (or locally synthesized bogon) details for an IP, if any.
+    pub(crate) fn get_cached(&self, ip: &str) -> Option<IpDetails> {
+        if let Some(details) = bogon_details(ip) {
+            return Some(details);
+        }
+
+        let Some(entry) = self.cache.get(ip) else {
+            trace_event!(debug, ip, "cache miss");
+            self.count_miss();
+            return None;
+        };
+        if is_expired(&entry, self.cache_ttl) {
+            if self.stale_while_revalidate {
+                // Serve the stale entry, refreshing it once in the background
+                if self.revalidating.lock().unwrap().insert(ip.to_owned()) {
+                    self.stale.lock().unwrap().push(ip.to_owned());
+                }
+                trace_event!(debug, ip, "stale cache hit");
+                self.count_hit();
+                return Some(IpDetails {
+                    stale: true,
+                    ..entry.value
+                });
+            }
+
+            // Keep the expired entry to fall back to
+            if !self.fallback_to_stale {
+                self.cache.remove(ip);
+            }
+            trace_event!(debug, ip, "expired cache entry");
+            self.count_miss();
+            return None;
+        }
+
+        trace_event!(debug, ip, "cache hit");
+        self.count_hit();
+        Some(entry.value)
+    }
+
+    fn count_hit(&self) {
+        self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_cache_hit();
+        }
+    }
+
+    fn count_miss(&self) {
+        self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.record_cache_miss();
+        }
+    }
+
+    /// Take the IPs whose stale entries were served and must be refreshed.
+    pub(crate) fn take_stale(&self) -> Vec<String> {
+        mem::take(&mut self.stale.lock().unwrap())
+    }
+
+    /// Mark the refresh of stale IPs as done, successful or not.
+    pub(crate) fn finish_revalidation(&self, ips: &[&str]) {
+        let mut revalidating = self.revalidating.lock().unwrap();
+        ips.iter().for_each(|ip| {
+            revalidating.remove(*ip);
+        });
+    }
+
+    /// Check that a token is configured if one is required for batch requests.
+    pub(crate) fn check_token(&self) -> Result<(), IpError> {
+        match (self.tokens.is_empty(), self.require_token) {
+            (true, true) => Err(err!(
+                UnauthorizedError,
+                "a token is required for batch requests"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Construct the request reporting the details of the token, failing if none is configured.
+    pub(crate) fn token_request(&self) -> Result<HttpRequest, IpError> {
+        self.get_authenticated(format!("{}/me", self.url))
+    }
+
+    /// Construct a GET API request which needs a token, failing if none is configured.
+    pub(crate) fn get_authenticated(&self, url: String) -> Result<HttpRequest, IpError> {
+        if self.tokens.is_empty() {
+            return Err(err!(UnauthorizedError, "no token is configured"));
+        }
+
+        Ok(self.get(url))
+    }
+
+    /// Record the rate limit reported by a response, if any.
+    pub(crate) fn record_rate_limit(&self, headers: &HeaderMap) {
+        if let Some(rate_limit) = RateLimit::from_headers(headers) {
+            *self.rate_limit.lock().unwrap() = Some(rate_limit);
+        }
+    }
+
+    /// Get the rate limit reported by the last response that had one.
+    pub(crate) fn last_rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().unwrap()
+    }
+
+    /// Get the usage statistics of the IP details cache.
+    pub(crate) fn cache_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.cache_hits.load(Ordering::Relaxed),
+            misses: self.cache_misses.load(Ordering::Relaxed),
+            evictions: self.cache.evictions(),
+            size: self.cache.size(),
+        }
+    }
+
+    /// Return the error recently reported by the API for an IP, if any.
+    pub(crate) fn check_negative(&self, ip: &str) -> Result<(), IpError> {
+        let mut negative_cache = self.negative_cache.lock().unwrap();
+        let Some(entry) = negative_cache.get(ip) else {
+            return Ok(());
+        };
+        if is_expired(entry, self.negative_cache_ttl) {
+            negative_cache.pop(ip);
+            return Ok(());
+        }
+
+        Err(entry.value.clone())
+    }
+
+    /// Validate an IP, failing with the error recently reported for it if any.
+    ///
+    /// Invalid IPs are negatively cached, so repeated lookups don't validate them again.
+    pub(crate) fn check_ip(&self, ip: &str) -> Result<(), IpError> {
+        self.check_negative(ip)?;
+        validate_ip(ip).inspect_err(|e| self.cache_negative(ip, e))
+    }
+
+    /// Validate a list of IPs, leaving out the IPs the API recently reported an error for.
+    ///
+    /// Fails if any IP is invalid, as for [`Context::check_ip`].
+    pub(crate) fn check_ips<'a>(&self, ips: &[&'a str]) -> Result<Vec<&'a str>, IpError> {
+        let mut valid = Vec::with_capacity(ips.len());
+        for ip in ips {
+            match self.check_ip(ip) {
+                Ok(()) => valid.push(*ip),
+                Err(e) if e.kind() == IpErrorKind::InvalidIpError => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        Ok(valid)
+    }
+
+    /// Cache an error for an IP, if negative caching is enabled.
+    ///
+    /// Only errors answered by the API and invalid IPs are cached, never transient HTTP
+    /// failures.
+    pub(crate) fn cache_negative(&self, ip: &str, error: &IpError) {
+        if self.negative_cache_ttl.is_some()
+            && matches!(
+                error.kind(),
+                IpErrorKind::IpRequestError | IpErrorKind::InvalidIpError
+            )
+        {
+            self.negative_cache
+                .lock()
+                .unwrap()
+                .put(ip.to_owned(), CacheEntry::new(error.clone()));
+        }
+    }
+
+    /// Remove the cached details of an IP.
+    pub(crate) fn cache_remove(&self, ip: &str) {
+        self.cache.remove(ip);
+        self.negative_cache.lock().unwrap().pop(ip);
+    }
+
+    /// Remove every cached IP and ASN.
+    pub(crate) fn cache_clear(&self) {
+        self.cache.clear();
+        self.asn_cache.lock().unwrap().clear();
+        self.negative_cache.lock().unwrap().clear();
+    }
+
+    /// Write the cached IPs to storage, if the cache backend persists them lazily.
+    pub(crate) fn cache_flush(&self) -> Result<(), IpError> {
+        self.cache.flush()
+    }
+
+    /// Cache the details of an IP.
+    fn cache_details(&self, details: &IpDetails) {
+        self.cache
+            .put(&details.ip, CacheEntry::new(details.clone()));
+    }
+
+    /// Parse a raw batch response, then enrich and cache the results.
+    ///
+    /// IPs the API answered with an error are negatively cached and left out of the results.
+    pub(crate) fn process_batch(
+        &self,
+        raw_resp: &str,
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        let results: HashMap<String, serde_json::Value> = parse_response(raw_resp)?;
+
+        let mut details = HashMap::with_capacity(results.len());
+        for (ip, value) in results {
+            match self.process_value(value) {
+                Ok(detail) => {
+                    details.insert(ip, detail);
+                }
+                Err(e) if e.kind() == IpErrorKind::IpRequestError => {
+                    self.cache_negative(&ip, &e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(details)
+    }
+
+    /// Process the response of a batch request, falling back to stale entries if it failed.
+    pub(crate) fn process_batch_or_stale(
+        &self,
+        chunk: &[&str],
+        raw_resp: Result<String, IpError>,
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        match raw_resp {
+            Ok(raw_resp) => self.process_batch(&raw_resp),
+            Err(e) => self.stale_batch_or(chunk, e),
+        }
+    }
+
+    /// Parse the raw batch response of a chunk of IPs into a result per IP.
+    ///
+    /// A failed request or unparseable response is reported for every IP of the chunk, and IPs
+    /// missing from the response are reported as request errors.
+    pub(crate) fn process_batch_each(
+        &self,
+        chunk: &[&str],
+        raw_resp: Result<String, IpError>,
+    ) -> HashMap<String, Result<IpDetails, IpError>> {
+        // Parse the results, leaving the details of each IP to be parsed on their own
+        let results = raw_resp.and_then(|raw_resp| parse_response(&raw_resp));
+        let results: HashMap<String, serde_json::Value> = match results {
+            Ok(results) => results,
+            Err(e) => {
+                return chunk
+                    .iter()
+                    .map(|ip| (ip.to_string(), self.stale_or(ip, e.clone())))
+                    .collect()
+            }
+        };
+
+        let mut details: HashMap<String, Result<IpDetails, IpError>> = results
+            .into_iter()
+            .map(|(ip, value)| {
+                let details = self.process_value(value);
+                if let Err(e) = &details {
+                    self.cache_negative(&ip, e);
+                }
+                (ip, details)
+            })
+            .collect();
+
+        chunk.iter().for_each(|ip| {
+            if !details.contains_key(*ip) {
+                let e = err!(IpRequestError, "no result returned for {}", ip);
+                details.insert(ip.to_string(), Err(e));
+            }
+        });
+
+        details
+    }
+
+    /// Parse the details of a single IP from a batch response, then enrich and cache them.
+    fn process_value(&self, value: serde_json::Value) -> Result<IpDetails, IpError> {
+        if let Some(e) = value["error"].as_str() {
+            return Err(err!(IpRequestError, e));
+        }
+
+        let mut details = self.parse_details(value)?;
+
+        self.enrich(&mut details);
+        self.cache_details(&details);
+
+        Ok(details)
+    }
+
+    /// Parse a raw single IP response, then enrich and cache the result.
+    pub(crate) fn process_single(&self, raw_resp: &str) -> Result<IpDetails, IpError> {
+        let mut details: IpDetails = if self.keep_raw {
+            self.parse_details(parse_response(raw_resp)?)?
+        } else {
+            parse_response(raw_resp)?
+        };
+
+        self.enrich(&mut details);
+        self.cache_details(&details);
+
+        Ok(details)
+    }
+
+    /// Parse the details of an IP, keeping the untyped JSON if enabled.
+    fn parse_details(&self, value: serde_json::Value) -> Result<IpDetails, IpError> {
+        let raw = self.keep_raw.then(|| value.clone());
+        let mut details: IpDetails = serde_json::from_value(value)?;
+        details.raw = raw;
+
+        Ok(details)
+    }
+
+    /// Get the cached details for an ASN, if any.
+    pub(crate) fn get_cached_asn(&self, asn: &str) -> Option<AsnResponse> {
+        let mut asn_cache = self.asn_cache.lock().unwrap();
+        let entry = asn_cache.get(asn)?;
+        if is_expired(entry, self.cache_ttl) {
+            asn_cache.pop(asn);
+            return None;
+        }
+
+        Some(entry.value.clone())
+    }
+
+    /// Parse a raw ASN response and cache the result.
+    pub(crate) fn process_asn(&self, raw_resp: &str) -> Result<AsnResponse, IpError> {
+        let details: AsnResponse = parse_response(raw_resp)?;
+
+        let entry = CacheEntry::new(details.clone());
+        self.asn_cache
+            .lock()
+            .unwrap()
+            .put(details.asn.clone(), entry);
+
+        Ok(details)
+    }
+
+    /// Read the user-provided country data files again, keeping the other country data.
+    ///
+    /// The country data is left unchanged if any file can't be loaded.
+    pub(crate) fn reload_assets(&self) -> Result<(), IpError> {
+        let mut countries = self.countries.write().unwrap();
+        *countries = countries.reload()?;
+
+        Ok(())
+    }
+
+    /// Get the country data used to enrich IP details.
+    pub(crate) fn countries(&self) -> CountryData {
+        self.countries.read().unwrap().clone()
+    }
+
+    /// Add the country name, EU status, flag, currency and continent to the details if
+    /// enabled, then call the custom enrichers.
+    pub(crate) fn enrich(&self, details: &mut IpDetails) {
+        self.enrich_countries(details);
+        for enricher in &self.enrichers {
+            enricher.enrich(details);
+        }
+    }
+
+    /// Add the country name, EU status, flag, currency and continent to the details if enabled.
+    pub(crate) fn enrich_countries(&self, details: &mut IpDetails) {
+        if self.enrich {
+            self.countries.read().unwrap().enrich(details);
+        }
+    }
+
+    /// Construct a GET API request, authenticated with the token, if any.
+    pub(crate) fn get(&self, url: String) -> HttpRequest {
+        self.request(Method::GET, url, None)
+    }
+
+    /// Construct a POST API request with a JSON body, authenticated with the token, if any.
+    pub(crate) fn post(&self, url: String, body: String) -> HttpRequest {
+        self.request(Method::POST, url, Some(body))
+    }
+
+    fn request(&self, method: Method, url: String, body: Option<String>) -> HttpRequest {
+        let mut request = HttpRequest {
+            method,
+            url,
+            headers: self.headers.clone(),
+            body,
+        };
+        self.authorize(&mut request);
+        request
+    }
+
+    /// Authenticate a request with the next token of the pool, if any.
+    fn authorize(&self, request: &mut HttpRequest) {
+        if let Some(token) = self.tokens.rotate() {
+            let header = Zeroizing::new(format!("Bearer {}", token.expose_secret()));
+            let mut value = HeaderValue::from_str(&header).expect("token should be validated");
+            value.set_sensitive(true);
+            request.headers.insert(AUTHORIZATION, value);
+        }
+    }
+
+    /// Switch a rate limited request to the next token of the pool, unless the request already
+    /// failed over `failovers` times and every token was tried.
+    fn fail_over(&self, request: &mut HttpRequest, failovers: usize) -> bool {
+        if failovers + 1 >= self.tokens.len() {
+            return false;
+        }
+
+        self.authorize(request);
+        true
+    }
+
+    /// Let the interceptors modify a request attempt before it is sent.
+    fn intercept(&self, request: &mut HttpRequest) {
+        for interceptor in &self.interceptors {
+            interceptor.on_request(request);
+        }
+    }
+
+    /// Build the next attempt of a request, failing fast while the API is unavailable.
+    ///
+    /// The clients only send the attempts and wait between them, leaving every decision to
+    /// [`Context::finish_attempt`].
+    pub(crate) fn start_attempt(&self, attempts: &Attempts) -> Result<HttpRequest, IpError> {
+        self.breaker.check()?;
+
+        let mut request = attempts.request.clone();
+        self.intercept(&mut request);
+        Ok(request)
+    }
+
+    /// Handle the response of a request attempt, which took `latency`, recording it with the
+    /// circuit breaker and deciding whether to fail over, retry or give up.
+    pub(crate) fn finish_attempt(
+        &self,
+        attempts: &mut Attempts,
+        request: &HttpRequest,
+        response: Result<HttpResponse, IpError>,
+        latency: Duration,
+    ) -> Next {
+        let failure = match self.read_response(request, response, latency) {
+            Ok(raw_resp) => {
+                self.breaker.record_success();
+                return Next::Done(Ok(raw_resp));
+            }
+            Err(failure) => failure,
+        };
+
+        if failure.unavailable {
+            self.breaker.record_failure();
+        }
+
+        // Retry rate limited requests with the next token of the pool right away
+        if failure.error.kind() == IpErrorKind::RateLimitExceededError
+            && self.fail_over(&mut attempts.request, attempts.failovers)
+        {
+            attempts.failovers += 1;
+            return Next::Retry(None);
+        }
+        if !failure.retryable || attempts.attempt >= self.retry.max_attempts {
+            return Next::Done(Err(failure.error));
+        }
+
+        let attempt = attempts.attempt;
+        let wait = failure.wait.unwrap_or_else(|| self.retry.delay(attempt));
+        trace_event!(debug, attempt, ?wait, error = %failure.error, "retrying request");
+        attempts.attempt += 1;
+        Next::Retry(Some(wait))
+    }
+
+    /// Check the response of a single request attempt, which took `latency`, and acquire its
+    /// body.
+    fn read_response(
+        &self,
+        request: &HttpRequest,
+        response: Result<HttpResponse, IpError>,
+        latency: Duration,
+    ) -> Result<String, Failure> {
+        if let Some(metrics) = &self.metrics {
+            metrics.record_request(response.as_ref().ok().map(|response| response.status));
+            metrics.record_latency(latency);
+        }
+
+        let result = self.check_response(request, response);
+        if let Err(failure) = &result {
+            for interceptor in &self.interceptors {
+                interceptor.on_error(request, &failure.error);
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.record_error(failure.error.kind());
+            }
+        }
+        result
+    }
+
+    fn check_response(
+        &self,
+        request: &HttpRequest,
+        response: Result<HttpResponse, IpError>,
+    ) -> Result<String, Failure> {
+        let response = response.map_err(|e| self.retry.classify(e))?;
+        trace_event!(debug, status = %response.status, "response received");
+        self.record_rate_limit(&response.headers);
+        for interceptor in &self.interceptors {
+            interceptor.on_response(request, &response);
+        }
+
+        // Check if we exhausted our request quota
+        if response.status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(self.retry.classify_rate_limited(&response.headers));
+        }
+
+        // Keep the body of error responses for debugging
+        if response.status.is_client_error() || response.status.is_server_error() {
+            let error =
+                IpError::from_status(response.status, &request.url).with_body(&response.body);
+            return Err(self.retry.classify(error));
+        }
+
+        Ok(response.body)
+    }
+
+    /// Construct API request headers.
+    fn construct_headers(config: &IpInfoConfig) -> Result<HeaderMap, IpError> {
+        let user_agent = match &config.user_agent_suffix {
+            Some(suffix) => format!("IPinfoClient/Rust/{VERSION} {suffix}"),
+            None => format!("IPinfoClient/Rust/{VERSION}"),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            USER_AGENT,
+            HeaderValue::from_str(&user_agent).map_err(|_| {
+                err!(
+                    InvalidConfigError,
+                    "user_agent_suffix contains invalid characters"
+                )
+            })?,
+        );
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.extend(config.extra_headers.clone());
+        Ok(headers)
+    }
+}
+
+/// Check whether a cache entry is older than the TTL.
+fn is_expired<T>(entry: &CacheEntry<T>, ttl: Option<Duration>) -> bool {
+    ttl.is_some_and(|ttl| entry.age() > ttl)
+}
+
+/// Validate that every input is a syntactically valid IP address.
+pub(crate) fn validate_ips(ips: &[&str]) -> Result<(), IpError> {
+    ips.iter().try_for_each(|ip| validate_ip(ip))
+}
+
+/// Validate that the input is a syntactically valid IP address.
+pub(crate) fn validate_ip(ip: &str) -> Result<(), IpError> {
+    match ip.parse::<IpAddr>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(err!(InvalidIpError, "{:?}", ip)),
+    }
+}
+
+/// Normalize an AS number, such as `AS15169`, `as15169` or `15169`, to its `AS15169` form,
+/// failing with a `ParseError` for anything else.
+pub(crate) fn normalize_asn(asn: &str) -> Result<String, IpError> {
+    let digits = asn
+        .get(..2)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("AS"))
+        .map_or(asn, |_| &asn[2..]);
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(err!(ParseError, "invalid ASN {:?}", asn));
+    }
+    Ok(format!("AS{digits}"))
+}
+
+/// Validate that the input is a hostname, dot separated labels of ASCII letters, digits and
+/// hyphens, so it can't change the path or query of the request it is sent in.
+pub(crate) fn validate_domain(domain: &str) -> Result<(), IpError> {
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    };
+
+    if domain.len() <= 253 && domain.split('.').all(valid_label) {
+        Ok(())
+    } else {
+        Err(err!(ParseError, "invalid domain {:?}", domain))
+    }
+}
+
+/// Expand a CIDR range (such as `203.0.113.0/28`) to all its addresses, failing with an
+/// `InvalidIpError` if it is malformed or has more than `max_hosts` addresses.
+pub(crate) fn expand_cidr(cidr: &str, max_hosts: usize) -> Result<Vec<IpAddr>, IpError> {
+    let invalid = || err!(InvalidIpError, "{:?}", cidr);
+    let (ip, prefix) = cidr.split_once('/').ok_or_else(invalid)?;
+    let ip: IpAddr = ip.parse().map_err(|_| invalid())?;
+    let prefix: u32 = prefix.parse().map_err(|_| invalid())?;
+
+    let bits = if ip.is_ipv4() { 32 } else { 128 };
+    if prefix > bits {
+        return Err(invalid());
+    }
+    let host_bits = bits - prefix;
+    if host_bits >= usize::BITS || 1usize << host_bits > max_hosts {
+        return Err(err!(
+            InvalidIpError,
+            "{} has more than {} addresses (max_cidr_hosts)",
+            cidr,
+            max_hosts
+        ));
+    }
+
+    Ok(match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(host_bits).unwrap_or(0);
+            v4_hosts(u32::from(ip) & mask, host_bits).collect()
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0);
+            let network = u128::from(ip) & mask;
+            (0..1u128 << host_bits)
+                .map(|i| IpAddr::from(std::net::Ipv6Addr::from(network | i)))
+                .collect()
+        }
+    })
+}
+
+/// Get the addresses of an IPv4 network with `host_bits` (up to 32) host bits.
+fn v4_hosts(network: u32, host_bits: u32) -> impl DoubleEndedIterator<Item = IpAddr> {
+    // Count in u64, as a /0 network has 2^32 addresses
+    (0..1u64 << host_bits).map(move |i| IpAddr::from(std::net::Ipv4Addr::from(network | i as u32)))
+}
+
+/// Resolve a hostname to its distinct IPv4 and IPv6 addresses.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn resolve_host(host: &str) -> Result<Vec<IpAddr>, IpError> {
+    use std::net::ToSocketAddrs;
+
+    let addrs = (host, 0)
+        .to_socket_addrs()
+        .map_err(|e| err!(DnsError, "cannot resolve {}: {}", host, e))?;
+
+    let mut ips: Vec<IpAddr> = Vec::new();
+    for addr in addrs {
+        if !ips.contains(&addr.ip()) {
+            ips.push(addr.ip());
+        }
+    }
+
+    if ips.is_empty() {
+        return Err(err!(DnsError, "no address found for {}", host));
+    }
+
+    Ok(ips)
+}
+
+/// Re-key lookup results by parsed IP address.
+pub(crate) fn key_by_addr(details: HashMap<String, IpDetails>) -> HashMap<IpAddr, IpDetails> {
+    details
+        .into_iter()
+        .filter_map(|(ip, detail)| Some((ip.parse().ok()?, detail)))
+        .collect()
+}
+
+/// Parse a raw API response, returning an error if the API reported one.
+pub(crate) fn parse_response<T: DeserializeOwned>(raw_resp: &str) -> Result<T, IpError> {
+    // Parse the response directly so large responses are only parsed once
+    serde_json::from_str(raw_resp).map_err(|err| {
+        // Return if an error occurred
+        match serde_json::from_str::<serde_json::Value>(raw_resp) {
+            Ok(resp) if resp["error"].is_string() => {
+                err!(IpRequestError, resp["error"].as_str().unwrap()).with_body(raw_resp)
+            }
+            _ => err.into(),
+        }
+    })
+}
+
+/// Check that a token can be sent in a header.
+fn validate_token(token: &SecretToken) -> Result<(), IpError> {
+    HeaderValue::from_str(token.expose_secret())
+        .map(|_| ())
+        .map_err(|_| err!(InvalidConfigError, "token contains invalid characters"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::details;
+
+    #[test]
+    fn configured_token_takes_precedence_over_env() {
+        let context = Context::new(IpInfoConfig {
+            token: Some("token".into()),
+            token_from_env: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let request = context.get("https://ipinfo.io/8.8.8.8".to_owned());
+        assert_eq!(request.headers[AUTHORIZATION], "Bearer token");
+    }
+
+    #[test]
+    fn required_token_is_checked() {
+        let context = Context::new(IpInfoConfig {
+            require_token: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+        assert_eq!(
+            context.check_token().err().unwrap().kind(),
+            IpErrorKind::UnauthorizedError
+        );
+
+        let context = Context::new(IpInfoConfig {
+            token: Some("token".into()),
+            require_token: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+        assert!(context.check_token().is_ok());
+    }
+
+    #[test]
+    fn zero_cache_size_is_rejected() {
+        let result = Context::new(IpInfoConfig {
+            cache_size: 0,
+            ..Default::default()
+        });
+
+        assert_eq!(
+            result.err().unwrap().kind(),
+            IpErrorKind::InvalidConfigError
+        );
+    }
+
+    #[test]
+    fn missing_asset_file_is_rejected() {
+        let result = Context::new(IpInfoConfig {
+            countries_file_path: Some("/nonexistent/countries.json".to_owned()),
+            ..Default::default()
+        });
+
+        assert_eq!(result.err().unwrap().kind(), IpErrorKind::AssetLoadError);
+    }
+
+    #[test]
+    fn enrichment_can_be_disabled() {
+        let context = Context::new(IpInfoConfig {
+            enrich: false,
+            countries_file_path: Some("/nonexistent/countries.json".to_owned()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+
+        let details = context.process_single(&raw).expect("should process");
+
+        assert_eq!(details.country_name, None);
+        assert_eq!(details.is_eu, None);
+        assert_eq!(details.country_flag_url, None);
+    }
+
+    struct Tagger;
+
+    impl Enricher for Tagger {
+        fn enrich(&self, details: &mut IpDetails) {
+            let tag = format!("{:?}", details.country_name);
+            details.extra.insert("tag".to_owned(), tag.into());
+        }
+    }
+
+    #[test]
+    fn enrichers_are_called_after_country_data() {
+        let context = Context::new(IpInfoConfig {
+            countries: Some(HashMap::from([("US".to_owned(), "USA".to_owned())])),
+            enrichers: vec![Arc::new(Tagger)],
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+
+        context.process_single(&raw).expect("should process");
+
+        let cached = context.get_cached("8.8.8.8").unwrap();
+        assert_eq!(cached.extra["tag"], r#"Some("USA")"#);
+    }
+
+    #[test]
+    fn raw_json_is_kept_when_enabled() {
+        let raw = r#"{"ip": "8.8.8.8", "country": "US", "new_field": [1, 2]}"#;
+
+        let context = Context::new(Default::default()).expect("should construct");
+        assert_eq!(context.process_single(raw).unwrap().raw, None);
+
+        let context = Context::new(IpInfoConfig {
+            keep_raw: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+        let details = context.process_single(raw).expect("should process");
+        assert_eq!(details.raw, Some(serde_json::from_str(raw).unwrap()));
+
+        let batch = format!(r#"{{"8.8.8.8": {raw}}}"#);
+        let details = context.process_batch(&batch).expect("should process");
+        assert_eq!(details["8.8.8.8"].raw.as_ref().unwrap()["new_field"][1], 2);
+    }
+
+    #[test]
+    fn cidr_ranges_are_expanded() {
+        let ips = expand_cidr("203.0.113.5/30", 256).unwrap();
+        let expected: Vec<IpAddr> = ["203.0.113.4", "203.0.113.5", "203.0.113.6", "203.0.113.7"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        assert_eq!(ips, expected);
+
+        assert_eq!(expand_cidr("8.8.8.8/32", 1).unwrap().len(), 1);
+        assert_eq!(expand_cidr("2001:db8::/120", 256).unwrap().len(), 256);
+
+        for cidr in ["8.8.8.8", "8.8.8.8/33", "foo/24", "8.8.8.0/x"] {
+            let err = expand_cidr(cidr, 256).err().unwrap();
+            assert_eq!(err.kind(), IpErrorKind::InvalidIpError);
+        }
+        let err = expand_cidr("10.0.0.0/8", 256).err().unwrap();
+        assert!(err.to_string().contains("max_cidr_hosts"));
+        assert!(expand_cidr("::/0", usize::MAX).is_err());
+        assert!(expand_cidr("0.0.0.0/0", 256).is_err());
+    }
+
+    #[test]
+    fn whole_v4_range_is_counted_without_overflow() {
+        // The hosts of 0.0.0.0/0, without collecting its 2^32 addresses
+        let mut hosts = v4_hosts(0, 32);
+        assert_eq!(hosts.next(), Some("0.0.0.0".parse().unwrap()));
+        assert_eq!(hosts.next_back(), Some("255.255.255.255".parse().unwrap()));
+        assert_eq!(v4_hosts(0x08080800, 8).count(), 256);
+    }
+
+    #[test]
+    fn hosts_are_resolved() {
+        assert_eq!(
+            resolve_host("127.0.0.1").unwrap(),
+            ["127.0.0.1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve_host("::1").unwrap(),
+            ["::1".parse::<IpAddr>().unwrap()]
+        );
+        assert_eq!(
+            resolve_host("host.invalid").err().unwrap().kind(),
+            IpErrorKind::DnsError
+        );
+    }
+
+    #[test]
+    fn request_headers_are_canonical() {
+        let headers = Context::construct_headers(&Default::default()).unwrap();
+
+        assert_eq!(headers[USER_AGENT], format!("IPinfoClient/Rust/{VERSION}"));
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+        assert_eq!(headers[ACCEPT], "application/json");
+    }
+
+    #[test]
+    fn extra_headers_are_merged() {
+        let mut extra_headers = HeaderMap::new();
+        extra_headers.insert("x-api-gateway-key", HeaderValue::from_static("key"));
+        extra_headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        let context = Context::new(IpInfoConfig {
+            token: Some("token".into()),
+            extra_headers,
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let request = context.get("https://ipinfo.io/8.8.8.8".to_owned());
+        assert_eq!(request.headers["x-api-gateway-key"], "key");
+        assert_eq!(request.headers[ACCEPT], "*/*");
+        assert_eq!(request.headers[AUTHORIZATION], "Bearer token");
+        assert_eq!(request.headers[CONTENT_TYPE], "application/json");
+    }
+
+    #[test]
+    fn user_agent_suffix_is_appended() {
+        let config = IpInfoConfig {
+            user_agent_suffix: Some("myapp/2.1".to_owned()),
+            ..Default::default()
+        };
+        let headers = Context::construct_headers(&config).unwrap();
+        assert_eq!(
+            headers[USER_AGENT],
+            format!("IPinfoClient/Rust/{VERSION} myapp/2.1")
+        );
+
+        let config = IpInfoConfig {
+            user_agent_suffix: Some("my\napp".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Context::new(config).err().unwrap().kind(),
+            IpErrorKind::InvalidConfigError
+        );
+    }
+
+    #[test]
+    fn cache_hits_skip_misses() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::json!({ "8.8.8.8": details("8.8.8.8", "US") }).to_string();
+        context.process_batch(&raw).expect("should process");
+
+        let (hits, misses) = context.check_cache(&["8.8.8.8", "4.2.2.4"]);
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].ip, "8.8.8.8");
+        assert_eq!(misses, vec!["4.2.2.4"]);
+    }
+
+    #[test]
+    fn expired_entries_are_misses() {
+        let context = Context::new(IpInfoConfig {
+            cache_ttl: Some(Duration::ZERO),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+        context.process_single(&raw).expect("should process");
+
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert!(context.get_cached("8.8.8.8").is_none());
+    }
+
+    #[test]
+    fn custom_cache_is_used() {
+        let cache = Arc::new(MemoryCache::new(NonZeroUsize::new(10).unwrap()));
+        let context = Context::new(IpInfoConfig {
+            cache: Some(cache.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+
+        context.process_single(&raw).expect("should process");
+
+        assert!(cache.get("8.8.8.8").is_some());
+    }
+
+    #[test]
+    fn stale_entries_are_served_and_revalidated_once() {
+        let context = Context::new(IpInfoConfig {
+            cache_ttl: Some(Duration::ZERO),
+            stale_while_revalidate: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+        context.process_single(&raw).expect("should process");
+
+        std::thread::sleep(Duration::from_millis(1));
+
+        assert!(context.get_cached("8.8.8.8").is_some());
+        assert!(context.get_cached("8.8.8.8").is_some());
+        assert_eq!(context.take_stale(), vec!["8.8.8.8"]);
+        assert!(context.take_stale().is_empty());
+
+        context.finish_revalidation(&["8.8.8.8"]);
+        context.get_cached("8.8.8.8");
+        assert_eq!(context.take_stale(), vec!["8.8.8.8"]);
+    }
+
+    #[test]
+    fn stale_entries_are_served_when_unavailable() {
+        let context = Context::new(IpInfoConfig {
+            cache_ttl: Some(Duration::ZERO),
+            fallback_to_stale: true,
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+        context.process_single(&raw).expect("should process");
+
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(context.get_cached("8.8.8.8").is_none());
+
+        let unavailable = err!(CircuitOpenError);
+        let details = context
+            .stale_or("8.8.8.8", unavailable.clone())
+            .expect("should fall back");
+        assert!(details.stale);
+        assert!(context.stale_or("1.1.1.1", unavailable).is_err());
+        assert!(context.stale_or("8.8.8.8", err!(IpRequestError)).is_err());
+
+        let results = context.process_batch_each(&["8.8.8.8"], Err(err!(CircuitOpenError)));
+        assert!(results["8.8.8.8"].as_ref().unwrap().stale);
+    }
+
+    #[test]
+    fn cache_stats_count_hits_and_misses() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+        context.process_single(&raw).expect("should process");
+
+        context.check_cache(&["8.8.8.8", "1.1.1.1", "192.168.1.1"]);
+
+        let stats = context.cache_stats();
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.evictions, Some(0));
+        assert_eq!(stats.size, Some(1));
+    }
+
+    #[test]
+    fn bogons_are_answered_locally() {
+        let context = Context::new(Default::default()).expect("should construct");
+
+        let (hits, misses) = context.check_cache(&["192.168.1.1", "8.8.8.8"]);
+
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].bogon, Some(true));
+        assert_eq!(misses, vec!["8.8.8.8"]);
+    }
+
+    #[test]
+    fn cache_remove_and_clear_evict_entries() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+
+        context.process_single(&raw).expect("should process");
+        context.cache_remove("8.8.8.8");
+        assert!(context.get_cached("8.8.8.8").is_none());
+
+        context.process_single(&raw).expect("should process");
+        context.cache_clear();
+        assert!(context.get_cached("8.8.8.8").is_none());
+    }
+
+    #[test]
+    fn api_errors_are_negatively_cached() {
+        let context = Context::new(IpInfoConfig {
+            negative_cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::json!({ "8.8.8.8": { "error": "Not found" } }).to_string();
+
+        context.process_batch_each(&["8.8.8.8"], Ok(raw));
+
+        let e = context
+            .check_negative("8.8.8.8")
+            .expect_err("should be cached");
+        assert_eq!(e.kind(), IpErrorKind::IpRequestError);
+        assert!(context.check_negative("1.1.1.1").is_ok());
+
+        context.cache_remove("8.8.8.8");
+        assert!(context.check_negative("8.8.8.8").is_ok());
+    }
+
+    #[test]
+    fn batch_errors_are_negatively_cached_and_skipped() {
+        let context = Context::new(IpInfoConfig {
+            negative_cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let raw = serde_json::json!({
+            "8.8.8.8": { "error": "Not found" },
+            "1.1.1.1": { "ip": "1.1.1.1" },
+        })
+        .to_string();
+
+        let details = context.process_batch(&raw).expect("should parse");
+
+        assert_eq!(details.keys().collect::<Vec<_>>(), ["1.1.1.1"]);
+        assert!(context.check_negative("8.8.8.8").is_err());
+        assert_eq!(
+            context.check_ips(&["8.8.8.8", "1.1.1.1"]).unwrap(),
+            ["1.1.1.1"]
+        );
+    }
+
+    #[test]
+    fn invalid_ips_are_negatively_cached() {
+        let context = Context::new(IpInfoConfig {
+            negative_cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let e = context.check_ips(&["1.1.1.1", "foo"]).err().unwrap();
+        assert_eq!(e.kind(), IpErrorKind::InvalidIpError);
+        let e = context.check_negative("foo").expect_err("should be cached");
+        assert_eq!(e.kind(), IpErrorKind::InvalidIpError);
+        assert!(context.check_negative("1.1.1.1").is_ok());
+    }
+
+    #[test]
+    fn negative_caching_is_disabled_by_default() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::json!({ "8.8.8.8": { "error": "Not found" } }).to_string();
+
+        context.process_batch_each(&["8.8.8.8"], Ok(raw));
+
+        assert!(context.check_negative("8.8.8.8").is_ok());
+    }
+
+    #[test]
+    fn forked_context_shares_cache() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let fork = context.fork();
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+
+        fork.process_single(&raw).expect("should process");
+
+        assert!(context.cache.get("8.8.8.8").is_some());
+    }
+
+    #[test]
+    fn check_bogons_ignores_cache() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+        context.process_single(&raw).expect("should process");
+
+        let (bogons, misses) = context.check_bogons(&["192.168.1.1", "8.8.8.8"]);
+
+        assert_eq!(bogons.len(), 1);
+        assert_eq!(misses, vec!["8.8.8.8"]);
+    }
+
+    #[test]
+    fn process_single_enriches_and_caches() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::to_string(&details("8.8.8.8", "US")).unwrap();
+
+        let details = context.process_single(&raw).expect("should process");
+
+        assert_eq!(details.country, "US");
+        #[cfg(feature = "bundled-assets")]
+        assert_eq!(details.country_name, Some("United States".to_owned()));
+        assert!(context.get_cached("8.8.8.8").is_some());
+    }
+
+    #[test]
+    fn process_asn_caches_separately() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::json!({
+            "asn": "AS7922",
+            "name": "Comcast Cable Communications, LLC",
+            "country": "US",
+            "allocated": "1997-02-14",
+            "registry": "arin",
+            "num_ips": 71224576,
+            "prefixes": [{
+                "netblock": "24.0.0.0/12",
+                "id": "CABLE-1",
+                "name": "Comcast Cable Communications, LLC",
+                "country": "US"
+            }]
+        })
+        .to_string();
+
+        let details = context.process_asn(&raw).expect("should process");
+
+        assert_eq!(details.registry, Some("arin".to_owned()));
+        assert_eq!(details.prefixes.len(), 1);
+        assert!(details.prefixes6.is_empty());
+        assert!(context.get_cached_asn("AS7922").is_some());
+        assert!(context.get_cached("AS7922").is_none());
+    }
+
+    #[test]
+    fn process_asn_accepts_bare_prefixes() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::json!({
+            "asn": "AS7922",
+            "name": "Comcast Cable Communications, LLC",
+            "country": "US",
+            "prefixes": [{ "netblock": "24.0.0.0/12" }]
+        })
+        .to_string();
+
+        let details = context.process_asn(&raw).expect("should process");
+
+        assert_eq!(details.prefixes[0].netblock, "24.0.0.0/12");
+        assert!(details.prefixes[0].id.is_empty());
+    }
+
+    #[test]
+    fn normalize_asn_accepts_as_numbers() {
+        assert_eq!(normalize_asn("AS7922").unwrap(), "AS7922");
+        assert_eq!(normalize_asn("as7922").unwrap(), "AS7922");
+        assert_eq!(normalize_asn("7922").unwrap(), "AS7922");
+        for asn in ["", "AS", "8.8.8.8", "../me", "AS7922?token=x", "ASé1"] {
+            let err = normalize_asn(asn).expect_err(asn);
+            assert_eq!(err.kind(), IpErrorKind::ParseError);
+        }
+    }
+
+    #[test]
+    fn ranges_num_ranges_accepts_string() {
+        let raw = r#"{"domain": "comcast.net", "num_ranges": "2", "ranges": ["23.24.240.0/29", "23.24.240.64/29"]}"#;
+
+        let ranges: crate::RangesResponse = parse_response(raw).expect("should parse");
+
+        assert_eq!(ranges.num_ranges, 2);
+        assert_eq!(ranges.ranges.len(), 2);
+    }
+
+    #[test]
+    fn results_are_keyed_by_addr() {
+        let details = HashMap::from([
+            (
+                "2001:4860:4860:0:0:0:0:8888".to_owned(),
+                details("8.8.8.8", "US"),
+            ),
+            ("8.8.8.8".to_owned(), details("8.8.8.8", "US")),
+        ]);
+
+        let details = key_by_addr(details);
+
+        assert!(details.contains_key(&"2001:4860:4860::8888".parse::<IpAddr>().unwrap()));
+        assert!(details.contains_key(&"8.8.8.8".parse::<IpAddr>().unwrap()));
+    }
+
+    #[test]
+    fn invalid_ips_are_rejected() {
+        assert!(validate_ips(&["8.8.8.8", "2001:4860:4860::8888"]).is_ok());
+
+        let err = validate_ips(&["8.8.8.8", "8.8.8"]).err().unwrap();
+
+        assert_eq!(err.kind(), crate::IpErrorKind::InvalidIpError);
+        assert_eq!(err.to_string(), "invalid IP address: \"8.8.8\"");
+    }
+
+    #[test]
+    fn batch_errors_are_reported_per_ip() {
+        let context = Context::new(Default::default()).expect("should construct");
+        let raw = serde_json::json!({
+            "8.8.8.8": details("8.8.8.8", "US"),
+            "1.1.1.1": { "error": "Unknown error" },
+        })
+        .to_string();
+
+        let details = context.process_batch_each(&["8.8.8.8", "1.1.1.1", "4.2.2.4"], Ok(raw));
+
+        assert!(details["8.8.8.8"].is_ok());
+        assert_eq!(
+            details["1.1.1.1"].as_ref().err().unwrap().kind(),
+            crate::IpErrorKind::IpRequestError
+        );
+        assert!(details["4.2.2.4"].is_err());
+    }
+
+    #[test]
+    fn batch_request_error_is_reported_for_chunk() {
+        let context = Context::new(Default::default()).expect("should construct");
+
+        let details =
+            context.process_batch_each(&["8.8.8.8", "1.1.1.1"], Err(err!(RateLimitExceededError)));
+
+        assert_eq!(details.len(), 2);
+        assert_eq!(
+            details["1.1.1.1"].as_ref().err().unwrap().kind(),
+            crate::IpErrorKind::RateLimitExceededError
+        );
+    }
+
+    #[test]
+    fn api_error_is_reported() {
+        let context = Context::new(Default::default()).expect("should construct");
+
+        let err = context
+            .process_single(r#"{"error": "Please provide a valid IP address"}"#)
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), crate::IpErrorKind::IpRequestError);
+        assert_eq!(
+            err.body(),
+            Some(r#"{"error": "Please provide a valid IP address"}"#)
+        );
+    }
+}
@@ -0,0 +1,571 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Country data used to enrich IP details, usable without a client.
+
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{Continent, CountryCurrency, CountryFlag, IpDetails, IpError, IpInfoConfig};
+
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "bundled-assets")]
+use include_dir::{include_dir, Dir};
+#[cfg(feature = "bundled-assets")]
+static ASSETS_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/assets");
+
+/// A bundled asset, parsed once and shared by every client.
+type Bundled<T> = OnceLock<Result<Arc<T>, IpError>>;
+
+static COUNTRIES: Bundled<HashMap<String, String>> = OnceLock::new();
+static EU: Bundled<Vec<String>> = OnceLock::new();
+static COUNTRY_FLAGS: Bundled<HashMap<String, CountryFlag>> = OnceLock::new();
+static COUNTRY_CURRENCIES: Bundled<HashMap<String, CountryCurrency>> = OnceLock::new();
+static CONTINENTS: Bundled<HashMap<String, Continent>> = OnceLock::new();
+
+/// The base URL of the SVG country flag images.
+const COUNTRY_FLAGS_URL: &str = "https://cdn.ipinfo.io/static/images/countries-flags";
+
+/// Country names, EU members, flags, currencies and continents, keyed by country code.
+///
+/// Clients load the country data from their configuration, but it can be loaded on its own to
+/// enrich country codes offline, without an HTTP client or a token. Cloning is cheap, and the
+/// bundled data is parsed once and then shared.
+#[derive(Clone)]
+pub struct CountryData {
+    countries: Arc<HashMap<String, String>>,
+    translated_countries: Arc<HashMap<String, String>>,
+    eu: Arc<Vec<String>>,
+    country_flags: Arc<HashMap<String, CountryFlag>>,
+    country_currencies: Arc<HashMap<String, CountryCurrency>>,
+    continents: Arc<HashMap<String, Continent>>,
+    paths: Arc<AssetPaths>,
+}
+
+/// The user-provided file paths of the country data, read again by [`CountryData::reload`].
+#[derive(Default)]
+struct AssetPaths {
+    countries: Option<String>,
+    eu: Option<String>,
+    country_flags: Option<String>,
+    country_currencies: Option<String>,
+    continents: Option<String>,
+}
+
+impl CountryData {
+    /// Load the bundled country data, with English country names.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::CountryData;
+    ///
+    /// let countries = CountryData::new().expect("should load");
+    /// # #[cfg(feature = "bundled-assets")]
+    /// assert_eq!(countries.country_name("DE"), Some("Germany"));
+    /// ```
+    pub fn new() -> Result<Self, IpError> {
+        Self::from_config(&IpInfoConfig::default())
+    }
+
+    /// Construct country data without any country.
+    pub(crate) fn empty() -> Self {
+        Self {
+            countries: Default::default(),
+            translated_countries: Default::default(),
+            eu: Default::default(),
+            country_flags: Default::default(),
+            country_currencies: Default::default(),
+            continents: Default::default(),
+            paths: Default::default(),
+        }
+    }
+
+    /// Load the country data set by the `*_file_path`, in-memory data and `locale` options of a
+    /// client configuration, using the bundled data for the rest.
+    ///
+    /// Fails with an `AssetLoadError` if a file can't be loaded, or an `InvalidConfigError` if
+    /// there are no country names for the locale.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{CountryData, IpInfoConfig};
+    ///
+    /// let config = IpInfoConfig {
+    ///     countries_file_path: Some("countries.json".to_owned()),
+    ///     ..Default::default()
+    /// };
+    /// let countries = CountryData::from_config(&config).expect("should load");
+    /// ```
+    pub fn from_config(config: &IpInfoConfig) -> Result<Self, IpError> {
+        let translated_countries = match config.locale.as_deref() {
+            None | Some("en") => HashMap::new(),
+            Some(locale) => config
+                .country_translations
+                .get(locale)
+                .cloned()
+                .ok_or_else(|| {
+                    err!(InvalidConfigError, "no country names for locale {}", locale)
+                })?,
+        };
+
+        // In-memory data replaces the files for good
+        let paths = AssetPaths {
+            countries: file_path(&config.countries, &config.countries_file_path),
+            eu: file_path(&config.eu, &config.eu_file_path),
+            country_flags: file_path(&config.country_flags, &config.country_flags_file_path),
+            country_currencies: file_path(
+                &config.country_currencies,
+                &config.country_currencies_file_path,
+            ),
+            continents: file_path(&config.continents, &config.continents_file_path),
+        };
+
+        Ok(Self {
+            countries: load_asset(
+                &config.countries,
+                &config.countries_file_path,
+                "countries.json",
+                &COUNTRIES,
+            )?,
+            translated_countries: Arc::new(translated_countries),
+            eu: load_asset(&config.eu, &config.eu_file_path, "eu.json", &EU)?,
+            country_flags: load_asset(
+                &config.country_flags,
+                &config.country_flags_file_path,
+                "flags.json",
+                &COUNTRY_FLAGS,
+            )?,
+            country_currencies: load_asset(
+                &config.country_currencies,
+                &config.country_currencies_file_path,
+                "currency.json",
+                &COUNTRY_CURRENCIES,
+            )?,
+            continents: load_asset(
+                &config.continents,
+                &config.continents_file_path,
+                "continent.json",
+                &CONTINENTS,
+            )?,
+            paths: Arc::new(paths),
+        })
+    }
+
+    /// Read the user-provided country data files again, keeping the other country data.
+    ///
+    /// Fails with an `AssetLoadError` if any file can't be loaded.
+    pub fn reload(&self) -> Result<Self, IpError> {
+        let paths = &self.paths;
+
+        Ok(Self {
+            countries: reload_asset(&paths.countries, &self.countries)?,
+            translated_countries: self.translated_countries.clone(),
+            eu: reload_asset(&paths.eu, &self.eu)?,
+            country_flags: reload_asset(&paths.country_flags, &self.country_flags)?,
+            country_currencies: reload_asset(&paths.country_currencies, &self.country_currencies)?,
+            continents: reload_asset(&paths.continents, &self.continents)?,
+            paths: self.paths.clone(),
+        })
+    }
+
+    /// Get the name of a country, in the configured locale.
+    pub fn country_name(&self, code: &str) -> Option<&str> {
+        let code = code.to_ascii_uppercase();
+        self.translated_countries
+            .get(&code)
+            .or_else(|| self.countries.get(&code))
+            .map(String::as_str)
+    }
+
+    /// Check whether a country is a member of the EU.
+    pub fn is_eu(&self, code: &str) -> bool {
+        self.eu.contains(&code.to_ascii_uppercase())
+    }
+
+    /// Get the flag of a country.
+    pub fn country_flag(&self, code: &str) -> Option<&CountryFlag> {
+        self.country_flags.get(&code.to_ascii_uppercase())
+    }
+
+    /// Get the currency of a country.
+    pub fn country_currency(&self, code: &str) -> Option<&CountryCurrency> {
+        self.country_currencies.get(&code.to_ascii_uppercase())
+    }
+
+    /// Get the continent of a country.
+    pub fn continent(&self, code: &str) -> Option<&Continent> {
+        self.continents.get(&code.to_ascii_uppercase())
+    }
+
+    /// Add the country name, EU status, flag, currency and continent to the details.
+    ///
+    /// Country data missing for the country code is left as `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::{CountryData, IpDetails};
+    ///
+    /// let countries = CountryData::new().expect("should load");
+    /// let mut details: IpDetails =
+    ///     serde_json::from_str(r#"{"ip": "8.8.8.8", "country": "US"}"#).unwrap();
+    /// countries.enrich(&mut details);
+    /// ```
+    pub fn enrich(&self, details: &mut IpDetails) {
+        let country = &details.country;
+        if !country.is_empty() {
+            details.country_name = self.country_name(country).map(str::to_owned);
+            details.is_eu = Some(self.is_eu(country));
+            details.country_flag = self.country_flag(country).cloned();
+            details.country_flag_url = Some(format!("{COUNTRY_FLAGS_URL}/{country}.svg"));
+            details.country_currency = self.country_currency(country).cloned();
+            details.continent = self.continent(country).cloned();
+        }
+    }
+}
+
+/// Use the user-provided asset data, or load the JSON asset from the user-provided path or
+/// from the bundled assets.
+///
+/// Bundled assets are parsed on first use only, then shared. Without the `bundled-assets`
+/// feature, assets without a path are left empty, so their enrichment is skipped.
+fn load_asset<T: AssetData>(
+    data: &Option<T>,
+    path: &Option<String>,
+    name: &str,
+    bundled: &'static Bundled<T>,
+) -> Result<Arc<T>, IpError> {
+    if let Some(data) = data {
+        return Ok(Arc::new(data.clone()));
+    }
+
+    match path {
+        Some(path) => load_file(path).map(Arc::new),
+        None => bundled
+            .get_or_init(|| parse_bundled(name).map(Arc::new))
+            .clone(),
+    }
+}
+
+/// Load a JSON asset from its user-provided path again, or keep the current one.
+fn reload_asset<T: AssetData>(path: &Option<String>, current: &Arc<T>) -> Result<Arc<T>, IpError> {
+    match path {
+        Some(path) => load_file(path).map(Arc::new),
+        None => Ok(current.clone()),
+    }
+}
+
+/// Get the file path an asset is loaded from, unless its data is provided in memory.
+fn file_path<T>(data: &Option<T>, path: &Option<String>) -> Option<String> {
+    match data {
+        Some(_) => None,
+        None => path.clone(),
+    }
+}
+
+/// Load a JSON asset from a user-provided file.
+fn load_file<T: AssetData>(path: &str) -> Result<T, IpError> {
+    let t_file =
+        fs::File::open(path).map_err(|e| err!(AssetLoadError, "cannot open {}: {}", path, e))?;
+    let value = serde_json::from_reader(t_file)
+        .map_err(|e| err!(AssetLoadError, "cannot parse {}: {}", path, e))?;
+
+    T::from_json(value).map_err(|e| err!(AssetLoadError, "invalid {}: {}", path, e))
+}
+
+/// Parse a JSON asset bundled with the crate.
+#[cfg(feature = "bundled-assets")]
+fn parse_bundled<T: AssetData>(name: &str) -> Result<T, IpError> {
+    let t_file = ASSETS_DIR
+        .get_file(name)
+        .ok_or_else(|| err!(AssetLoadError, "missing bundled {}", name))?;
+    let contents = t_file
+        .contents_utf8()
+        .ok_or_else(|| err!(AssetLoadError, "bundled {} is not UTF-8", name))?;
+    let value = serde_json::from_str(contents)
+        .map_err(|e| err!(AssetLoadError, "cannot parse bundled {}: {}", name, e))?;
+
+    T::from_json(value).map_err(|e| err!(AssetLoadError, "invalid bundled {}: {}", name, e))
+}
+
+/// Use empty data in place of the bundled assets, which are excluded.
+#[cfg(not(feature = "bundled-assets"))]
+fn parse_bundled<T: AssetData>(_name: &str) -> Result<T, IpError> {
+    Ok(T::default())
+}
+
+/// Country data parsed from a JSON asset.
+trait AssetData: Default + Clone {
+    /// Parse the data, describing the first entry that doesn't have the expected shape.
+    fn from_json(value: serde_json::Value) -> Result<Self, String>;
+}
+
+/// Country data keyed by country code.
+impl<V: DeserializeOwned + Clone> AssetData for HashMap<String, V> {
+    fn from_json(value: serde_json::Value) -> Result<Self, String> {
+        let serde_json::Value::Object(entries) = value else {
+            return Err(format!(
+                "expected an object keyed by country code, found {}",
+                json_type(&value)
+            ));
+        };
+
+        entries
+            .into_iter()
+            .map(|(key, value)| match serde_json::from_value(value) {
+                Ok(value) => Ok((key, value)),
+                Err(e) => Err(format!("key \"{}\": {}", key, e)),
+            })
+            .collect()
+    }
+}
+
+/// A list of country codes.
+impl AssetData for Vec<String> {
+    fn from_json(value: serde_json::Value) -> Result<Self, String> {
+        let serde_json::Value::Array(entries) = value else {
+            return Err(format!(
+                "expected an array of country codes, found {}",
+                json_type(&value)
+            ));
+        };
+
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| match value {
+                serde_json::Value::String(code) => Ok(code),
+                value => Err(format!(
+                    "index {}: expected a country code string, found {}",
+                    index,
+                    json_type(&value)
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Describe the type of a JSON value.
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{testing::details, IpErrorKind};
+
+    #[test]
+    fn enrich_tolerates_unknown_country() {
+        let countries = CountryData::new().expect("should load");
+        let mut details = details("8.8.8.8", "ZZ");
+
+        countries.enrich(&mut details);
+
+        assert_eq!(details.country_name, None);
+        assert_eq!(details.is_eu, Some(false));
+        assert!(details.country_flag.is_none());
+        assert!(details.continent.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "bundled-assets")]
+    fn enrich_adds_country_data() {
+        let countries = CountryData::new().expect("should load");
+        let mut details = details("8.8.8.8", "DE");
+
+        countries.enrich(&mut details);
+
+        assert_eq!(details.country_name, Some("Germany".to_owned()));
+        assert_eq!(details.is_eu, Some(true));
+        assert_eq!(
+            details.continent,
+            Some(Continent {
+                code: "EU".to_owned(),
+                name: "Europe".to_owned()
+            })
+        );
+        assert_eq!(details.country_currency.unwrap().code, "EUR");
+        assert_eq!(details.country_flag.unwrap().emoji, "🇩🇪");
+    }
+
+    #[test]
+    #[cfg(not(feature = "bundled-assets"))]
+    fn enrich_is_skipped_without_bundled_assets() {
+        let countries = CountryData::new().expect("should load");
+        let mut details = details("8.8.8.8", "DE");
+
+        countries.enrich(&mut details);
+
+        assert_eq!(details.country_name, None);
+        assert!(details.country_flag.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "bundled-assets")]
+    fn country_data_is_looked_up_by_code() {
+        let countries = CountryData::new().expect("should load");
+
+        assert_eq!(countries.country_name("de"), Some("Germany"));
+        assert!(countries.is_eu("DE"));
+        assert!(!countries.is_eu("US"));
+        assert_eq!(countries.country_currency("US").unwrap().code, "USD");
+        assert_eq!(countries.continent("US").unwrap().code, "NA");
+        assert_eq!(countries.country_flag("XX"), None);
+    }
+
+    #[test]
+    fn bundled_assets_are_shared() {
+        let a = CountryData::new().expect("should load");
+        let b = CountryData::new().expect("should load");
+
+        assert!(Arc::ptr_eq(&a.countries, &b.countries));
+        assert!(Arc::ptr_eq(&a.continents, &b.continents));
+    }
+
+    #[test]
+    fn in_memory_assets_are_used() {
+        let countries = CountryData::from_config(&IpInfoConfig {
+            countries: Some(HashMap::from([("DE".to_owned(), "Deutschland".to_owned())])),
+            eu: Some(vec![]),
+            countries_file_path: Some("/nonexistent/countries.json".to_owned()),
+            ..Default::default()
+        })
+        .expect("should load");
+        let mut details = details("8.8.8.8", "DE");
+
+        countries.enrich(&mut details);
+
+        assert_eq!(details.country_name, Some("Deutschland".to_owned()));
+        assert_eq!(details.is_eu, Some(false));
+        assert_eq!(
+            details.country_flag_url.as_deref(),
+            Some("https://cdn.ipinfo.io/static/images/countries-flags/DE.svg")
+        );
+    }
+
+    #[test]
+    fn asset_files_are_reloaded() {
+        let path =
+            std::env::temp_dir().join(format!("ipinfo-countries-{}.json", std::process::id()));
+        fs::write(&path, r#"{"DE": "Germany"}"#).unwrap();
+        let countries = CountryData::from_config(&IpInfoConfig {
+            countries_file_path: Some(path.to_str().unwrap().to_owned()),
+            ..Default::default()
+        })
+        .expect("should load");
+
+        fs::write(&path, r#"{"DE": "Deutschland"}"#).unwrap();
+        let reloaded = countries.reload().expect("should reload");
+        assert_eq!(reloaded.country_name("DE"), Some("Deutschland"));
+        assert_eq!(countries.country_name("DE"), Some("Germany"));
+
+        fs::write(&path, "[]").unwrap();
+        let err = reloaded.reload().err().unwrap();
+        assert_eq!(err.kind(), IpErrorKind::AssetLoadError);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_asset_files_are_described() {
+        let dir = std::env::temp_dir();
+        let invalid = |name: &str, contents: &str, config: fn(String) -> IpInfoConfig| {
+            let path = dir.join(format!("ipinfo-{}-{}.json", std::process::id(), name));
+            fs::write(&path, contents).unwrap();
+            let err = CountryData::from_config(&config(path.to_str().unwrap().to_owned()))
+                .err()
+                .unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(err.kind(), IpErrorKind::AssetLoadError);
+            err.to_string()
+        };
+
+        let err = invalid("flags", r#"{"DE": {"emoji": "🇩🇪"}}"#, |path| {
+            IpInfoConfig {
+                country_flags_file_path: Some(path),
+                ..Default::default()
+            }
+        });
+        assert!(err.contains("flags.json"), "{}", err);
+        assert!(
+            err.contains(r#"key "DE": missing field `unicode`"#),
+            "{}",
+            err
+        );
+
+        let err = invalid("eu", r#"{"DE": true}"#, |path| IpInfoConfig {
+            eu_file_path: Some(path),
+            ..Default::default()
+        });
+        assert!(
+            err.contains("expected an array of country codes, found an object"),
+            "{}",
+            err
+        );
+
+        let err = invalid("countries", r#"{"DE": 1}"#, |path| IpInfoConfig {
+            countries_file_path: Some(path),
+            ..Default::default()
+        });
+        assert!(
+            err.contains(r#"key "DE": invalid type: integer `1`"#),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn country_names_are_localized() {
+        let countries = CountryData::from_config(&IpInfoConfig {
+            countries: Some(HashMap::from([
+                ("DE".to_owned(), "Germany".to_owned()),
+                ("FR".to_owned(), "France".to_owned()),
+            ])),
+            locale: Some("de".to_owned()),
+            country_translations: HashMap::from([(
+                "de".to_owned(),
+                HashMap::from([("DE".to_owned(), "Deutschland".to_owned())]),
+            )]),
+            ..Default::default()
+        })
+        .expect("should load");
+
+        assert_eq!(countries.country_name("DE"), Some("Deutschland"));
+        assert_eq!(countries.country_name("FR"), Some("France"));
+
+        let result = CountryData::from_config(&IpInfoConfig {
+            locale: Some("fr".to_owned()),
+            ..Default::default()
+        });
+        assert_eq!(
+            result.err().unwrap().kind(),
+            IpErrorKind::InvalidConfigError
+        );
+    }
+}
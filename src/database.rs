@@ -0,0 +1,488 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Download and periodic refresh of the IPinfo database snapshots.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{client, context::Context, IpError, IpInfoConfig};
+
+use serde::Deserialize;
+
+/// A downloadable IPinfo database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseKind {
+    /// The free IPinfo Lite database, with the country and ASN of IP addresses.
+    Lite,
+
+    /// The free country database.
+    Country,
+
+    /// The free country and ASN database.
+    CountryAsn,
+
+    /// The free ASN database.
+    Asn,
+
+    /// Another database of the account, by file name. (i.e., standard_location.mmdb)
+    Other(String),
+}
+
+impl DatabaseKind {
+    /// Get the path of the database in the data API.
+    pub fn path(&self) -> &str {
+        match self {
+            DatabaseKind::Lite => "ipinfo_lite.mmdb",
+            DatabaseKind::Country => "free/country.mmdb",
+            DatabaseKind::CountryAsn => "free/country_asn.mmdb",
+            DatabaseKind::Asn => "free/asn.mmdb",
+            DatabaseKind::Other(name) => name,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChecksumsResponse {
+    checksums: Checksums,
+}
+
+#[derive(Deserialize)]
+struct Checksums {
+    sha256: String,
+}
+
+/// Downloads the latest snapshots of the IPinfo databases with the account token.
+///
+/// Downloads are verified against the SHA-256 checksum published by IPinfo, then atomically
+/// swapped into place, so readers never see a partial file. The `token`, `base_url`, proxy and
+/// TLS settings of the configuration are used, and its `timeout` applies to whole downloads, so
+/// it should be raised for large databases.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use ipinfo::{Database, DatabaseKind, DatabaseManager, IpInfoConfig};
+///
+/// let manager = DatabaseManager::new(IpInfoConfig {
+///     token: Some("my token".into()),
+///     timeout: Duration::from_secs(300),
+///     ..Default::default()
+/// })
+/// .expect("should construct");
+///
+/// manager
+///     .download(&DatabaseKind::CountryAsn, "country_asn.mmdb")
+///     .expect("should download");
+/// let db = Database::open("country_asn.mmdb").expect("should open");
+/// ```
+#[derive(Clone)]
+pub struct DatabaseManager {
+    client: reqwest::blocking::Client,
+    context: Arc<Context>,
+}
+
+impl DatabaseManager {
+    /// Construct a new database manager.
+    pub fn new(config: IpInfoConfig) -> Result<Self, IpError> {
+        let client = client::configure(reqwest::blocking::Client::builder(), &config)?.build()?;
+        let context = Context::new(IpInfoConfig {
+            enrich: false,
+            ..config
+        })?;
+
+        Ok(Self {
+            client,
+            context: Arc::new(context),
+        })
+    }
+
+    /// Get the SHA-256 checksum of the latest snapshot of a database, in lowercase hex.
+    ///
+    /// Fails with an `UnauthorizedError` if no token is configured.
+    pub fn checksum(&self, kind: &DatabaseKind) -> Result<String, IpError> {
+        let body = self.fetch(&format!("{}/checksums", self.url(kind)))?;
+        let response: ChecksumsResponse = serde_json::from_slice(&body)?;
+
+        Ok(response.checksums.sha256.to_lowercase())
+    }
+
+    /// Download the latest snapshot of a database to a file, replacing it atomically.
+    ///
+    /// The snapshot is written next to the file before being renamed to it. Fails with a
+    /// `DatabaseError` if the download doesn't match its checksum.
+    pub fn download(&self, kind: &DatabaseKind, dest: impl AsRef<Path>) -> Result<(), IpError> {
+        let checksum = self.checksum(kind)?;
+        self.download_verified(kind, dest.as_ref(), &checksum)
+    }
+
+    /// Download the latest snapshot of a database to a file, unless the file already matches it.
+    ///
+    /// Returns whether the file was replaced.
+    pub fn update(&self, kind: &DatabaseKind, dest: impl AsRef<Path>) -> Result<bool, IpError> {
+        let dest = dest.as_ref();
+        let checksum = self.checksum(kind)?;
+        if fs::read(dest).is_ok_and(|current| sha256_hex(&current) == checksum) {
+            return Ok(false);
+        }
+
+        self.download_verified(kind, dest, &checksum)?;
+        Ok(true)
+    }
+
+    /// Update a database file now and then every `interval` in a background thread, calling
+    /// `on_update` whenever the file is replaced, such as to reopen the database.
+    ///
+    /// The thread stops once the returned [`Refresher`] is dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use ipinfo::{DatabaseKind, DatabaseManager, IpInfoConfig};
+    ///
+    /// let manager = DatabaseManager::new(IpInfoConfig {
+    ///     token: Some("my token".into()),
+    ///     ..Default::default()
+    /// })
+    /// .expect("should construct");
+    ///
+    /// let refresher = manager.refresh_every(
+    ///     DatabaseKind::Lite,
+    ///     "ipinfo_lite.mmdb",
+    ///     Duration::from_secs(24 * 3600),
+    ///     |path| println!("{} was updated", path.display()),
+    /// );
+    /// ```
+    pub fn refresh_every(
+        &self,
+        kind: DatabaseKind,
+        dest: impl Into<PathBuf>,
+        interval: Duration,
+        mut on_update: impl FnMut(&Path) + Send + 'static,
+    ) -> Refresher {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let last_error = Arc::new(Mutex::new(None));
+        let manager = self.clone();
+        let dest = dest.into();
+
+        let error = last_error.clone();
+        thread::spawn(move || loop {
+            match manager.update(&kind, &dest) {
+                Ok(updated) => {
+                    *error.lock().unwrap() = None;
+                    if updated {
+                        on_update(&dest);
+                    }
+                }
+                Err(e) => {
+                    trace_event!(warn, error = %e, "database refresh failed");
+                    *error.lock().unwrap() = Some(e);
+                }
+            }
+
+            if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                break;
+            }
+        });
+
+        Refresher {
+            _stop: stop,
+            last_error,
+        }
+    }
+
+    fn url(&self, kind: &DatabaseKind) -> String {
+        format!("{}/data/{}", self.context.url, kind.path())
+    }
+
+    fn download_verified(
+        &self,
+        kind: &DatabaseKind,
+        dest: &Path,
+        checksum: &str,
+    ) -> Result<(), IpError> {
+        let body = self.fetch(&self.url(kind))?;
+        if sha256_hex(&body) != checksum {
+            return Err(err!(DatabaseError, "checksum mismatch for {}", kind.path()));
+        }
+
+        let mut tmp = dest.as_os_str().to_owned();
+        tmp.push(".download");
+        let write =
+            |e: std::io::Error| err!(DatabaseError, "cannot write {}: {}", dest.display(), e);
+        fs::write(&tmp, &body).map_err(write)?;
+        fs::rename(&tmp, dest).map_err(|e| {
+            let _ = fs::remove_file(&tmp);
+            write(e)
+        })
+    }
+
+    /// Get the body of an authenticated request.
+    fn fetch(&self, url: &str) -> Result<Vec<u8>, IpError> {
+        let request = self.context.get_authenticated(url.to_owned())?;
+        let response = self
+            .client
+            .request(request.method, url)
+            .headers(request.headers)
+            .send()?;
+        if !response.status().is_success() {
+            return Err(IpError::from_status(response.status(), url));
+        }
+
+        Ok(response.bytes()?.to_vec())
+    }
+}
+
+/// Handle of the background refresh of a database file, stopping it when dropped.
+pub struct Refresher {
+    _stop: mpsc::Sender<()>,
+    last_error: Arc<Mutex<Option<IpError>>>,
+}
+
+impl Refresher {
+    /// Get the error of the last refresh, if it failed.
+    pub fn last_error(&self) -> Option<IpError> {
+        self.last_error.lock().unwrap().clone()
+    }
+}
+
+/// Get the SHA-256 digest of data in lowercase hex.
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in sha256(data) {
+        write!(hex, "{byte:02x}").unwrap();
+    }
+    hex
+}
+
+/// Get the SHA-256 digest of data.
+fn sha256(data: &[u8]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    // Pad with a one bit, zeros and the length in bits to a multiple of 64 bytes
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend(((data.len() as u64).wrapping_mul(8)).to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+
+        for (state, value) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0; 32];
+    for (bytes, word) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use crate::IpErrorKind;
+
+    #[test]
+    fn sha256_matches_test_vectors() {
+        assert_eq!(
+            sha256_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            sha256_hex(&[b'a'; 1000]),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    /// Serve the data API on a local port, returning its base URL.
+    fn serve(database: &'static [u8], checksum: String, requests: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for _ in 0..requests {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 4096];
+                let len = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..len]);
+
+                let body = if request.starts_with("GET /data/free/country.mmdb/checksums ") {
+                    format!(r#"{{"checksums": {{"sha256": "{checksum}"}}}}"#).into_bytes()
+                } else {
+                    database.to_vec()
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        url
+    }
+
+    fn manager(base_url: String) -> DatabaseManager {
+        DatabaseManager::new(IpInfoConfig {
+            token: Some("token".into()),
+            base_url,
+            ..Default::default()
+        })
+        .expect("should construct")
+    }
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ipinfo-{}-{}.mmdb", name, std::process::id()))
+    }
+
+    #[test]
+    fn databases_are_downloaded_and_verified() {
+        let path = path("download");
+        let manager = manager(serve(b"database", sha256_hex(b"database"), 4));
+
+        manager
+            .download(&DatabaseKind::Country, &path)
+            .expect("should download");
+        assert_eq!(fs::read(&path).unwrap(), b"database");
+        assert!(!manager
+            .update(&DatabaseKind::Country, &path)
+            .expect("should update"));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_downloads_are_rejected() {
+        let path = path("corrupted");
+        fs::write(&path, b"previous").unwrap();
+        let manager = manager(serve(b"truncated", sha256_hex(b"database"), 2));
+
+        let err = manager
+            .download(&DatabaseKind::Country, &path)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), IpErrorKind::DatabaseError);
+        assert_eq!(fs::read(&path).unwrap(), b"previous");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn downloads_require_a_token() {
+        let manager = DatabaseManager::new(Default::default()).expect("should construct");
+
+        let err = manager.checksum(&DatabaseKind::Lite).err().unwrap();
+        assert_eq!(err.kind(), IpErrorKind::UnauthorizedError);
+    }
+
+    #[test]
+    fn databases_are_refreshed_in_background() {
+        let path = path("refresh");
+        let manager = manager(serve(b"database", sha256_hex(b"database"), 2));
+        let (updated, updates) = mpsc::channel();
+
+        let refresher = manager.refresh_every(
+            DatabaseKind::Country,
+            &path,
+            Duration::from_secs(3600),
+            move |path| updated.send(path.to_owned()).unwrap(),
+        );
+
+        let updated = updates.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(updated, path);
+        assert!(refresher.last_error().is_none());
+        drop(refresher);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Custom enrichment of IP details.
+
+use crate::IpDetails;
+
+/// Custom enrichment of the IP details parsed from API responses, such as internal
+/// organization names or threat intelligence tags.
+///
+/// Enrichers are called in order after the built-in country data enrichment, before the
+/// details are cached. Bogon details, answered locally, are not enriched.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use ipinfo::{Enricher, IpDetails, IpInfo, IpInfoConfig};
+///
+/// struct Datacenters;
+///
+/// impl Enricher for Datacenters {
+///     fn enrich(&self, details: &mut IpDetails) {
+///         if details.org.as_deref() == Some("AS15169 Google LLC") {
+///             details.extra.insert("datacenter".to_owned(), true.into());
+///         }
+///     }
+/// }
+///
+/// let ipinfo = IpInfo::new(IpInfoConfig {
+///     enrichers: vec![Arc::new(Datacenters)],
+///     ..Default::default()
+/// })
+/// .expect("should construct");
+/// ```
+pub trait Enricher: Send + Sync {
+    /// Add to or modify the details of an IP address.
+    fn enrich(&self, details: &mut IpDetails);
+}
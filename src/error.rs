@@ -14,7 +14,9 @@
 
 //! IPinfo error type and kinds.
 
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, time::Duration};
+
+use reqwest::StatusCode;
 
 /// Create a new error (of a given kind) with a formatted message
 ///
@@ -34,7 +36,7 @@ macro_rules! err {
         $crate::IpError::new($crate::IpErrorKind::$kind, Some($msg))
     };
     ($kind:ident, $fmt:expr, $($arg:tt)+) => {
-        err!($crate::IpErrorKind::$kind, &format!($fmt, $($arg)+))
+        $crate::IpError::new($crate::IpErrorKind::$kind, Some(&format!($fmt, $($arg)+)))
     };
 }
 
@@ -52,6 +54,36 @@ pub enum IpErrorKind {
 
     /// Parse error.
     ParseError,
+
+    /// Invalid IP address error.
+    InvalidIpError,
+
+    /// Circuit breaker open error.
+    CircuitOpenError,
+
+    /// Cache backend error.
+    CacheError,
+
+    /// Country data asset loading error.
+    AssetLoadError,
+
+    /// Invalid client configuration error.
+    InvalidConfigError,
+
+    /// HTTP request timeout error.
+    TimeoutError,
+
+    /// DNS resolution error.
+    DnsError,
+
+    /// TLS handshake or certificate error.
+    TlsError,
+
+    /// Missing, invalid or unauthorized token error. (401 and 403 responses)
+    UnauthorizedError,
+
+    /// Offline database reading or decoding error.
+    DatabaseError,
 }
 
 impl IpErrorKind {
@@ -62,6 +94,16 @@ impl IpErrorKind {
             IpErrorKind::RateLimitExceededError => "rate limit exceeded",
             IpErrorKind::IpRequestError => "application error",
             IpErrorKind::ParseError => "parse error",
+            IpErrorKind::InvalidIpError => "invalid IP address",
+            IpErrorKind::CircuitOpenError => "circuit breaker open",
+            IpErrorKind::CacheError => "cache error",
+            IpErrorKind::AssetLoadError => "asset load error",
+            IpErrorKind::InvalidConfigError => "invalid configuration",
+            IpErrorKind::TimeoutError => "request timed out",
+            IpErrorKind::DnsError => "DNS resolution error",
+            IpErrorKind::TlsError => "TLS error",
+            IpErrorKind::UnauthorizedError => "unauthorized",
+            IpErrorKind::DatabaseError => "database error",
         }
     }
 }
@@ -73,12 +115,20 @@ impl fmt::Display for IpErrorKind {
 }
 
 /// The IpError type is the only error type that can be returned from this crate's API.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IpError {
     kind: IpErrorKind,
     description: Option<String>,
+    retry_after: Option<Duration>,
+    unavailable: bool,
+    status: Option<StatusCode>,
+    url: Option<String>,
+    body: Option<String>,
 }
 
+/// The maximum length of the response body kept in errors.
+const BODY_SNIPPET_MAX_LEN: usize = 1024;
+
 impl IpError {
     /// Create a new error object with an optional error message.
     ///
@@ -93,6 +143,11 @@ impl IpError {
         Self {
             kind,
             description: description.map(|desc| desc.to_string()),
+            retry_after: None,
+            unavailable: kind == IpErrorKind::CircuitOpenError,
+            status: None,
+            url: None,
+            body: None,
         }
     }
 
@@ -109,6 +164,124 @@ impl IpError {
     pub fn kind(&self) -> IpErrorKind {
         self.kind
     }
+
+    /// Get how long the API asked to wait before retrying, if it did.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use ipinfo::{IpError, IpErrorKind};
+    ///
+    /// let err = IpError::new(IpErrorKind::RateLimitExceededError, None);
+    /// assert_eq!(err.retry_after(), None);
+    /// ```
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
+
+    /// Check whether the error indicates the API is unavailable, meaning a network error, a 5xx
+    /// response or an open circuit breaker.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::{IpError, IpErrorKind};
+    ///
+    /// let err = IpError::new(IpErrorKind::CircuitOpenError, None);
+    /// assert!(err.is_unavailable());
+    /// ```
+    pub fn is_unavailable(&self) -> bool {
+        self.unavailable
+    }
+
+    /// Get the HTTP status code of the response that caused the error, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::{IpError, IpErrorKind};
+    ///
+    /// let err = IpError::new(IpErrorKind::HTTPClientError, None);
+    /// assert_eq!(err.status(), None);
+    /// ```
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// Get the URL of the request that caused the error, if any.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Get the beginning of the response body that caused the error, if any.
+    pub fn body(&self) -> Option<&str> {
+        self.body.as_deref()
+    }
+
+    /// Mark the error as meaning the API is unavailable, such as a connection failure reported
+    /// by a custom [`Transport`](crate::Transport).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::{IpError, IpErrorKind};
+    ///
+    /// let err = IpError::new(IpErrorKind::HTTPClientError, None).mark_unavailable();
+    /// assert!(err.is_unavailable());
+    /// ```
+    pub fn mark_unavailable(self) -> Self {
+        Self {
+            unavailable: true,
+            ..self
+        }
+    }
+
+    /// Create an error for a response with an error status.
+    pub(crate) fn from_status(status: StatusCode, url: &str) -> Self {
+        let class = if status.is_server_error() {
+            "server"
+        } else {
+            "client"
+        };
+        let description = format!("{status}: HTTP status {class} error for url ({url})");
+
+        Self {
+            unavailable: status.is_server_error(),
+            status: Some(status),
+            url: Some(url.to_owned()),
+            ..IpError::new(status_kind(status), Some(&description))
+        }
+    }
+
+    /// Attach the body of the response that caused the error, truncated to a snippet.
+    pub(crate) fn with_body(self, body: &str) -> Self {
+        let mut end = body.len().min(BODY_SNIPPET_MAX_LEN);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        Self {
+            body: Some(body[..end].to_owned()),
+            ..self
+        }
+    }
+
+    /// Create a rate limit exceeded error with the wait duration requested by the API.
+    pub(crate) fn rate_limited(retry_after: Option<Duration>) -> Self {
+        match retry_after {
+            Some(wait) => Self {
+                retry_after,
+                status: Some(StatusCode::TOO_MANY_REQUESTS),
+                ..err!(RateLimitExceededError, "retry after {}s", wait.as_secs())
+            },
+            None => Self {
+                status: Some(StatusCode::TOO_MANY_REQUESTS),
+                ..err!(RateLimitExceededError)
+            },
+        }
+    }
 }
 
 impl fmt::Display for IpError {
@@ -124,23 +297,93 @@ impl Error for IpError {}
 
 impl From<IpErrorKind> for IpError {
     fn from(kind: IpErrorKind) -> Self {
-        Self {
-            kind,
-            description: None,
-        }
+        Self::new(kind, None)
     }
 }
 
 impl From<reqwest::Error> for IpError {
     fn from(err: reqwest::Error) -> Self {
-        match err.status() {
-            Some(status) => err!(
-                HTTPClientError,
-                &format!("{}: {}", status, &err.to_string())
-            ),
-            None => err!(HTTPClientError, &err.to_string()),
+        #[cfg(not(target_arch = "wasm32"))]
+        let network = err.is_timeout() || err.is_connect() || err.is_body();
+        #[cfg(target_arch = "wasm32")]
+        let network = err.is_timeout() || err.is_request() || err.is_body();
+        let server = err.status().is_some_and(|status| status.is_server_error());
+
+        let description = match err.status() {
+            Some(status) => format!("{}: {}", status, &err.to_string()),
+            None => err.to_string(),
+        };
+        Self {
+            unavailable: network || server,
+            status: err.status(),
+            url: err.url().map(|url| url.to_string()),
+            ..IpError::new(reqwest_kind(&err), Some(&description))
+        }
+    }
+}
+
+/// Tell the kind of an HTTP client error from its status or underlying cause.
+fn reqwest_kind(err: &reqwest::Error) -> IpErrorKind {
+    if err.is_timeout() {
+        return IpErrorKind::TimeoutError;
+    }
+    if err.is_decode() {
+        return IpErrorKind::ParseError;
+    }
+    if let Some(status) = err.status() {
+        return status_kind(status);
+    }
+
+    let mut source = err.source();
+    while let Some(cause) = source {
+        if let Some(kind) = source_kind(cause) {
+            return kind;
+        }
+        source = cause.source();
+    }
+
+    IpErrorKind::HTTPClientError
+}
+
+/// Tell the kind of a connection failure from one of the sources of an HTTP client error.
+fn source_kind(cause: &(dyn Error + 'static)) -> Option<IpErrorKind> {
+    // Typed causes first: TLS errors of rustls, wrapped in I/O errors by tokio-rustls
+    #[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+    if cause.is::<rustls::Error>() {
+        return Some(IpErrorKind::TlsError);
+    }
+    if let Some(io) = cause.downcast_ref::<std::io::Error>() {
+        #[cfg(all(feature = "rustls", not(target_arch = "wasm32")))]
+        if io
+            .get_ref()
+            .is_some_and(|inner| inner.is::<rustls::Error>())
+        {
+            return Some(IpErrorKind::TlsError);
+        }
+        if io.kind() == std::io::ErrorKind::TimedOut {
+            return Some(IpErrorKind::TimeoutError);
         }
     }
+
+    // Best effort otherwise: DNS failures of hyper and native-tls errors have no public type to
+    // match, so they are told by their messages, which may change with these dependencies
+    let message = cause.to_string().to_lowercase();
+    if message.contains("dns error") || message.contains("failed to lookup address") {
+        return Some(IpErrorKind::DnsError);
+    }
+    if message.contains("tls") || message.contains("certificate") {
+        return Some(IpErrorKind::TlsError);
+    }
+
+    None
+}
+
+/// Tell the kind of an HTTP client error from its response status.
+fn status_kind(status: StatusCode) -> IpErrorKind {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => IpErrorKind::UnauthorizedError,
+        _ => IpErrorKind::HTTPClientError,
+    }
 }
 
 impl From<serde_json::Error> for IpError {
@@ -153,6 +396,28 @@ impl From<serde_json::Error> for IpError {
 mod tests {
     use super::*;
 
+    #[test]
+    fn connection_failures_are_classified() {
+        use std::io;
+
+        let timeout = io::Error::new(io::ErrorKind::TimedOut, "connect timeout");
+        assert_eq!(source_kind(&timeout), Some(IpErrorKind::TimeoutError));
+
+        #[cfg(feature = "rustls")]
+        {
+            let tls = io::Error::new(
+                io::ErrorKind::InvalidData,
+                rustls::Error::InvalidCertificate(rustls::CertificateError::Expired),
+            );
+            assert_eq!(source_kind(&tls), Some(IpErrorKind::TlsError));
+        }
+
+        let dns = io::Error::other("dns error: failed to lookup address information");
+        assert_eq!(source_kind(&dns), Some(IpErrorKind::DnsError));
+        let refused = io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused");
+        assert_eq!(source_kind(&refused), None);
+    }
+
     #[test]
     fn iperrorkind_string_values() {
         assert_eq!(
@@ -165,6 +430,25 @@ mod tests {
         );
         assert_eq!(IpErrorKind::IpRequestError.to_string(), "application error");
         assert_eq!(IpErrorKind::ParseError.to_string(), "parse error");
+        assert_eq!(
+            IpErrorKind::InvalidIpError.to_string(),
+            "invalid IP address"
+        );
+        assert_eq!(
+            IpErrorKind::CircuitOpenError.to_string(),
+            "circuit breaker open"
+        );
+        assert_eq!(IpErrorKind::CacheError.to_string(), "cache error");
+        assert_eq!(IpErrorKind::AssetLoadError.to_string(), "asset load error");
+        assert_eq!(
+            IpErrorKind::InvalidConfigError.to_string(),
+            "invalid configuration"
+        );
+        assert_eq!(IpErrorKind::TimeoutError.to_string(), "request timed out");
+        assert_eq!(IpErrorKind::DnsError.to_string(), "DNS resolution error");
+        assert_eq!(IpErrorKind::TlsError.to_string(), "TLS error");
+        assert_eq!(IpErrorKind::UnauthorizedError.to_string(), "unauthorized");
+        assert_eq!(IpErrorKind::DatabaseError.to_string(), "database error");
     }
 
     #[test]
@@ -176,9 +460,68 @@ mod tests {
         assert_eq!(err.to_string(), "HTTP client library error");
     }
 
+    #[test]
+    fn err_macro_formats_message() {
+        let err = err!(InvalidIpError, "{} is not an IP", "foo");
+
+        assert_eq!(err.kind(), IpErrorKind::InvalidIpError);
+        assert_eq!(err.to_string(), "invalid IP address: foo is not an IP");
+    }
+
+    #[test]
+    fn rate_limited_carries_retry_after() {
+        let err = IpError::rate_limited(Some(Duration::from_secs(30)));
+
+        assert_eq!(err.kind(), IpErrorKind::RateLimitExceededError);
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(30)));
+        assert_eq!(err.to_string(), "rate limit exceeded: retry after 30s");
+    }
+
     #[test]
     fn iperrorkind_convert_to_iperror() {
         let err = IpError::new(IpErrorKind::HTTPClientError, None);
         assert_eq!(err, IpError::from(IpErrorKind::HTTPClientError));
     }
+
+    fn status_error(status: u16) -> reqwest::Error {
+        let response = http::Response::builder().status(status).body("").unwrap();
+        reqwest::Response::from(response)
+            .error_for_status()
+            .unwrap_err()
+    }
+
+    #[test]
+    fn http_errors_are_classified() {
+        let err = IpError::from(status_error(403));
+        assert_eq!(err.kind(), IpErrorKind::UnauthorizedError);
+        assert!(!err.is_unavailable());
+
+        let err = IpError::from(status_error(503));
+        assert_eq!(err.kind(), IpErrorKind::HTTPClientError);
+        assert_eq!(err.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(err.is_unavailable());
+    }
+
+    #[test]
+    fn status_errors_match_http_client_errors() {
+        let err = IpError::from_status(StatusCode::FORBIDDEN, "https://ipinfo.io/8.8.8.8");
+        assert_eq!(err.kind(), IpErrorKind::UnauthorizedError);
+        assert_eq!(err.url(), Some("https://ipinfo.io/8.8.8.8"));
+        assert!(!err.is_unavailable());
+
+        let err = IpError::from_status(StatusCode::BAD_GATEWAY, "https://ipinfo.io/8.8.8.8");
+        assert_eq!(err.kind(), IpErrorKind::HTTPClientError);
+        assert_eq!(err.status(), Some(StatusCode::BAD_GATEWAY));
+        assert!(err.is_unavailable());
+    }
+
+    #[test]
+    fn body_is_truncated_to_snippet() {
+        let body = "é".repeat(BODY_SNIPPET_MAX_LEN);
+
+        let err = err!(IpRequestError).with_body(&body);
+
+        assert!(err.body().unwrap().len() <= BODY_SNIPPET_MAX_LEN);
+        assert!(err.body().unwrap().starts_with("éé"));
+    }
 }
@@ -0,0 +1,168 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Export of lookup results to other formats.
+
+use std::{collections::HashMap, io::Write};
+
+use crate::IpDetails;
+
+/// The columns written by [`to_csv`], in order.
+pub const CSV_COLUMNS: &[&str] = &[
+    "ip",
+    "hostname",
+    "city",
+    "region",
+    "country",
+    "country_name",
+    "is_eu",
+    "continent",
+    "loc",
+    "org",
+    "postal",
+    "timezone",
+    "asn",
+    "asn_name",
+    "company",
+    "carrier",
+    "bogon",
+    "anycast",
+];
+
+/// Write lookup results as CSV, with a header row and one row per IP address sorted by IP.
+///
+/// The columns are listed in [`CSV_COLUMNS`] and stay stable across releases; new columns are
+/// only ever appended. Missing values are written as empty fields.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{export, IpInfo, IpInfoConfig};
+///
+/// let ipinfo = IpInfo::new(IpInfoConfig::default()).expect("should construct");
+/// let details = ipinfo.lookup(&["8.8.8.8", "1.1.1.1"]).expect("should lookup");
+///
+/// let file = std::fs::File::create("ips.csv").expect("should create");
+/// export::to_csv(file, &details).expect("should write");
+/// ```
+pub fn to_csv<W: Write>(
+    mut writer: W,
+    details: &HashMap<String, IpDetails>,
+) -> std::io::Result<()> {
+    write_row(&mut writer, CSV_COLUMNS.iter().copied())?;
+
+    let mut ips: Vec<&String> = details.keys().collect();
+    ips.sort();
+
+    for ip in ips {
+        let row = csv_row(&details[ip]);
+        write_row(&mut writer, row.iter().map(String::as_str))?;
+    }
+
+    writer.flush()
+}
+
+/// Get the CSV fields of IP details, in the order of [`CSV_COLUMNS`].
+fn csv_row(details: &IpDetails) -> Vec<String> {
+    let string = |value: &Option<String>| value.clone().unwrap_or_default();
+    let boolean = |value: Option<bool>| value.map(|v| v.to_string()).unwrap_or_default();
+
+    vec![
+        details.ip.clone(),
+        string(&details.hostname),
+        details.city.clone(),
+        details.region.clone(),
+        details.country.clone(),
+        string(&details.country_name),
+        boolean(details.is_eu),
+        string(&details.continent.as_ref().map(|c| c.code.clone())),
+        details.loc.clone(),
+        string(&details.org),
+        string(&details.postal),
+        string(&details.timezone),
+        string(&details.asn.as_ref().map(|asn| asn.asn.clone())),
+        string(&details.asn.as_ref().map(|asn| asn.name.clone())),
+        string(&details.company.as_ref().map(|company| company.name.clone())),
+        string(&details.carrier.as_ref().map(|carrier| carrier.name.clone())),
+        boolean(details.bogon),
+        boolean(details.anycast),
+    ]
+}
+
+/// Write a CSV row, quoting the fields which need it.
+fn write_row<'a, W: Write>(
+    writer: &mut W,
+    fields: impl Iterator<Item = &'a str>,
+) -> std::io::Result<()> {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+
+        if field.contains([',', '"', '\n', '\r']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            writer.write_all(field.as_bytes())?;
+        }
+    }
+
+    writer.write_all(b"\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture as details;
+
+    #[test]
+    fn results_are_written_as_csv() {
+        let results = HashMap::from([
+            (
+                "8.8.8.8".to_owned(),
+                details(serde_json::json!({
+                    "ip": "8.8.8.8",
+                    "hostname": "dns.google",
+                    "city": "Mountain View",
+                    "country": "US",
+                    "org": "AS15169 Google, LLC",
+                    "anycast": true,
+                    "asn": {
+                        "asn": "AS15169",
+                        "name": "Google \"LLC\"",
+                        "domain": "google.com",
+                        "route": "8.8.8.0/24",
+                        "type": "hosting"
+                    },
+                })),
+            ),
+            (
+                "10.0.0.1".to_owned(),
+                details(serde_json::json!({ "ip": "10.0.0.1", "bogon": true })),
+            ),
+        ]);
+        let mut out = Vec::new();
+
+        to_csv(&mut out, &results).expect("should write");
+
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(lines[0], CSV_COLUMNS.join(","));
+        assert_eq!(lines[1], "10.0.0.1,,,,,,,,,,,,,,,,true,");
+        assert_eq!(
+            lines[2],
+            "8.8.8.8,dns.google,Mountain View,,US,,,,,\"AS15169 Google, LLC\",,,AS15169,\"Google \"\"LLC\"\"\",,,,true"
+        );
+        assert_eq!(lines[3], "");
+    }
+}
@@ -0,0 +1,148 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Request coalescing of concurrent identical lookups.
+
+use std::{
+    collections::HashMap,
+    pin::pin,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use tokio::sync::Notify;
+
+/// The IPs being looked up, shared by the clients of a context and its forks.
+#[derive(Default)]
+pub(crate) struct Flights {
+    inflight: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+/// An in-flight lookup of an IP, completed once its result is cached or it failed.
+#[derive(Default)]
+pub(crate) struct Flight {
+    done: Mutex<bool>,
+    cond: Condvar,
+    notify: Notify,
+}
+
+/// The IPs claimed by a lookup, whose flights are completed when dropped.
+pub(crate) struct Claim {
+    flights: Arc<Flights>,
+    ips: Vec<String>,
+}
+
+/// The IPs to query, and the flights of the IPs already being looked up.
+pub(crate) type Claimed<'a> = (Claim, Vec<&'a str>, Vec<(&'a str, Arc<Flight>)>);
+
+impl Flights {
+    /// Claim the IPs that are not in flight yet.
+    pub(crate) fn claim<'a>(self: &Arc<Self>, ips: &[&'a str]) -> Claimed<'a> {
+        let mut inflight = self.inflight.lock().unwrap();
+        let mut owned = vec![];
+        let mut joined = vec![];
+
+        for ip in ips {
+            match inflight.get(*ip) {
+                Some(flight) => joined.push((*ip, flight.clone())),
+                None => {
+                    inflight.insert(ip.to_string(), Default::default());
+                    owned.push(*ip);
+                }
+            }
+        }
+
+        let claim = Claim {
+            flights: self.clone(),
+            ips: owned.iter().map(|ip| ip.to_string()).collect(),
+        };
+        (claim, owned, joined)
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        let mut inflight = self.flights.inflight.lock().unwrap();
+        self.ips.iter().for_each(|ip| {
+            if let Some(flight) = inflight.remove(ip) {
+                flight.complete();
+            }
+        });
+    }
+}
+
+impl Flight {
+    fn complete(&self) {
+        *self.done.lock().unwrap() = true;
+        self.cond.notify_all();
+        self.notify.notify_waiters();
+    }
+
+    /// Block until the lookup is completed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn wait(&self) {
+        let mut done = self.done.lock().unwrap();
+        while !*done {
+            done = self.cond.wait(done).unwrap();
+        }
+    }
+
+    /// Wait until the lookup is completed.
+    pub(crate) async fn wait_async(&self) {
+        let mut notified = pin!(self.notify.notified());
+
+        // Register for the notification before checking, so it can't be missed
+        notified.as_mut().enable();
+        if *self.done.lock().unwrap() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claimed_ips_are_joined_until_completed() {
+        let flights = Arc::new(Flights::default());
+
+        let (claim, owned, joined) = flights.claim(&["8.8.8.8"]);
+        assert_eq!(owned, vec!["8.8.8.8"]);
+        assert!(joined.is_empty());
+
+        let (_, owned, joined) = flights.claim(&["8.8.8.8", "1.1.1.1"]);
+        assert_eq!(owned, vec!["1.1.1.1"]);
+        assert_eq!(joined.len(), 1);
+
+        let waiter = std::thread::spawn(move || joined[0].1.wait());
+        drop(claim);
+        waiter.join().unwrap();
+
+        let (_, owned, _) = flights.claim(&["8.8.8.8"]);
+        assert_eq!(owned, vec!["8.8.8.8"]);
+    }
+
+    #[tokio::test]
+    async fn flights_can_be_awaited() {
+        let flights = Arc::new(Flights::default());
+        let (claim, _, _) = flights.claim(&["8.8.8.8"]);
+        let (_, _, joined) = flights.claim(&["8.8.8.8"]);
+
+        let waiter = tokio::spawn(async move { joined[0].1.wait_async().await });
+        drop(claim);
+
+        waiter.await.unwrap();
+    }
+}
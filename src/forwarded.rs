@@ -0,0 +1,194 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Client IP detection from the forwarded headers set by reverse proxies.
+
+use std::net::{IpAddr, SocketAddr};
+
+/// Get the original client IP of a request behind `trusted_proxies` reverse proxies, from its
+/// `Forwarded`, `X-Forwarded-For` or `X-Real-IP` header, in that order of preference.
+///
+/// Each proxy appends the address of its peer to the right of the `Forwarded` and
+/// `X-Forwarded-For` lists, so the entries a client sends itself are on the left. The client IP
+/// is the entry appended by the outermost trusted proxy, `trusted_proxies` entries from the
+/// right, and lists with fewer entries have none. Without trusted proxies, the headers are
+/// ignored, as clients can forge them.
+///
+/// `header` gets the value of a request header by its lowercase name, with the values of a
+/// repeated header joined by commas, so this works with the header types of any web framework,
+/// such as actix-web, warp or Rocket.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use ipinfo::forwarded_ip;
+///
+/// // The client forged the leftmost entry, and a single proxy appended its real address
+/// let headers = HashMap::from([("x-forwarded-for", "1.1.1.1, 8.8.8.8")]);
+/// let ip = forwarded_ip(|name| headers.get(name).copied(), 1);
+/// assert_eq!(ip, Some("8.8.8.8".parse().unwrap()));
+/// ```
+pub fn forwarded_ip<'a>(
+    header: impl Fn(&str) -> Option<&'a str>,
+    trusted_proxies: usize,
+) -> Option<IpAddr> {
+    if trusted_proxies == 0 {
+        return None;
+    }
+    let appended = |list: &'a str| list.split(',').rev().nth(trusted_proxies - 1);
+
+    // Forwarded: for=192.0.2.60;proto=http, for="[2001:db8::1]:4711"
+    let forwarded = header("forwarded").and_then(|value| {
+        appended(value)?.split(';').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case("for")
+                .then(|| parse_node(value))?
+        })
+    });
+
+    forwarded
+        .or_else(|| header("x-forwarded-for").and_then(|value| parse_node(appended(value)?)))
+        .or_else(|| header("x-real-ip").and_then(parse_node))
+}
+
+/// Configuration of the web framework integrations.
+#[cfg(any(
+    feature = "actix",
+    feature = "axum",
+    feature = "rocket",
+    feature = "warp"
+))]
+#[derive(Debug, Clone, Default)]
+pub struct MiddlewareConfig {
+    /// The number of reverse proxies in front of the server, whose `Forwarded`,
+    /// `X-Forwarded-For` and `X-Real-IP` headers are trusted, see [`forwarded_ip`]. Without
+    /// trusted proxies the client IP is the peer address, as clients can forge these headers.
+    /// (default: 0)
+    pub trusted_proxies: usize,
+
+    /// Whether the actix-web middleware answers requests whose client IP lookup failed with a
+    /// 500 Internal Server Error, rather than passing them on without details. The other
+    /// integrations leave failed lookups to handlers extracting optional details.
+    /// (default: false)
+    pub fail_on_lookup_error: bool,
+}
+
+/// Get the client IP of a request from the forwarded headers of its trusted proxies, falling
+/// back to the address of the peer.
+///
+/// `headers` gets all the values of a request header by its lowercase name.
+#[cfg(any(
+    feature = "actix",
+    feature = "axum",
+    feature = "rocket",
+    feature = "warp"
+))]
+pub(crate) fn client_ip<'a, I>(
+    trusted_proxies: usize,
+    headers: impl Fn(&str) -> I,
+    peer: Option<IpAddr>,
+) -> Option<IpAddr>
+where
+    I: Iterator<Item = &'a str>,
+{
+    // Proxies may append their entries as repeated headers rather than to the existing ones
+    let joined: Vec<(&str, String)> = ["forwarded", "x-forwarded-for", "x-real-ip"]
+        .into_iter()
+        .map(|name| (name, headers(name).collect::<Vec<_>>().join(",")))
+        .filter(|(_, value)| !value.is_empty())
+        .collect();
+    let header = |name: &str| {
+        joined
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    forwarded_ip(header, trusted_proxies).or(peer)
+}
+
+/// Parse a forwarded node, an IP with an optional port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(headers: &[(&str, &str)], trusted_proxies: usize) -> Option<IpAddr> {
+        forwarded_ip(
+            |name| {
+                headers
+                    .iter()
+                    .find(|(header, _)| *header == name)
+                    .map(|(_, value)| *value)
+            },
+            trusted_proxies,
+        )
+    }
+
+    #[test]
+    fn forwarded_headers_are_parsed() {
+        assert_eq!(
+            ip(
+                &[("forwarded", r#"proto=http;for="[2001:db8::1]:4711""#)],
+                1
+            ),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(
+            ip(&[("forwarded", "for=4.2.2.4:80, for=10.0.0.1")], 2),
+            Some("4.2.2.4".parse().unwrap())
+        );
+        assert_eq!(
+            ip(&[("x-forwarded-for", "1.1.1.1, 10.0.0.1")], 2),
+            Some("1.1.1.1".parse().unwrap())
+        );
+        assert_eq!(
+            ip(&[("x-real-ip", "9.9.9.9")], 1),
+            Some("9.9.9.9".parse().unwrap())
+        );
+        assert_eq!(
+            ip(
+                &[("x-forwarded-for", "unknown"), ("x-real-ip", "9.9.9.9")],
+                1
+            ),
+            Some("9.9.9.9".parse().unwrap())
+        );
+        assert_eq!(ip(&[("forwarded", "for=_hidden")], 1), None);
+        assert_eq!(ip(&[], 1), None);
+    }
+
+    #[test]
+    fn forged_leftmost_entries_are_skipped() {
+        // The client sent "1.1.1.1", then two trusted proxies appended 8.8.8.8 and 10.0.0.1
+        let forged = [("x-forwarded-for", "1.1.1.1, 8.8.8.8, 10.0.0.1")];
+        assert_eq!(ip(&forged, 2), Some("8.8.8.8".parse().unwrap()));
+        assert_eq!(ip(&forged, 1), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(ip(&forged, 0), None);
+
+        let forged = [("forwarded", "for=1.1.1.1, for=8.8.8.8")];
+        assert_eq!(ip(&forged, 1), Some("8.8.8.8".parse().unwrap()));
+
+        // With more trusted proxies than entries, none of them is the client IP
+        assert_eq!(ip(&[("x-forwarded-for", "8.8.8.8")], 2), None);
+    }
+}
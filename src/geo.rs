@@ -0,0 +1,66 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Geographic utilities on IP locations.
+
+/// The mean radius of the Earth in kilometers.
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// Get the great-circle distance in kilometers between two (latitude, longitude) coordinates
+/// in degrees, using the haversine formula.
+///
+/// # Examples
+///
+/// ```
+/// use ipinfo::distance;
+///
+/// let paris = (48.8566, 2.3522);
+/// let london = (51.5074, -0.1278);
+/// assert_eq!(distance(paris, london).round(), 344.0);
+/// ```
+pub fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_is_symmetric_and_zero_for_same_point() {
+        let mountain_view = (37.4056, -122.0775);
+        let sydney = (-33.8688, 151.2093);
+
+        assert_eq!(distance(mountain_view, mountain_view), 0.0);
+        assert_eq!(
+            distance(mountain_view, sydney),
+            distance(sydney, mountain_view)
+        );
+        assert_eq!(distance(mountain_view, sydney).round(), 11_954.0);
+    }
+
+    #[test]
+    fn antipodes_are_half_the_circumference_apart() {
+        let half = std::f64::consts::PI * EARTH_RADIUS_KM;
+
+        assert!((distance((0.0, 0.0), (0.0, 180.0)) - half).abs() < 1e-6);
+        assert!((distance((90.0, 0.0), (-90.0, 0.0)) - half).abs() < 1e-6);
+    }
+}
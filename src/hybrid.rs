@@ -0,0 +1,270 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Offline-first lookups in a local database, falling back to the API.
+
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+
+use crate::{Database, IpDetails, IpError, IpInfo};
+
+use serde_json::Value;
+
+/// Resolves IP addresses from a local [`Database`] first, calling the API only for the IPs
+/// missing from the database or whose record lacks some of the required fields.
+///
+/// When the API is called, its answer only fills the fields the database record lacks, and the
+/// country data follows the country of the record. Details answered locally are enriched like
+/// those of the client, and aren't cached by it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+///
+/// use ipinfo::{Database, HybridResolver, IpInfo};
+///
+/// let database = Arc::new(Database::open("country_asn.mmdb").expect("should open"));
+/// let client = IpInfo::new(Default::default()).expect("should construct");
+///
+/// // Only call the API for the IPs without a city in the database
+/// let resolver = HybridResolver::new(database, client, &["city"]);
+/// let details = resolver.lookup("8.8.8.8").expect("should lookup");
+/// ```
+pub struct HybridResolver {
+    database: Arc<Database>,
+    client: IpInfo,
+    required_fields: Vec<String>,
+}
+
+impl HybridResolver {
+    /// Construct a new resolver, requiring the given API fields (such as `city`, `asn` or
+    /// `privacy`) from the database records to answer without calling the API.
+    pub fn new(database: Arc<Database>, client: IpInfo, required_fields: &[&str]) -> Self {
+        Self {
+            database,
+            client,
+            required_fields: required_fields.iter().map(|f| f.to_string()).collect(),
+        }
+    }
+
+    /// Lookup a single IP address.
+    ///
+    /// Fails with an `InvalidIpError` if the IP address is invalid.
+    pub fn lookup(&self, ip: &str) -> Result<IpDetails, IpError> {
+        let mut details = self.lookup_many(&[ip])?;
+        details
+            .remove(ip)
+            .ok_or_else(|| err!(IpRequestError, "no details for {}", ip))
+    }
+
+    /// Lookup a list of one or more IP addresses, sending one batch request for the IPs which
+    /// couldn't be answered by the database.
+    pub fn lookup_many(&self, ips: &[&str]) -> Result<HashMap<String, IpDetails>, IpError> {
+        let mut results = HashMap::with_capacity(ips.len());
+        let mut partial = HashMap::new();
+
+        for ip in ips {
+            let addr: IpAddr = ip.parse().map_err(|_| err!(InvalidIpError, "{:?}", ip))?;
+            match self.database.lookup_details(addr)? {
+                Some(mut details) if self.is_complete(&details) => {
+                    self.client.enrich(&mut details);
+                    results.insert(ip.to_string(), details);
+                }
+                local => {
+                    partial.insert(*ip, local);
+                }
+            }
+        }
+
+        if !partial.is_empty() {
+            let misses: Vec<&str> = partial.keys().copied().collect();
+            let mut answers = self.client.lookup(&misses)?;
+            for (ip, local) in partial {
+                let Some(answer) = answers.remove(ip) else {
+                    continue;
+                };
+                let details = match local {
+                    Some(local) => {
+                        let mut details = merge(local, answer)?;
+                        self.client.enrich_countries(&mut details);
+                        details
+                    }
+                    None => answer,
+                };
+                results.insert(ip.to_owned(), details);
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Check whether details have every required field.
+    fn is_complete(&self, details: &IpDetails) -> bool {
+        let Ok(Value::Object(fields)) = serde_json::to_value(details) else {
+            return false;
+        };
+
+        self.required_fields
+            .iter()
+            .all(|field| fields.get(field).is_some_and(|value| !is_empty(value)))
+    }
+}
+
+/// Fill the fields missing from the details of a database record with those of the API.
+fn merge(local: IpDetails, api: IpDetails) -> Result<IpDetails, IpError> {
+    let stale = api.stale;
+    let (Value::Object(mut fields), Value::Object(api_fields)) =
+        (serde_json::to_value(local)?, serde_json::to_value(api)?)
+    else {
+        unreachable!("IP details should serialize to objects");
+    };
+
+    // The country data of the API answer would not match the country of the record
+    let country_set = fields.get("country").is_some_and(|v| !is_empty(v));
+    let country_data = [
+        "country_name",
+        "is_eu",
+        "country_flag",
+        "country_flag_url",
+        "country_currency",
+        "continent",
+    ];
+
+    for (key, value) in api_fields {
+        if country_set && country_data.contains(&key.as_str()) {
+            continue;
+        }
+        if fields.get(&key).is_none_or(is_empty) {
+            fields.insert(key, value);
+        }
+    }
+
+    let mut details: IpDetails = serde_json::from_value(Value::Object(fields))?;
+    details.stale = stale;
+    Ok(details)
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use crate::{mmdb::tests::database, HttpRequest, HttpResponse, IpInfoConfig, Transport};
+
+    /// Transport answering batch requests from a JSON body, recording the requests.
+    struct Api {
+        body: Value,
+        requests: Mutex<Vec<HttpRequest>>,
+    }
+
+    impl Transport for Api {
+        fn send(&self, request: HttpRequest) -> Result<HttpResponse, IpError> {
+            self.requests.lock().unwrap().push(request);
+            Ok(HttpResponse {
+                status: reqwest::StatusCode::OK,
+                headers: Default::default(),
+                body: self.body.to_string(),
+            })
+        }
+    }
+
+    fn resolver(required_fields: &[&str]) -> (HybridResolver, Arc<Api>) {
+        let database = Database::from_bytes(database(
+            24,
+            &[
+                (
+                    "8.8.8.0/24",
+                    serde_json::json!({
+                        "country_code": "US",
+                        "city": "Mountain View",
+                        "asn": "AS15169",
+                        "as_name": "Google LLC",
+                    }),
+                ),
+                ("1.1.1.0/24", serde_json::json!({ "country_code": "AU" })),
+            ],
+        ))
+        .expect("should read");
+        let api = Arc::new(Api {
+            body: serde_json::json!({
+                "1.1.1.1": { "ip": "1.1.1.1", "city": "Brisbane", "country": "NZ" },
+                "9.9.9.9": { "ip": "9.9.9.9", "city": "Berkeley", "country": "US" },
+            }),
+            requests: Default::default(),
+        });
+        let client = IpInfo::new(IpInfoConfig {
+            transport: Some(api.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        (
+            HybridResolver::new(Arc::new(database), client, required_fields),
+            api,
+        )
+    }
+
+    #[test]
+    fn complete_records_are_answered_locally() {
+        let (resolver, api) = resolver(&["city", "asn"]);
+
+        let details = resolver.lookup("8.8.8.8").expect("should lookup");
+
+        assert_eq!(details.city, "Mountain View");
+        assert_eq!(details.asn.unwrap().name, "Google LLC");
+        assert!(api.requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn incomplete_and_missing_records_are_looked_up() {
+        let (resolver, api) = resolver(&["city"]);
+
+        let details = resolver
+            .lookup_many(&["8.8.8.8", "1.1.1.1", "9.9.9.9"])
+            .expect("should lookup");
+
+        assert_eq!(details.len(), 3);
+        assert_eq!(details["8.8.8.8"].city, "Mountain View");
+        // The database record is completed by the API, without replacing its fields
+        assert_eq!(details["1.1.1.1"].city, "Brisbane");
+        assert_eq!(details["1.1.1.1"].country, "AU");
+        #[cfg(feature = "bundled-assets")]
+        assert_eq!(
+            details["1.1.1.1"].country_name.as_deref(),
+            Some("Australia")
+        );
+        assert_eq!(details["9.9.9.9"].city, "Berkeley");
+
+        let requests = api.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].body.as_ref().unwrap().contains("8.8.8.8"));
+    }
+
+    #[test]
+    fn invalid_ips_are_rejected() {
+        let (resolver, _) = resolver(&[]);
+
+        let err = resolver.lookup("foo").err().unwrap();
+        assert_eq!(err.kind(), crate::IpErrorKind::InvalidIpError);
+    }
+}
@@ -0,0 +1,54 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Hooks around the HTTP requests of the clients.
+
+use crate::{HttpRequest, HttpResponse, IpError};
+
+/// Hooks called around every attempt of an HTTP request, to log, modify headers or collect
+/// metrics.
+///
+/// Every method does nothing by default, so implementations only override the hooks they need.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use ipinfo::{HttpRequest, HttpResponse, Interceptor, IpInfo, IpInfoConfig};
+///
+/// struct Logger;
+///
+/// impl Interceptor for Logger {
+///     fn on_response(&self, request: &HttpRequest, response: &HttpResponse) {
+///         println!("{} {}: {}", request.method, request.url, response.status);
+///     }
+/// }
+///
+/// let ipinfo = IpInfo::new(IpInfoConfig {
+///     interceptors: vec![Arc::new(Logger)],
+///     ..Default::default()
+/// })
+/// .expect("should construct");
+/// ```
+pub trait Interceptor: Send + Sync {
+    /// Called before a request is sent, possibly modifying it.
+    fn on_request(&self, _request: &mut HttpRequest) {}
+
+    /// Called when a response is received, whatever its status.
+    fn on_response(&self, _request: &HttpRequest, _response: &HttpResponse) {}
+
+    /// Called when a request fails, because of its response status or a transport error.
+    fn on_error(&self, _request: &HttpRequest, _error: &IpError) {}
+}
@@ -12,302 +12,1765 @@
 //   See the License for the specific language governing permissions and
 //   limitations under the License.
 
-use std::{collections::HashMap, fs, num::NonZeroUsize, time::Duration};
+use std::{collections::HashMap, net::IpAddr, sync::Arc, thread, time::Instant};
 
-use crate::{Continent, CountryCurrency, CountryFlag, IpDetails, IpError, VERSION};
+use crate::{
+    bogon::bogon_details,
+    client,
+    context::{
+        expand_cidr, key_by_addr, normalize_asn, parse_response, resolve_host, validate_domain,
+        validate_ip, validate_ips, Attempts, Context, Next, BATCH_MAX_SIZE,
+    },
+    flight::Flight,
+    AsnResponse, CacheStats, Continent, CountryCurrency, CountryData, CountryFlag, DomainsDetails,
+    HttpRequest, IpDetails, IpError, IpField, IpInfoConfig, LiteDetails, MapResponse,
+    RangesResponse, RateLimit, ReqwestTransport, SecretToken, TokenDetails, Transport,
+};
 
-use lru::LruCache;
 use serde_json::json;
 
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE, USER_AGENT};
-
-use include_dir::{include_dir, Dir};
-static ASSETS_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/assets");
-
-/// IpInfo structure configuration.
-pub struct IpInfoConfig {
-    /// IPinfo access token.
-    pub token: Option<String>,
+/// IPinfo requests context structure.
+///
+/// Lookups take `&self`, so a client can be shared between threads and used concurrently.
+/// Cloning is cheap: clones share the HTTP client, caches, statistics and country data.
+#[derive(Clone)]
+pub struct IpInfo {
+    transport: Arc<dyn Transport>,
+    context: Arc<Context>,
+}
 
-    /// The timeout of HTTP requests. (default: 3 seconds)
-    pub timeout: Duration,
+impl IpInfo {
+    /// Construct a new IpInfo structure.
+    ///
+    /// Fails with an `InvalidConfigError` if `cache_size` is 0, or an `AssetLoadError` if the
+    /// country data files can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// ```
+    pub fn new(config: IpInfoConfig) -> Result<Self, IpError> {
+        let transport = match &config.transport {
+            Some(transport) => transport.clone(),
+            None => {
+                let builder = client::configure(reqwest::blocking::Client::builder(), &config)?;
+                Arc::new(ReqwestTransport::new(builder.build()?))
+            }
+        };
 
-    /// The size of the LRU cache. (default: 100 IPs)
-    pub cache_size: usize,
+        Ok(Self {
+            transport,
+            context: Arc::new(Context::new(config)?),
+        })
+    }
 
-    /// The file path of `countries.json`
-    pub countries_file_path: Option<String>,
+    /// Construct a new IpInfo structure with the default configuration and the token read from
+    /// the `IPINFO_TOKEN` environment variable, if set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::from_env().expect("should construct");
+    /// ```
+    pub fn from_env() -> Result<Self, IpError> {
+        Self::new(IpInfoConfig {
+            token_from_env: true,
+            ..Default::default()
+        })
+    }
 
-    /// The file path of `eu.json`
-    pub eu_file_path: Option<String>,
+    /// Lookup a list of one or more IP addresses.
+    ///
+    /// Lists larger than the batch API limit of 1000 IPs are split into multiple requests. IPs
+    /// the API reports an error for are left out of the result, with or without negative
+    /// caching, and so are IPs whose error is still negatively cached: the result can miss
+    /// requested IPs, so get them rather than index it. See [`lookup_each`](IpInfo::lookup_each)
+    /// for their errors.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup(&["8.8.8.8"]).expect("should run");
+    /// let country = res.get("8.8.8.8").map(|details| &details.country);
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub fn lookup(&self, ips: &[&str]) -> Result<HashMap<String, IpDetails>, IpError> {
+        // Leave out the IPs with a recently reported error
+        let ips = self.context.check_ips(ips)?;
 
-    /// The file path of `flags.json`
-    pub country_flags_file_path: Option<String>,
+        // Check for cache hits
+        let (hits, misses) = self.context.check_cache(&ips);
+        self.revalidate();
 
-    /// The file path of `currencies.json`
-    pub country_currencies_file_path: Option<String>,
+        self.lookup_misses(hits, &misses)
+    }
 
-    /// The file path of `continents.json`
-    pub continents_file_path: Option<String>,
-}
+    /// Lookup a list of one or more IP addresses, authenticating with another token than the
+    /// configured one.
+    ///
+    /// This lets multi-tenant services use the token of each customer while sharing a single
+    /// client and its cache.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo
+    ///     .lookup_with_token(&["8.8.8.8"], "customer token")
+    ///     .expect("should run");
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub fn lookup_with_token(
+        &self,
+        ips: &[&str],
+        token: impl Into<SecretToken>,
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        let tenant = IpInfo {
+            transport: self.transport.clone(),
+            context: Arc::new(self.context.with_token(token.into())?),
+        };
+        let result = tenant.lookup(ips);
+        self.context.merge_stats(&tenant.context);
 
-impl Default for IpInfoConfig {
-    fn default() -> Self {
-        Self {
-            token: None,
-            timeout: Duration::from_secs(3),
-            cache_size: 100,
-            countries_file_path: None,
-            eu_file_path: None,
-            country_flags_file_path: None,
-            country_currencies_file_path: None,
-            continents_file_path: None,
-        }
+        result
     }
-}
-
-/// IPinfo requests context structure.
-pub struct IpInfo {
-    url: String,
-    token: Option<String>,
-    client: reqwest::blocking::Client,
-    cache: LruCache<String, IpDetails>,
-    countries: HashMap<String, String>,
-    eu: Vec<String>,
-    country_flags: HashMap<String, CountryFlag>,
-    country_currencies: HashMap<String, CountryCurrency>,
-    continents: HashMap<String, Continent>,
-}
 
-impl IpInfo {
-    /// Construct a new IpInfo structure.
+    /// Lookup a list of one or more IP addresses, ignoring cached entries.
+    ///
+    /// Every IP except bogons is queried from the API, and the cache is updated with the
+    /// results.
     ///
     /// # Examples
     ///
-    /// ```
+    /// ```no_run
     /// use ipinfo::IpInfo;
     ///
     /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_fresh(&["8.8.8.8"]).expect("should run");
     /// ```
-    pub fn new(config: IpInfoConfig) -> Result<Self, IpError> {
-        let client = reqwest::blocking::Client::builder()
-            .timeout(config.timeout)
-            .build()?;
-
-        let url = "https://ipinfo.io".to_owned();
-
-        let mut ipinfo_obj = Self {
-            url,
-            client,
-            token: config.token,
-            cache: LruCache::new(NonZeroUsize::new(config.cache_size).unwrap()),
-            countries: HashMap::new(),
-            eu: Vec::new(),
-            country_flags: HashMap::new(),
-            country_currencies: HashMap::new(),
-            continents: HashMap::new(),
-        };
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub fn lookup_fresh(&self, ips: &[&str]) -> Result<HashMap<String, IpDetails>, IpError> {
+        validate_ips(ips)?;
 
-        if config.countries_file_path.is_none() {
-            let t_file = ASSETS_DIR
-                .get_file("countries.json")
-                .expect("error opening file");
-            ipinfo_obj.countries =
-                serde_json::from_str(t_file.contents_utf8().unwrap()).expect("error parsing JSON!");
-        } else {
-            let t_file = fs::File::open(config.countries_file_path.as_ref().unwrap())
-                .expect("error opening file");
-            ipinfo_obj.countries = serde_json::from_reader(t_file).expect("error parsing JSON!");
-        }
+        // Only answer bogons locally
+        let (bogons, misses) = self.context.check_bogons(ips);
 
-        if config.eu_file_path.is_none() {
-            let t_file = ASSETS_DIR.get_file("eu.json").expect("error opening file");
-            ipinfo_obj.eu =
-                serde_json::from_str(t_file.contents_utf8().unwrap()).expect("error parsing JSON!");
-        } else {
-            let t_file =
-                fs::File::open(config.eu_file_path.as_ref().unwrap()).expect("error opening file");
-            ipinfo_obj.eu = serde_json::from_reader(t_file).expect("error parsing JSON!");
-        }
+        self.lookup_misses(bogons, &misses)
+    }
 
-        if config.country_flags_file_path.is_none() {
-            let t_file = ASSETS_DIR
-                .get_file("flags.json")
-                .expect("error opening file");
-            ipinfo_obj.country_flags =
-                serde_json::from_str(t_file.contents_utf8().unwrap()).expect("error parsing JSON!");
-        } else {
-            let t_file = fs::File::open(config.country_flags_file_path.as_ref().unwrap())
-                .expect("error opening file");
-            ipinfo_obj.country_flags =
-                serde_json::from_reader(t_file).expect("error parsing JSON!");
+    /// Query the API for the IPs missing from the cache, then merge in the cache hits.
+    fn lookup_misses(
+        &self,
+        mut hits: Vec<IpDetails>,
+        misses: &[&str],
+    ) -> Result<HashMap<String, IpDetails>, IpError> {
+        // Return directly from the cache when there is nothing to query
+        if misses.is_empty() {
+            return Ok(hits.into_iter().map(|x| (x.ip.clone(), x)).collect());
         }
 
-        if config.country_currencies_file_path.is_none() {
-            let t_file = ASSETS_DIR
-                .get_file("currency.json")
-                .expect("error opening file");
-            ipinfo_obj.country_currencies =
-                serde_json::from_str(t_file.contents_utf8().unwrap()).expect("error parsing JSON!");
-        } else {
-            let t_file = fs::File::open(config.country_currencies_file_path.as_ref().unwrap())
-                .expect("error opening file");
-            ipinfo_obj.country_currencies =
-                serde_json::from_reader(t_file).expect("error parsing JSON!");
+        // Only query the IPs not already being looked up by another thread
+        let (claim, owned, joined) = self.context.flights.claim(misses);
+
+        // Lookup cache misses
+        let mut details = HashMap::new();
+        for (chunk, raw_resp) in self.send_batches(&owned) {
+            details.extend(self.context.process_batch_or_stale(chunk, raw_resp)?);
         }
+        drop(claim);
 
-        if config.continents_file_path.is_none() {
-            let t_file = ASSETS_DIR
-                .get_file("continent.json")
-                .expect("error opening file");
-            ipinfo_obj.continents =
-                serde_json::from_str(t_file.contents_utf8().unwrap()).expect("error parsing JSON!");
-        } else {
-            let t_file = fs::File::open(config.continents_file_path.as_ref().unwrap())
-                .expect("error opening file");
-            ipinfo_obj.continents = serde_json::from_reader(t_file).expect("error parsing JSON!");
+        // Share the results of the joined lookups, querying again the IPs they failed
+        let (shared, failed) = self.join_flights(joined);
+        hits.extend(shared);
+        for (chunk, raw_resp) in self.send_batches(&failed) {
+            details.extend(self.context.process_batch_or_stale(chunk, raw_resp)?);
         }
 
-        Ok(ipinfo_obj)
+        // Add cache hits to the result
+        hits.into_iter().for_each(|x| {
+            details.insert(x.ip.clone(), x);
+        });
+
+        Ok(details)
     }
 
-    /// Lookup a list of one or more IP addresses.
+    /// Lookup a single IP address using the single IP endpoint.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use ipinfo::IpInfo;
     ///
-    /// let mut ipinfo = IpInfo::new(Default::default()).expect("should construct");
-    /// let res = ipinfo.lookup(&["8.8.8.8"]).expect("should run");
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_one("8.8.8.8").expect("should run");
     /// ```
-    pub fn lookup(&mut self, ips: &[&str]) -> Result<HashMap<String, IpDetails>, IpError> {
-        let mut hits: Vec<IpDetails> = vec![];
-        let mut misses: Vec<&str> = vec![];
-
-        // Check for cache hits
-        ips.iter()
-            .for_each(|x| match self.cache.get(&x.to_string()) {
-                Some(detail) => hits.push(detail.clone()),
-                None => misses.push(*x),
-            });
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn lookup_one(&self, ip: &str) -> Result<IpDetails, IpError> {
+        // Fail with a recently reported error
+        self.context.check_ip(ip)?;
 
-        // Lookup cache misses
-        let response = self
-            .client
-            .post(&format!("{}/batch", self.url))
-            .headers(Self::construct_headers())
-            .bearer_auth(self.token.as_ref().unwrap_or(&"".to_string()))
-            .json(&json!(misses))
-            .send()?;
-
-        // Check if we exhausted our request quota
-        if let reqwest::StatusCode::TOO_MANY_REQUESTS = response.status() {
-            return Err(err!(RateLimitExceededError));
+        // Check for a cache hit
+        if let Some(detail) = self.context.get_cached(ip) {
+            self.revalidate();
+            return Ok(detail);
         }
 
-        // Acquire response
-        let raw_resp = response.error_for_status()?.text()?;
+        // Share the result of an identical lookup by another thread
+        let (_claim, _, joined) = self.context.flights.claim(&[ip]);
+        if let Some((_, flight)) = joined.first() {
+            flight.wait();
+            if let Some(detail) = self.context.get_joined(ip) {
+                return Ok(detail);
+            }
+        }
 
-        // Parse the response
-        let resp: serde_json::Value = serde_json::from_str(&raw_resp)?;
+        let request = self.context.get(format!("{}/{}", self.context.url, ip));
+        let raw_resp = match self.send(request) {
+            Ok(raw_resp) => raw_resp,
+            Err(e) => return self.context.stale_or(ip, e),
+        };
 
-        // Return if an error occurred
-        if let Some(e) = resp["error"].as_str() {
-            return Err(err!(IpRequestError, e));
+        let details = self.context.process_single(&raw_resp);
+        if let Err(e) = &details {
+            self.context.cache_negative(ip, e);
         }
+        details
+    }
+
+    /// Lookup a list of one or more IP addresses, with a separate result for each IP.
+    ///
+    /// Unlike [`lookup`](IpInfo::lookup), an invalid IP or a failed batch request only produces
+    /// errors for the IPs concerned, and the results for every other IP are still returned.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_each(&["8.8.8.8", "foo"]);
+    /// assert!(res["foo"].is_err());
+    /// ```
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, fields(ips = ips.len()))
+    )]
+    pub fn lookup_each(&self, ips: &[&str]) -> HashMap<String, Result<IpDetails, IpError>> {
+        let mut results = HashMap::new();
 
-        // Parse the results
-        let mut details: HashMap<String, IpDetails> = serde_json::from_str(&raw_resp)?;
-
-        // Add country_name and EU status to response
-        for detail in details.clone() {
-            let mut_details = details.get_mut(&detail.0).unwrap();
-            let country = &mut_details.country;
-            if !country.is_empty() {
-                let country_name = self.countries.get(&mut_details.country).unwrap();
-                mut_details.country_name = Some(country_name.to_string());
-                mut_details.is_eu = Some(self.eu.contains(country));
-                let country_flag = self.country_flags.get(&mut_details.country).unwrap();
-                mut_details.country_flag = Some(country_flag.to_owned());
-                let country_currency = self.country_currencies.get(&mut_details.country).unwrap();
-                mut_details.country_currency = Some(country_currency.to_owned());
-                let continent = self.continents.get(&mut_details.country).unwrap();
-                mut_details.continent = Some(continent.to_owned());
+        // Reject invalid IPs and recently reported errors individually
+        let mut valid = vec![];
+        for ip in ips {
+            match self.context.check_ip(ip) {
+                Ok(()) => valid.push(*ip),
+                Err(e) => {
+                    results.insert(ip.to_string(), Err(e));
+                }
             }
         }
 
-        // Update cache
-        details.iter().for_each(|x| {
-            self.cache.put(x.0.clone(), x.1.clone());
+        // Check for cache hits
+        let (hits, misses) = self.context.check_cache(&valid);
+        self.revalidate();
+
+        // Only query the IPs not already being looked up by another thread
+        let (claim, owned, joined) = self.context.flights.claim(&misses);
+
+        // Lookup cache misses
+        for (chunk, raw_resp) in self.send_batches(&owned) {
+            results.extend(self.context.process_batch_each(chunk, raw_resp));
+        }
+        drop(claim);
+
+        // Share the results of the joined lookups, querying again the IPs they failed
+        let (shared, failed) = self.join_flights(joined);
+        shared.into_iter().for_each(|x| {
+            results.insert(x.ip.clone(), Ok(x));
         });
+        for (chunk, raw_resp) in self.send_batches(&failed) {
+            results.extend(self.context.process_batch_each(chunk, raw_resp));
+        }
 
         // Add cache hits to the result
-        hits.iter().for_each(|x| {
-            details.insert(x.ip.clone(), x.clone());
+        hits.into_iter().for_each(|x| {
+            results.insert(x.ip.clone(), Ok(x));
         });
 
-        Ok(details)
+        results
     }
 
-    /// Construct API request headers.
-    fn construct_headers() -> HeaderMap {
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            USER_AGENT,
-            HeaderValue::from_str(&format!("IPinfoClient/Rust/{VERSION}")).unwrap(),
-        );
-        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
-        headers
+    /// Lookup a list of one or more parsed IP addresses.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::Ipv4Addr;
+    ///
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo
+    ///     .lookup_addrs(&[Ipv4Addr::new(8, 8, 8, 8)])
+    ///     .expect("should run");
+    /// ```
+    pub fn lookup_addrs<T: Into<IpAddr> + Copy>(
+        &self,
+        ips: &[T],
+    ) -> Result<HashMap<IpAddr, IpDetails>, IpError> {
+        let ips: Vec<String> = ips.iter().map(|ip| (*ip).into().to_string()).collect();
+        let ips: Vec<&str> = ips.iter().map(String::as_str).collect();
+
+        Ok(key_by_addr(self.lookup(&ips)?))
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Resolve a hostname to its A and AAAA records, then lookup all the resulting IP addresses.
+    ///
+    /// Fails with a `DnsError` if the hostname can't be resolved.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_host("example.com").expect("should run");
+    /// ```
+    pub fn lookup_host(&self, host: &str) -> Result<HashMap<IpAddr, IpDetails>, IpError> {
+        self.lookup_addrs(&resolve_host(host)?)
+    }
 
-    fn get_ipinfo_client() -> IpInfo {
-        dotenv::dotenv().ok();
-        IpInfo::new(IpInfoConfig {
-            token: Some(std::env::var("IPINFO_TOKEN").unwrap()),
-            timeout: Duration::from_secs(3),
-            cache_size: 100,
-            ..Default::default()
-        })
-        .expect("should construct")
+    /// Lookup every address of a CIDR range, such as `203.0.113.0/28`, in batch requests.
+    ///
+    /// Fails with an `InvalidIpError` if the range is malformed or has more addresses than
+    /// `max_cidr_hosts`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_cidr("8.8.8.0/28").expect("should run");
+    /// ```
+    pub fn lookup_cidr(&self, cidr: &str) -> Result<HashMap<IpAddr, IpDetails>, IpError> {
+        self.lookup_addrs(&expand_cidr(cidr, self.context.max_cidr_hosts)?)
     }
 
-    #[test]
-    fn ipinfo_config_defaults_reasonable() {
-        let ipinfo_config = IpInfoConfig::default();
+    /// Lookup a single parsed IP address using the single IP endpoint.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::Ipv4Addr;
+    ///
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_addr(Ipv4Addr::new(8, 8, 8, 8)).expect("should run");
+    /// ```
+    pub fn lookup_addr(&self, ip: impl Into<IpAddr>) -> Result<IpDetails, IpError> {
+        self.lookup_one(&ip.into().to_string())
+    }
 
-        assert_eq!(ipinfo_config.timeout, Duration::from_secs(3));
-        assert_eq!(ipinfo_config.cache_size, 100);
+    /// Lookup the country and ASN of a single IP address using the free IPinfo Lite API.
+    ///
+    /// Bogons are answered locally. Lite responses are not cached.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{IpInfo, IpInfoConfig};
+    ///
+    /// let config = IpInfoConfig {
+    ///     token: Some("my token".into()),
+    ///     ..Default::default()
+    /// };
+    /// let ipinfo = IpInfo::new(config).expect("should construct");
+    /// let res = ipinfo.lookup_lite("8.8.8.8").expect("should run");
+    /// println!("{} ({})", res.country, res.as_name);
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn lookup_lite(&self, ip: &str) -> Result<LiteDetails, IpError> {
+        validate_ip(ip)?;
+
+        if let Some(details) = bogon_details(ip) {
+            return Ok(LiteDetails {
+                ip: details.ip,
+                bogon: details.bogon,
+                ..Default::default()
+            });
+        }
+
+        let request = self
+            .context
+            .get(format!("{}/{}", self.context.lite_url, ip));
+        let raw_resp = self.send(request)?;
+
+        parse_response(&raw_resp)
     }
 
-    #[test]
-    fn request_headers_are_canonical() {
-        let headers = IpInfo::construct_headers();
+    /// Lookup the details of the IP address making the request.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_self().expect("should run");
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn lookup_self(&self) -> Result<IpDetails, IpError> {
+        let request = self.context.get(format!("{}/json", self.context.url));
+        let raw_resp = self.send(request)?;
 
-        assert_eq!(headers[USER_AGENT], format!("IPinfoClient/Rust/{VERSION}"));
-        assert_eq!(headers[CONTENT_TYPE], "application/json");
-        assert_eq!(headers[ACCEPT], "application/json");
+        self.context.process_single(&raw_resp)
     }
 
-    #[test]
-    fn request_single_ip() {
-        let mut ipinfo = get_ipinfo_client();
+    /// Lookup a single field of the details of an IP address.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{IpInfo, IpField};
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let country = ipinfo.lookup_field("8.8.8.8", IpField::Country).expect("should run");
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn lookup_field(&self, ip: &str, field: IpField) -> Result<String, IpError> {
+        validate_ips(&[ip])?;
 
-        let details = ipinfo.lookup(&["66.87.125.72"]).expect("should lookup");
+        let request = self
+            .context
+            .get(format!("{}/{}/{}", self.context.url, ip, field));
+        let raw_resp = self.send(request)?;
 
-        assert!(details.contains_key("66.87.125.72"));
-        assert_eq!(details.len(), 1);
+        Ok(raw_resp.trim().to_owned())
+    }
+
+    /// Lookup the details of an autonomous system.
+    ///
+    /// The AS number may be given with or without its `AS` prefix. Fails with a `ParseError` if it
+    /// isn't an AS number.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.lookup_asn("AS7922").expect("should run");
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn lookup_asn(&self, asn: &str) -> Result<AsnResponse, IpError> {
+        let asn = normalize_asn(asn)?;
+
+        // Check for a cache hit
+        if let Some(detail) = self.context.get_cached_asn(&asn) {
+            return Ok(detail);
+        }
+
+        let request = self
+            .context
+            .get(format!("{}/{}/json", self.context.url, asn));
+        let raw_resp = self.send(request)?;
+
+        self.context.process_asn(&raw_resp)
+    }
+
+    /// Get the IP ranges announced for a domain.
+    ///
+    /// Fails with a `ParseError` if the domain isn't a hostname.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.get_ranges("comcast.net").expect("should run");
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn get_ranges(&self, domain: &str) -> Result<RangesResponse, IpError> {
+        validate_domain(domain)?;
+
+        let request = self
+            .context
+            .get(format!("{}/ranges/{}", self.context.url, domain));
+        let raw_resp = self.send(request)?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Get a page of the domains hosted on an IP address.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let res = ipinfo.get_domains("1.1.1.1", 0, 100).expect("should run");
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn get_domains(&self, ip: &str, page: u32, limit: u32) -> Result<DomainsDetails, IpError> {
+        validate_ips(&[ip])?;
+
+        let request = self.context.get(format!(
+            "{}/domains/{}?page={}&limit={}",
+            self.context.url, ip, page, limit
+        ));
+        let raw_resp = self.send(request)?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Create a map of IP addresses, getting the URL of a shareable report visualizing them.
+    ///
+    /// Fails with an `InvalidIpError` if any of the IP addresses is invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let map = ipinfo.create_map(&["8.8.8.8", "1.1.1.1"]).expect("should run");
+    /// println!("{}", map.report_url);
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn create_map(&self, ips: &[&str]) -> Result<MapResponse, IpError> {
+        validate_ips(ips)?;
+
+        let request = self.context.post(
+            format!("{}/tools/map?cli=1", self.context.url),
+            json!(ips).to_string(),
+        );
+        let raw_resp = self.send(request)?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Verify the configured token, getting its usage and the plan features available to it.
+    ///
+    /// Fails with an `UnauthorizedError` if no token is configured or the API rejects it, so
+    /// services can check their configuration at startup.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{IpInfo, IpInfoConfig};
+    ///
+    /// let config = IpInfoConfig {
+    ///     token: Some("my token".into()),
+    ///     ..Default::default()
+    /// };
+    /// let ipinfo = IpInfo::new(config).expect("should construct");
+    /// let details = ipinfo.verify_token().expect("token should be valid");
+    /// println!("{} requests remaining", details.requests.remaining);
+    /// ```
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip(self)))]
+    pub fn verify_token(&self) -> Result<TokenDetails, IpError> {
+        let request = self.context.token_request()?;
+        let raw_resp = self.send(request)?;
+
+        parse_response(&raw_resp)
+    }
+
+    /// Populate the cache with the details of IP addresses in a background thread.
+    ///
+    /// Cached IPs and bogons are skipped. The returned handle can be joined to wait for the
+    /// outcome, or dropped to let the prefetch run detached.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let handle = ipinfo.prefetch(&["8.8.8.8", "1.1.1.1"]);
+    /// ```
+    pub fn prefetch(&self, ips: &[&str]) -> thread::JoinHandle<Result<(), IpError>> {
+        self.spawn_lookup(ips, false)
+    }
+
+    /// Get the rate limit reported by the last API response that had one.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// if let Some(rate_limit) = ipinfo.last_rate_limit() {
+    ///     println!("{:?} requests remaining", rate_limit.remaining);
+    /// }
+    /// ```
+    pub fn last_rate_limit(&self) -> Option<RateLimit> {
+        self.context.last_rate_limit()
+    }
+
+    /// Get the usage statistics of the IP details cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let stats = ipinfo.cache_stats();
+    /// assert_eq!(stats.hits, 0);
+    /// ```
+    pub fn cache_stats(&self) -> CacheStats {
+        self.context.cache_stats()
+    }
+
+    /// Get the country data used to enrich IP details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let countries = ipinfo.country_data();
+    /// let eu = countries.is_eu("DE");
+    /// ```
+    pub fn country_data(&self) -> CountryData {
+        self.context.countries()
+    }
+
+    /// Get the name of a country from its code, in the configured locale.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let name = ipinfo.get_country_name("DE");
+    /// ```
+    pub fn get_country_name(&self, code: &str) -> Option<String> {
+        self.context
+            .countries()
+            .country_name(code)
+            .map(str::to_owned)
+    }
+
+    /// Check whether a country is a member of the EU from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let eu = ipinfo.is_eu("DE");
+    /// ```
+    pub fn is_eu(&self, code: &str) -> bool {
+        self.context.countries().is_eu(code)
+    }
+
+    /// Get the flag of a country from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let flag = ipinfo.get_country_flag("DE");
+    /// ```
+    pub fn get_country_flag(&self, code: &str) -> Option<CountryFlag> {
+        self.context.countries().country_flag(code).cloned()
+    }
+
+    /// Get the currency of a country from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let currency = ipinfo.get_currency("DE");
+    /// ```
+    pub fn get_currency(&self, code: &str) -> Option<CountryCurrency> {
+        self.context.countries().country_currency(code).cloned()
+    }
+
+    /// Get the continent of a country from its code.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// let continent = ipinfo.get_continent("DE");
+    /// ```
+    pub fn get_continent(&self, code: &str) -> Option<Continent> {
+        self.context.countries().continent(code).cloned()
+    }
+
+    /// Read the country data files set by the `*_file_path` options again, so updated data is
+    /// used without restarting.
+    ///
+    /// The country data is shared with clones of this client. It is left unchanged if any file
+    /// can't be loaded, failing with an `AssetLoadError`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{IpInfo, IpInfoConfig};
+    ///
+    /// let config = IpInfoConfig {
+    ///     countries_file_path: Some("countries.json".to_owned()),
+    ///     ..Default::default()
+    /// };
+    /// let ipinfo = IpInfo::new(config).expect("should construct");
+    /// ipinfo.reload_assets().expect("should reload");
+    /// ```
+    pub fn reload_assets(&self) -> Result<(), IpError> {
+        self.context.reload_assets()
+    }
+
+    /// Remove the cached details of an IP address, if any.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// ipinfo.cache_remove("8.8.8.8");
+    /// ```
+    pub fn cache_remove(&self, ip: &str) {
+        self.context.cache_remove(ip);
+    }
+
+    /// Remove every cached IP address and ASN.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// ipinfo.cache_clear();
+    /// ```
+    pub fn cache_clear(&self) {
+        self.context.cache_clear();
+    }
+
+    /// Write the cached IPs to storage, such as the snapshot of the
+    /// [`cache_file_path`](IpInfoConfig::cache_file_path) file.
+    ///
+    /// Does nothing for caches that don't persist their entries lazily.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ipinfo::IpInfo;
+    ///
+    /// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+    /// ipinfo.cache_flush().expect("should flush");
+    /// ```
+    pub fn cache_flush(&self) -> Result<(), IpError> {
+        self.context.cache_flush()
+    }
+
+    /// Wait for the joined lookups, splitting their IPs into shared results and failures.
+    fn join_flights<'a>(
+        &self,
+        joined: Vec<(&'a str, Arc<Flight>)>,
+    ) -> (Vec<IpDetails>, Vec<&'a str>) {
+        let mut shared = vec![];
+        let mut failed = vec![];
+
+        for (ip, flight) in joined {
+            flight.wait();
+            match self.context.get_joined(ip) {
+                Some(detail) => shared.push(detail),
+                None => failed.push(ip),
+            }
+        }
+
+        (shared, failed)
+    }
+
+    /// Refresh the stale cache entries served meanwhile in the background.
+    fn revalidate(&self) {
+        let stale = self.context.take_stale();
+        if !stale.is_empty() {
+            let ips: Vec<&str> = stale.iter().map(String::as_str).collect();
+            self.spawn_lookup(&ips, true);
+        }
+    }
+
+    /// Lookup IPs in the background with a client sharing the cache, bypassing cached entries
+    /// when `fresh`.
+    fn spawn_lookup(&self, ips: &[&str], fresh: bool) -> thread::JoinHandle<Result<(), IpError>> {
+        let worker = IpInfo {
+            transport: self.transport.clone(),
+            context: Arc::new(self.context.fork()),
+        };
+        let ips: Vec<String> = ips.iter().map(|ip| ip.to_string()).collect();
+
+        thread::spawn(move || {
+            let ips: Vec<&str> = ips.iter().map(String::as_str).collect();
+            if !fresh {
+                return worker.lookup(&ips).map(|_| ());
+            }
+
+            let result = worker.lookup_fresh(&ips).map(|_| ());
+            worker.context.finish_revalidation(&ips);
+            result
+        })
+    }
+
+    /// Send batch requests for the IPs in chunks the batch API accepts, a few chunks at a time.
+    fn send_batches<'a>(
+        &self,
+        misses: &'a [&'a str],
+    ) -> Vec<(&'a [&'a str], Result<String, IpError>)> {
+        let chunks: Vec<&[&str]> = misses.chunks(BATCH_MAX_SIZE).collect();
+
+        // Refuse to send unauthenticated batch requests if a token is required
+        if let Err(e) = self.context.check_token() {
+            return chunks
+                .into_iter()
+                .map(|chunk| (chunk, Err(e.clone())))
+                .collect();
+        }
+        let mut responses = Vec::with_capacity(chunks.len());
+
+        for group in chunks.chunks(self.context.max_concurrency) {
+            thread::scope(|s| {
+                let handles: Vec<_> = group
+                    .iter()
+                    .map(|chunk| (*chunk, s.spawn(|| self.send(self.batch_request(chunk)))))
+                    .collect();
+                responses.extend(handles.into_iter().map(|(chunk, handle)| {
+                    (
+                        chunk,
+                        handle.join().expect("batch request should not panic"),
+                    )
+                }));
+            });
+        }
+
+        responses
+    }
+
+    /// Construct a batch API request for a chunk of IPs.
+    fn batch_request(&self, chunk: &[&str]) -> HttpRequest {
+        self.context.post(
+            format!("{}/batch", self.context.url),
+            json!(chunk).to_string(),
+        )
+    }
+
+    /// Send an API request and acquire the raw response body, retrying transient failures.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(method = %request.method, url = %request.url)
+        )
+    )]
+    fn send(&self, request: HttpRequest) -> Result<String, IpError> {
+        let mut attempts = Attempts::new(request);
+        loop {
+            let request = self.context.start_attempt(&attempts)?;
+            let start = Instant::now();
+            let response = self.transport.send(request.clone());
+
+            match self
+                .context
+                .finish_attempt(&mut attempts, &request, response, start.elapsed())
+            {
+                Next::Done(result) => return result,
+                Next::Retry(Some(wait)) => thread::sleep(wait),
+                Next::Retry(None) => {}
+            }
+        }
+    }
+
+    /// Enrich IP details obtained without the API, as the details answered by the API are.
+    #[cfg(feature = "mmdb")]
+    pub(crate) fn enrich(&self, details: &mut IpDetails) {
+        self.context.enrich(details);
+    }
+
+    /// Enrich IP details with the country data only, if enabled.
+    #[cfg(feature = "mmdb")]
+    pub(crate) fn enrich_countries(&self, details: &mut IpDetails) {
+        self.context.enrich_countries(details);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Continent, CountryCurrency, CountryFlag, Interceptor, Metrics};
+
+    use std::{
+        io::{Read, Write},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    fn get_ipinfo_client() -> IpInfo {
+        dotenv::dotenv().ok();
+        IpInfo::new(IpInfoConfig {
+            token: Some(std::env::var("IPINFO_TOKEN").unwrap().into()),
+            timeout: Duration::from_secs(3),
+            cache_size: 100,
+            ..Default::default()
+        })
+        .expect("should construct")
+    }
+
+    #[test]
+    fn ipinfo_config_defaults_reasonable() {
+        let ipinfo_config = IpInfoConfig::default();
+
+        assert_eq!(ipinfo_config.base_url, "https://ipinfo.io");
+        assert_eq!(ipinfo_config.timeout, Duration::from_secs(3));
+        assert_eq!(ipinfo_config.cache_size, 100);
+        assert_eq!(ipinfo_config.cache_ttl, None);
+        assert_eq!(ipinfo_config.max_concurrency, 4);
+        assert!(!ipinfo_config.danger_accept_invalid_certs);
+    }
+
+    #[test]
+    fn request_single_ip() {
+        let ipinfo = get_ipinfo_client();
+
+        let details = ipinfo.lookup(&["66.87.125.72"]).expect("should lookup");
+
+        assert!(details.contains_key("66.87.125.72"));
+        assert_eq!(details.len(), 1);
+    }
+
+    #[test]
+    fn request_lookup_one() {
+        let ipinfo = get_ipinfo_client();
+
+        let details = ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+
+        assert_eq!(details.ip, "8.8.8.8");
+        assert_eq!(details.country, "US");
+        #[cfg(feature = "bundled-assets")]
+        assert_eq!(details.country_name, Some("United States".to_owned()));
+    }
+
+    #[test]
+    fn request_lookup_self() {
+        let ipinfo = get_ipinfo_client();
+
+        let details = ipinfo.lookup_self().expect("should lookup");
+
+        assert!(!details.ip.is_empty());
+    }
+
+    #[test]
+    fn request_lookup_field() {
+        let ipinfo = get_ipinfo_client();
+
+        let country = ipinfo
+            .lookup_field("8.8.8.8", IpField::Country)
+            .expect("should lookup");
+
+        assert_eq!(country, "US");
+    }
+
+    #[test]
+    fn request_lookup_asn() {
+        let ipinfo = get_ipinfo_client();
+
+        let details = ipinfo.lookup_asn("AS7922").expect("should lookup");
+
+        assert_eq!(details.asn, "AS7922");
+        assert_eq!(details.country, "US");
+    }
+
+    #[test]
+    fn request_get_domains() {
+        let ipinfo = get_ipinfo_client();
+
+        let details = ipinfo.get_domains("1.1.1.1", 1, 5).expect("should lookup");
+
+        assert_eq!(details.page, Some(1));
+        assert!(details.domains.len() <= 5);
+    }
+
+    #[test]
+    fn lookup_invalid_ip() {
+        let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+
+        assert_eq!(
+            ipinfo.lookup(&["8.8.8.8", "foo"]).err().unwrap().kind(),
+            crate::IpErrorKind::InvalidIpError
+        );
+        assert_eq!(
+            ipinfo.lookup_one("8.8.8.256").err().unwrap().kind(),
+            crate::IpErrorKind::InvalidIpError
+        );
+    }
+
+    /// Serve a single JSON response on a local port, returning its base URL.
+    fn serve_once(body: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        url
+    }
+
+    #[test]
+    fn base_url_is_used() {
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            base_url: serve_once(r#"{"ip": "8.8.8.8", "country": "US"}"#),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+        assert_eq!(details.country, "US");
+        #[cfg(feature = "bundled-assets")]
+        assert_eq!(details.country_name, Some("United States".to_owned()));
+
+        // The server is gone, so this must be answered from the cache
+        assert!(ipinfo.lookup_one("8.8.8.8").is_ok());
+    }
+
+    #[test]
+    fn proxy_is_used() {
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            base_url: "http://ipinfo.invalid".to_owned(),
+            proxy_url: Some(serve_once(r#"{"ip": "8.8.8.8"}"#)),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        assert!(ipinfo.lookup_one("8.8.8.8").is_ok());
+    }
+
+    #[test]
+    fn invalid_base_url_is_rejected() {
+        let result = IpInfo::new(IpInfoConfig {
+            base_url: "not a url".to_owned(),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            result.err().unwrap().kind(),
+            crate::IpErrorKind::InvalidConfigError
+        );
+    }
+
+    /// Transport answering every request with the same response, recording the requests.
+    struct Recorder {
+        status: reqwest::StatusCode,
+        body: &'static str,
+        requests: std::sync::Mutex<Vec<HttpRequest>>,
+    }
+
+    impl Recorder {
+        fn new(status: u16, body: &'static str) -> Arc<Self> {
+            Arc::new(Self {
+                status: reqwest::StatusCode::from_u16(status).unwrap(),
+                body,
+                requests: Default::default(),
+            })
+        }
+    }
+
+    impl Transport for Recorder {
+        fn send(&self, request: HttpRequest) -> Result<crate::HttpResponse, IpError> {
+            self.requests.lock().unwrap().push(request);
+            Ok(crate::HttpResponse {
+                status: self.status,
+                headers: Default::default(),
+                body: self.body.to_owned(),
+            })
+        }
+    }
+
+    #[test]
+    fn requests_without_token_are_not_authorized() {
+        let transport = Recorder::new(200, r#"{"ip": "8.8.8.8"}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+
+        let requests = transport.requests.lock().unwrap();
+        assert!(!requests[0]
+            .headers
+            .contains_key(reqwest::header::AUTHORIZATION));
+
+        let transport = Recorder::new(200, r#"{"ip": "8.8.8.8"}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            token: Some("token".into()),
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(
+            requests[0].headers[reqwest::header::AUTHORIZATION],
+            "Bearer token"
+        );
+    }
+
+    #[test]
+    fn ranges_domains_are_validated() {
+        let transport = Recorder::new(
+            200,
+            r#"{"domain": "comcast.net", "num_ranges": "1", "ranges": ["23.24.240.0/29"]}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        for domain in [
+            "../batch",
+            "x?token=secret",
+            "a/b",
+            "",
+            "comcast..net",
+            "ex ample.com",
+        ] {
+            let err = ipinfo.get_ranges(domain).expect_err(domain);
+            assert_eq!(err.kind(), crate::IpErrorKind::ParseError);
+        }
+        assert!(transport.requests.lock().unwrap().is_empty());
+
+        let ranges = ipinfo.get_ranges("comcast.net").expect("should get ranges");
+        assert_eq!(ranges.num_ranges, 1);
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url, "https://ipinfo.io/ranges/comcast.net");
+    }
+
+    #[test]
+    fn cache_file_survives_restart() {
+        let path = std::env::temp_dir().join(format!("ipinfo-restart-{}.json", std::process::id()));
+        let config = || IpInfoConfig {
+            cache_file_path: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+
+        let transport = Recorder::new(200, r#"{"ip": "8.8.8.8", "city": "Mountain View"}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport),
+            ..config()
+        })
+        .expect("should construct");
+        ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+        ipinfo.cache_flush().expect("should flush");
+
+        // Simulate the process being killed, without dropping the cache
+        std::mem::forget(ipinfo);
+
+        let transport = Recorder::new(500, "");
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..config()
+        })
+        .expect("should construct");
+        let details = ipinfo.lookup_one("8.8.8.8").expect("should hit the cache");
+        assert_eq!(details.city, "Mountain View");
+        assert!(transport.requests.lock().unwrap().is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn asn_inputs_are_validated() {
+        let transport = Recorder::new(
+            200,
+            r#"{"asn": "AS7922", "name": "Comcast Cable Communications, LLC", "country": "US"}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        for asn in ["8.8.8.8", "../me", "AS", "AS7922/json?token=x"] {
+            let err = ipinfo.lookup_asn(asn).expect_err(asn);
+            assert_eq!(err.kind(), crate::IpErrorKind::ParseError);
+        }
+        assert!(transport.requests.lock().unwrap().is_empty());
+
+        let details = ipinfo.lookup_asn("7922").expect("should lookup");
+        assert_eq!(details.asn, "AS7922");
+        assert!(ipinfo.lookup_asn("as7922").is_ok());
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url, "https://ipinfo.io/AS7922/json");
+    }
+
+    #[test]
+    fn batch_errors_are_negatively_cached() {
+        let transport = Recorder::new(
+            200,
+            r#"{"8.8.8.8": {"error": "Not found"}, "1.1.1.1": {"ip": "1.1.1.1"}}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            negative_cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo
+            .lookup(&["8.8.8.8", "1.1.1.1"])
+            .expect("should lookup");
+        assert_eq!(details.keys().collect::<Vec<_>>(), ["1.1.1.1"]);
+
+        // Both IPs are now answered without a request, from the negative and details caches
+        let details = ipinfo
+            .lookup(&["8.8.8.8", "1.1.1.1"])
+            .expect("should lookup");
+        assert_eq!(details.len(), 1);
+        assert!(ipinfo.lookup_one("8.8.8.8").is_err());
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn ips_with_errors_are_missing_from_lookup() {
+        let transport = Recorder::new(
+            200,
+            r#"{"8.8.8.8": {"error": "Not found"}, "1.1.1.1": {"ip": "1.1.1.1"}}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        // Without negative caching, the IP is still left out rather than mapped to its error
+        let details = ipinfo
+            .lookup(&["8.8.8.8", "1.1.1.1"])
+            .expect("should lookup");
+        assert!(!details.contains_key("8.8.8.8"));
+        assert!(details.contains_key("1.1.1.1"));
+
+        let results = ipinfo.lookup_each(&["8.8.8.8", "1.1.1.1"]);
+        assert_eq!(
+            results["8.8.8.8"].as_ref().err().unwrap().kind(),
+            crate::IpErrorKind::IpRequestError
+        );
+        assert!(results["1.1.1.1"].is_ok());
+    }
+
+    #[test]
+    fn transport_is_used() {
+        let transport = Recorder::new(200, r#"{"ip": "8.8.8.8", "country": "US"}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+        assert_eq!(details.country, "US");
+        #[cfg(feature = "bundled-assets")]
+        assert_eq!(details.country_name, Some("United States".to_owned()));
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].method, reqwest::Method::GET);
+        assert_eq!(requests[0].url, "https://ipinfo.io/8.8.8.8");
+    }
+
+    #[test]
+    fn transport_error_statuses_are_classified() {
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(Recorder::new(403, "forbidden")),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let err = ipinfo.lookup_one("8.8.8.8").unwrap_err();
+        assert_eq!(err.kind(), crate::IpErrorKind::UnauthorizedError);
+        assert_eq!(err.body(), Some("forbidden"));
+        assert_eq!(err.url(), Some("https://ipinfo.io/8.8.8.8"));
+    }
+
+    #[test]
+    fn token_can_be_overridden_per_call() {
+        let transport = Recorder::new(200, r#"{"8.8.8.8": {"ip": "8.8.8.8"}}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            token: Some("default".into()),
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        ipinfo
+            .lookup_with_token(&["8.8.8.8"], "tenant")
+            .expect("should lookup");
+        ipinfo
+            .lookup_with_token(&["8.8.8.8"], "tenant")
+            .expect("should hit the cache");
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].headers["authorization"], "Bearer tenant");
+        assert_eq!(ipinfo.cache_stats().hits, 1);
+        assert_eq!(
+            ipinfo
+                .lookup_with_token(&["8.8.8.8"], "bad\ntoken")
+                .unwrap_err()
+                .kind(),
+            crate::IpErrorKind::InvalidConfigError
+        );
+    }
+
+    #[test]
+    fn per_token_lookups_share_the_negative_cache() {
+        let transport = Recorder::new(200, r#"{"8.8.8.8": {"error": "Not found"}}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            negative_cache_ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        for _ in 0..2 {
+            let details = ipinfo
+                .lookup_with_token(&["8.8.8.8"], "tenant")
+                .expect("should lookup");
+            assert!(details.is_empty());
+        }
+
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+        assert!(ipinfo.lookup_one("8.8.8.8").is_err());
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn lookups_run_concurrently_from_shared_client() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<IpInfo>();
+
+        let transport = Recorder::new(200, r#"{"ip": "8.8.8.8"}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| ipinfo.lookup_one("8.8.8.8").expect("should lookup"));
+            }
+        });
+
+        let stats = ipinfo.cache_stats();
+        assert_eq!(stats.hits + stats.misses, 4);
+        assert!(!transport.requests.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn clones_share_the_cache() {
+        let transport = Recorder::new(200, r#"{"ip": "8.8.8.8"}"#);
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let clone = ipinfo.clone();
+        thread::spawn(move || clone.lookup_one("8.8.8.8").expect("should lookup"))
+            .join()
+            .unwrap();
+        ipinfo.lookup_one("8.8.8.8").expect("should hit the cache");
+
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+        assert_eq!(ipinfo.cache_stats().hits, 1);
+        assert_eq!(ipinfo.cache_stats().misses, 1);
+    }
+
+    #[test]
+    fn lite_api_is_used() {
+        let transport = Recorder::new(
+            200,
+            r#"{"ip": "8.8.8.8", "asn": "AS15169", "country_code": "US"}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo.lookup_lite("8.8.8.8").expect("should lookup");
+        assert_eq!(details.asn, "AS15169");
+        assert_eq!(details.country_code, "US");
+        assert_eq!(
+            transport.requests.lock().unwrap()[0].url,
+            "https://api.ipinfo.io/lite/8.8.8.8"
+        );
+
+        let details = ipinfo.lookup_lite("10.0.0.1").expect("should lookup");
+        assert_eq!(details.bogon, Some(true));
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn map_is_created() {
+        let transport = Recorder::new(
+            200,
+            r#"{"status": "Report Generated", "reportUrl": "https://ipinfo.io/tools/map/abc"}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let map = ipinfo
+            .create_map(&["8.8.8.8", "1.1.1.1"])
+            .expect("should create");
+        assert_eq!(map.report_url, "https://ipinfo.io/tools/map/abc");
+
+        let requests = transport.requests.lock().unwrap();
+        assert_eq!(requests[0].url, "https://ipinfo.io/tools/map?cli=1");
+        assert_eq!(
+            requests[0].body.as_deref(),
+            Some(r#"["8.8.8.8","1.1.1.1"]"#)
+        );
+        drop(requests);
+
+        let err = ipinfo.create_map(&["8.8.8.8", "foo"]).err().unwrap();
+        assert_eq!(err.kind(), crate::IpErrorKind::InvalidIpError);
+    }
+
+    #[test]
+    fn token_is_verified() {
+        let transport = Recorder::new(
+            200,
+            r#"{"requests": {"remaining": 10}, "features": {"core": {}}}"#,
+        );
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            token: Some("token".into()),
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo.verify_token().expect("should verify");
+        assert_eq!(details.requests.remaining, 10);
+        assert!(details.has_feature("core"));
+        assert_eq!(
+            transport.requests.lock().unwrap()[0].url,
+            "https://ipinfo.io/me"
+        );
+
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            token: Some("token".into()),
+            transport: Some(Recorder::new(403, "unknown token")),
+            ..Default::default()
+        })
+        .expect("should construct");
+        assert_eq!(
+            ipinfo.verify_token().unwrap_err().kind(),
+            crate::IpErrorKind::UnauthorizedError
+        );
+
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        assert_eq!(
+            ipinfo.verify_token().unwrap_err().kind(),
+            crate::IpErrorKind::UnauthorizedError
+        );
+        assert_eq!(transport.requests.lock().unwrap().len(), 1);
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        responses: AtomicUsize,
+        errors: AtomicUsize,
+    }
+
+    impl Interceptor for Counter {
+        fn on_request(&self, request: &mut HttpRequest) {
+            request.headers.insert(
+                "x-tenant",
+                reqwest::header::HeaderValue::from_static("acme"),
+            );
+        }
+
+        fn on_response(&self, _: &HttpRequest, _: &crate::HttpResponse) {
+            self.responses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_error(&self, _: &HttpRequest, _: &IpError) {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn interceptors_are_called() {
+        let transport = Recorder::new(500, "oops");
+        let counter = Arc::new(Counter::default());
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(transport.clone()),
+            interceptors: vec![counter.clone()],
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        assert!(ipinfo.lookup_one("8.8.8.8").is_err());
+
+        assert_eq!(
+            transport.requests.lock().unwrap()[0].headers["x-tenant"],
+            "acme"
+        );
+        assert_eq!(counter.responses.load(Ordering::Relaxed), 1);
+        assert_eq!(counter.errors.load(Ordering::Relaxed), 1);
+    }
+
+    #[derive(Default)]
+    struct Telemetry {
+        requests: AtomicUsize,
+        hits: AtomicUsize,
+        misses: AtomicUsize,
+        errors: std::sync::Mutex<Vec<crate::IpErrorKind>>,
+    }
+
+    impl Metrics for Telemetry {
+        fn record_request(&self, status: Option<reqwest::StatusCode>) {
+            assert!(status.is_some());
+            self.requests.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn record_cache_hit(&self) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn record_cache_miss(&self) {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn record_error(&self, kind: crate::IpErrorKind) {
+            self.errors.lock().unwrap().push(kind);
+        }
+    }
+
+    #[test]
+    fn metrics_are_recorded() {
+        let telemetry = Arc::new(Telemetry::default());
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(Recorder::new(200, r#"{"ip": "8.8.8.8"}"#)),
+            metrics: Some(telemetry.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+        ipinfo.lookup_one("8.8.8.8").expect("should lookup");
+        assert_eq!(telemetry.requests.load(Ordering::Relaxed), 1);
+        assert_eq!(telemetry.misses.load(Ordering::Relaxed), 1);
+        assert_eq!(telemetry.hits.load(Ordering::Relaxed), 1);
+
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            transport: Some(Recorder::new(401, "")),
+            metrics: Some(telemetry.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        assert!(ipinfo.lookup_one("8.8.8.8").is_err());
+        assert_eq!(
+            *telemetry.errors.lock().unwrap(),
+            vec![crate::IpErrorKind::UnauthorizedError]
+        );
+    }
+
+    /// Transport rate limiting every request authorized with the token `a`.
+    struct RateLimited {
+        tokens: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl Transport for RateLimited {
+        fn send(&self, request: HttpRequest) -> Result<crate::HttpResponse, IpError> {
+            let token = request.headers[reqwest::header::AUTHORIZATION]
+                .to_str()
+                .unwrap()
+                .to_owned();
+            self.tokens.lock().unwrap().push(token.clone());
+
+            let status = match token.as_str() {
+                "Bearer a" => reqwest::StatusCode::TOO_MANY_REQUESTS,
+                _ => reqwest::StatusCode::OK,
+            };
+            Ok(crate::HttpResponse {
+                status,
+                headers: Default::default(),
+                body: r#"{"ip": "8.8.8.8"}"#.to_owned(),
+            })
+        }
+    }
+
+    #[test]
+    fn token_pool_rotates_and_fails_over() {
+        let transport = Arc::new(RateLimited {
+            tokens: Default::default(),
+        });
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            token: Some("a".into()),
+            token_pool: vec!["b".into(), "c".into()],
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        assert!(ipinfo.lookup_one("8.8.8.8").is_ok());
+        ipinfo.cache_clear();
+        assert!(ipinfo.lookup_one("8.8.8.8").is_ok());
+        assert_eq!(
+            *transport.tokens.lock().unwrap(),
+            ["Bearer a", "Bearer b", "Bearer c"]
+        );
+
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            token: Some("a".into()),
+            transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+        assert_eq!(
+            ipinfo.lookup_one("8.8.8.8").unwrap_err().kind(),
+            crate::IpErrorKind::RateLimitExceededError
+        );
+    }
+
+    #[test]
+    fn invalid_token_is_rejected() {
+        let result = IpInfo::new(IpInfoConfig {
+            token: Some("tok\nen".into()),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            result.err().unwrap().kind(),
+            crate::IpErrorKind::InvalidConfigError
+        );
+    }
+
+    #[test]
+    fn prefetch_runs_in_background() {
+        let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+
+        let handle = ipinfo.prefetch(&["10.0.0.1"]);
+        assert!(handle.join().unwrap().is_ok());
+
+        let handle = ipinfo.prefetch(&["8.8.8.256"]);
+        assert!(handle.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn lookup_all_hits_sends_no_request() {
+        let ipinfo = IpInfo::new(IpInfoConfig {
+            timeout: Duration::from_millis(1),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo
+            .lookup(&["10.0.0.1", "192.168.1.1"])
+            .expect("should lookup");
+
+        assert_eq!(details.len(), 2);
+        assert_eq!(details["10.0.0.1"].bogon, Some(true));
+    }
+
+    #[test]
+    fn lookup_host_looks_up_resolved_addresses() {
+        let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+
+        let details = ipinfo.lookup_host("127.0.0.1").expect("should lookup");
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        assert_eq!(details[&ip].bogon, Some(true));
+
+        let err = ipinfo.lookup_host("host.invalid").err().unwrap();
+        assert_eq!(err.kind(), crate::IpErrorKind::DnsError);
+    }
+
+    #[test]
+    fn lookup_cidr_looks_up_every_address() {
+        let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+
+        let details = ipinfo.lookup_cidr("10.0.0.0/29").expect("should lookup");
+        assert_eq!(details.len(), 8);
+        assert!(details.values().all(|details| details.bogon == Some(true)));
+
+        let err = ipinfo.lookup_cidr("10.0.0.0/16").err().unwrap();
+        assert_eq!(err.kind(), crate::IpErrorKind::InvalidIpError);
+    }
+
+    #[test]
+    fn lookup_each_reports_invalid_ips_individually() {
+        let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+
+        let details = ipinfo.lookup_each(&["10.0.0.1", "foo"]);
+
+        assert_eq!(details["10.0.0.1"].as_ref().unwrap().bogon, Some(true));
+        assert_eq!(
+            details["foo"].as_ref().err().unwrap().kind(),
+            crate::IpErrorKind::InvalidIpError
+        );
     }
 
     #[test]
     fn request_single_ip_no_token() {
-        let mut ipinfo = IpInfo::new(Default::default()).expect("should construct");
+        let ipinfo = IpInfo::new(Default::default()).expect("should construct");
 
         assert_eq!(
             ipinfo.lookup(&["8.8.8.8"]).err().unwrap().kind(),
@@ -317,7 +1780,7 @@ mod tests {
 
     #[test]
     fn request_multiple_ip() {
-        let mut ipinfo = get_ipinfo_client();
+        let ipinfo = get_ipinfo_client();
 
         let details = ipinfo
             .lookup(&["8.8.8.8", "4.2.2.4"])
@@ -373,7 +1836,7 @@ mod tests {
 
     #[test]
     fn request_cache_miss_and_hit() {
-        let mut ipinfo = get_ipinfo_client();
+        let ipinfo = get_ipinfo_client();
 
         // Populate the cache with 8.8.8.8
         let details = ipinfo.lookup(&["8.8.8.8"]).expect("should lookup");
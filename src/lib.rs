@@ -24,23 +24,58 @@
 //!
 //! ## Features
 //!
-//! * Smart LRU cache for cost and quota savings.
+//! * Smart LRU cache for cost and quota savings, optionally persisted to a file, or any
+//!   [`Cache`] implementation such as moka or Redis (with the `moka` and `redis` features).
 //! * Structured and type checked query results.
+//! * Typed time zones of IP details (`IpDetails::tz`), with the `chrono-tz` feature.
 //! * Bulk IP address lookup using IPinfo batch API.
+//! * Client-side bogon detection, so private and reserved IPs don't spend quota.
+//! * Configurable retries with exponential backoff and jitter.
+//! * Optional circuit breaker failing fast while the API is unavailable, and fallback to stale
+//!   cached details meanwhile.
+//! * Async client (`AsyncIpInfo`), runtime-agnostic given a custom `AsyncTransport`, and
+//!   usable on WASM.
+//! * Micro-batching of single IP lookups from many threads or tasks (`Batcher`).
+//! * Pluggable HTTP transport (`Transport` and `AsyncTransport`), using reqwest by default.
+//! * Interceptor hooks around every HTTP request (`Interceptor`), telemetry hooks (`Metrics`),
+//!   and `tracing` instrumentation (with the `tracing` feature).
+//! * Country names, EU status, flags, currencies and continents, also usable offline without a
+//!   client (`CountryData`), and custom enrichment of IP details (`Enricher`).
+//! * Export of lookup results to CSV (`export::to_csv`) and local summaries by country, ASN,
+//!   company and privacy type (`summarize`).
+//! * Offline lookups in the downloadable IPinfo MMDB databases (`Database`), and verified
+//!   downloads and refreshes of their snapshots (`DatabaseManager`), with the `mmdb` feature.
+//!   `HybridResolver` answers from a database first, calling the API only when needed.
+//! * `tower::Service` implementation of the async client, for composition with tower middleware
+//!   (`LookupRequest`), with the `tower` feature.
+//! * Tower middleware adding the details of the client IP to HTTP requests, for axum and other
+//!   tower based servers (`IpInfoLayer`), with the `axum` feature, and client IP detection from
+//!   reverse proxy headers for the middleware of other frameworks (`forwarded_ip`).
+//! * Actix-web middleware adding the details of the client IP to requests (`IpInfoMiddleware`),
+//!   with the `actix` feature.
+//! * Warp filter extracting the details of the client IP of requests (`ip_details_filter`), with
+//!   the `warp` feature.
+//! * Rocket request guard for the details of the client IP of requests (`IpDetails`), sharing
+//!   the client managed by `IpInfoFairing`, with the `rocket` feature.
+//! * C API of the blocking client, in the `ipinfo-ffi` crate of the repository.
+//! * Python bindings of the blocking client, in the `ipinfo-python` crate of the repository.
 //! ## Example
 //!
 //! ```no_run
 //! use ipinfo::{IpInfo, IpInfoConfig};
 //!
 //! // Setup token and other configurations.
-//! let config = IpInfoConfig { token: Some("my token".to_string()), ..Default::default() };
+//! let config = IpInfoConfig { token: Some("my token".into()), ..Default::default() };
 //!
 //! // Setup IpInfo structure and start looking up IP addresses.
-//! let mut ipinfo = IpInfo::new(config).expect("should construct");
+//! let ipinfo = IpInfo::new(config).expect("should construct");
 //! let res = ipinfo.lookup(&["8.8.8.8", "4.2.2.4"]);
 //!
 //! match res {
-//!   Ok(r) => println!("{}: {}", "8.8.8.8", r["8.8.8.8"].hostname.as_ref().unwrap()),
+//!   Ok(r) => match r.get("8.8.8.8") {
+//!     Some(details) => println!("{}: {:?}", "8.8.8.8", details.hostname),
+//!     None => println!("no details for 8.8.8.8"),
+//!   },
 //!   Err(e) => println!("error occurred: {}", &e.to_string()),
 //! }
 //! ```
@@ -50,9 +85,101 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 
 #[macro_use]
 mod error;
+#[macro_use]
+mod trace;
+#[cfg(feature = "actix")]
+mod actix_middleware;
 mod api;
+mod async_ipinfo;
+#[cfg(not(target_arch = "wasm32"))]
+mod batcher;
+mod bogon;
+mod cache;
+mod circuit;
+mod client;
+mod clock;
+mod config;
+mod config_file;
+mod context;
+mod countries;
+#[cfg(all(feature = "mmdb", not(target_arch = "wasm32")))]
+mod database;
+mod enricher;
+pub mod export;
+mod flight;
+mod forwarded;
+mod geo;
+#[cfg(all(feature = "mmdb", not(target_arch = "wasm32")))]
+mod hybrid;
+mod interceptor;
+#[cfg(not(target_arch = "wasm32"))]
 mod ipinfo;
+mod metrics;
+#[cfg(feature = "axum")]
+mod middleware;
+#[cfg(feature = "mmdb")]
+mod mmdb;
+mod rate_limit;
+mod retry;
+#[cfg(feature = "rocket")]
+mod rocket_guard;
+#[cfg(feature = "tower")]
+mod service;
+mod summary;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod token;
+mod transport;
+#[cfg(feature = "warp")]
+mod warp_filter;
 
+#[cfg(not(target_arch = "wasm32"))]
 pub use crate::ipinfo::*;
+#[cfg(feature = "actix")]
+pub use actix_middleware::{IpInfoMiddleware, IpInfoMiddlewareService};
 pub use api::*;
+pub use async_ipinfo::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use batcher::{Batcher, BatcherConfig};
+pub use bogon::is_bogon;
+pub use cache::*;
+pub use circuit::CircuitBreakerConfig;
+pub use client::TlsVersion;
+pub use config::IpInfoConfig;
+pub use countries::CountryData;
+#[cfg(all(feature = "mmdb", not(target_arch = "wasm32")))]
+pub use database::{DatabaseKind, DatabaseManager, Refresher};
+pub use enricher::Enricher;
 pub use error::*;
+pub use forwarded::forwarded_ip;
+#[cfg(any(
+    feature = "actix",
+    feature = "axum",
+    feature = "rocket",
+    feature = "warp"
+))]
+pub use forwarded::MiddlewareConfig;
+pub use geo::distance;
+#[cfg(all(feature = "mmdb", not(target_arch = "wasm32")))]
+pub use hybrid::HybridResolver;
+pub use interceptor::Interceptor;
+pub use metrics::*;
+#[cfg(feature = "axum")]
+pub use middleware::{IpInfoLayer, IpInfoService};
+#[cfg(feature = "mmdb")]
+pub use mmdb::{AsnRecord, Database, DatabaseMetadata};
+pub use rate_limit::RateLimit;
+pub use retry::RetryPolicy;
+#[cfg(feature = "rocket")]
+pub use rocket_guard::IpInfoFairing;
+#[cfg(feature = "tower")]
+pub use service::LookupRequest;
+pub use summary::{summarize, Summary};
+pub use token::SecretToken;
+#[cfg(not(target_arch = "wasm32"))]
+pub use transport::ReqwestTransport;
+pub use transport::{
+    AsyncReqwestTransport, AsyncTransport, HttpRequest, HttpResponse, Transport, TransportFuture,
+};
+#[cfg(feature = "warp")]
+pub use warp_filter::ip_details_filter;
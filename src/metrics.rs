@@ -0,0 +1,75 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Hooks collecting telemetry of the clients.
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+
+#[cfg(feature = "prometheus")]
+pub use self::prometheus::PrometheusMetrics;
+
+use std::time::Duration;
+
+use crate::IpErrorKind;
+
+use reqwest::StatusCode;
+
+/// Telemetry recorder of a client, to pipe requests, latencies, cache usage and errors into
+/// statsd, OTLP or any other metrics backend.
+///
+/// Every method does nothing by default, so implementations only override the metrics they
+/// need. Methods are called on the request path, and should return quickly.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::{
+///     atomic::{AtomicU64, Ordering},
+///     Arc,
+/// };
+///
+/// use ipinfo::{IpInfo, IpInfoConfig, Metrics};
+///
+/// #[derive(Default)]
+/// struct CacheHits(AtomicU64);
+///
+/// impl Metrics for CacheHits {
+///     fn record_cache_hit(&self) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let ipinfo = IpInfo::new(IpInfoConfig {
+///     metrics: Some(Arc::new(CacheHits::default())),
+///     ..Default::default()
+/// })
+/// .expect("should construct");
+/// ```
+pub trait Metrics: Send + Sync {
+    /// Record an HTTP request attempt, with the status of its response if one was received.
+    fn record_request(&self, _status: Option<StatusCode>) {}
+
+    /// Record how long an HTTP request attempt took.
+    fn record_latency(&self, _latency: Duration) {}
+
+    /// Record a lookup answered from the cache.
+    fn record_cache_hit(&self) {}
+
+    /// Record a lookup that missed the cache or found an expired entry.
+    fn record_cache_miss(&self) {}
+
+    /// Record a failed HTTP request attempt.
+    fn record_error(&self, _kind: IpErrorKind) {}
+}
@@ -0,0 +1,233 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Prometheus recorder of client telemetry.
+
+use std::time::Duration;
+
+use crate::{IpErrorKind, Metrics};
+
+use prometheus::{
+    core::{Collector, Desc},
+    proto::MetricFamily,
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, TextEncoder,
+};
+use reqwest::StatusCode;
+
+/// The upper bounds of the request latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// [`Metrics`] recorder keeping Prometheus counters and histograms, as a [`Collector`] to
+/// register with a `prometheus::Registry`.
+///
+/// Clones share the same metrics, so a clone can be registered while the recorder is passed to
+/// the client. [`render`](PrometheusMetrics::render) gets the metrics in the Prometheus text
+/// format, for applications without a registry.
+///
+/// The metrics are prefixed with `ipinfo_`:
+///
+/// * `ipinfo_requests_total`: HTTP request attempts, by response `status` (`none` if no
+///   response was received)
+/// * `ipinfo_request_duration_seconds`: histogram of HTTP request attempt latencies
+/// * `ipinfo_cache_hits_total` and `ipinfo_cache_misses_total`: cache lookups, for the cache hit
+///   ratio
+/// * `ipinfo_errors_total`: failed HTTP request attempts, by error `kind`
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use ipinfo::{IpInfo, IpInfoConfig, PrometheusMetrics};
+/// use prometheus::Registry;
+///
+/// let metrics = PrometheusMetrics::new();
+/// let registry = Registry::new();
+/// registry.register(Box::new(metrics.clone())).expect("should register");
+///
+/// let ipinfo = IpInfo::new(IpInfoConfig {
+///     metrics: Some(Arc::new(metrics)),
+///     ..Default::default()
+/// })
+/// .expect("should construct");
+///
+/// assert!(registry.gather().iter().any(|family| family.name() == "ipinfo_cache_hits_total"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PrometheusMetrics {
+    requests: IntCounterVec,
+    latency: Histogram,
+    cache_hits: IntCounter,
+    cache_misses: IntCounter,
+    errors: IntCounterVec,
+}
+
+impl PrometheusMetrics {
+    /// Construct a new recorder with every metric at zero.
+    pub fn new() -> Self {
+        // The options are static and valid, so constructing the metrics can't fail
+        Self {
+            requests: IntCounterVec::new(
+                Opts::new(
+                    "ipinfo_requests_total",
+                    "HTTP request attempts to the IPinfo API.",
+                ),
+                &["status"],
+            )
+            .unwrap(),
+            latency: Histogram::with_opts(
+                HistogramOpts::new(
+                    "ipinfo_request_duration_seconds",
+                    "Latency of HTTP request attempts to the IPinfo API.",
+                )
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            )
+            .unwrap(),
+            cache_hits: IntCounter::new(
+                "ipinfo_cache_hits_total",
+                "Lookups answered from the cache.",
+            )
+            .unwrap(),
+            cache_misses: IntCounter::new(
+                "ipinfo_cache_misses_total",
+                "Lookups missing the cache.",
+            )
+            .unwrap(),
+            errors: IntCounterVec::new(
+                Opts::new(
+                    "ipinfo_errors_total",
+                    "Failed HTTP request attempts to the IPinfo API.",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = Vec::new();
+        // Encoding in memory only fails for invalid metric families, which these aren't
+        TextEncoder::new()
+            .encode(&self.collect(), &mut out)
+            .unwrap();
+
+        String::from_utf8(out).unwrap()
+    }
+}
+
+impl Default for PrometheusMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Collector for PrometheusMetrics {
+    fn desc(&self) -> Vec<&Desc> {
+        [
+            self.requests.desc(),
+            self.latency.desc(),
+            self.cache_hits.desc(),
+            self.cache_misses.desc(),
+            self.errors.desc(),
+        ]
+        .concat()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        [
+            self.requests.collect(),
+            self.latency.collect(),
+            self.cache_hits.collect(),
+            self.cache_misses.collect(),
+            self.errors.collect(),
+        ]
+        .concat()
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn record_request(&self, status: Option<StatusCode>) {
+        let status = match status {
+            Some(status) => status.as_u16().to_string(),
+            None => "none".to_owned(),
+        };
+        self.requests.with_label_values(&[status]).inc();
+    }
+
+    fn record_latency(&self, latency: Duration) {
+        self.latency.observe(latency.as_secs_f64());
+    }
+
+    fn record_cache_hit(&self) {
+        self.cache_hits.inc();
+    }
+
+    fn record_cache_miss(&self) {
+        self.cache_misses.inc();
+    }
+
+    fn record_error(&self, kind: IpErrorKind) {
+        self.errors.with_label_values(&[format!("{kind:?}")]).inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_rendered() {
+        let metrics = PrometheusMetrics::new();
+
+        metrics.record_request(Some(StatusCode::OK));
+        metrics.record_request(Some(StatusCode::OK));
+        metrics.record_request(None);
+        metrics.record_latency(Duration::from_millis(30));
+        metrics.record_cache_hit();
+        metrics.record_error(IpErrorKind::TimeoutError);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("ipinfo_requests_total{status=\"200\"} 2\n"));
+        assert!(rendered.contains("ipinfo_requests_total{status=\"none\"} 1\n"));
+        assert!(rendered.contains("ipinfo_request_duration_seconds_bucket{le=\"0.025\"} 0\n"));
+        assert!(rendered.contains("ipinfo_request_duration_seconds_bucket{le=\"0.05\"} 1\n"));
+        assert!(rendered.contains("ipinfo_request_duration_seconds_count 1\n"));
+        assert!(rendered.contains("ipinfo_cache_hits_total 1\n"));
+        assert!(rendered.contains("ipinfo_cache_misses_total 0\n"));
+        assert!(rendered.contains("ipinfo_errors_total{kind=\"TimeoutError\"} 1\n"));
+    }
+
+    #[test]
+    fn metrics_are_registered() {
+        let metrics = PrometheusMetrics::new();
+        let registry = prometheus::Registry::new();
+        registry
+            .register(Box::new(metrics.clone()))
+            .expect("should register");
+
+        metrics.record_cache_hit();
+        metrics.record_cache_hit();
+
+        let families = registry.gather();
+        let hits = families
+            .iter()
+            .find(|family| family.name() == "ipinfo_cache_hits_total")
+            .expect("should be gathered");
+        assert_eq!(hits.get_metric()[0].get_counter().get_value(), 2.0);
+        assert!(registry.register(Box::new(metrics)).is_err());
+    }
+}
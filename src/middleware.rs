@@ -0,0 +1,236 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Tower middleware enriching HTTP requests with the details of their client IP.
+
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::{forwarded, AsyncIpInfo, MiddlewareConfig};
+
+use http::Request;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// A [`Layer`] looking up the client IP of every request, and inserting its
+/// [`IpDetails`](crate::IpDetails) into the request extensions.
+///
+/// Without trusted proxies, the client IP is taken from a [`SocketAddr`] or
+/// [`IpAddr`] request extension. Lookups go through the client's cache, and failed lookups
+/// leave the request without details rather than failing it.
+///
+/// With axum, handlers extract the details with `Extension<IpDetails>`, or
+/// `Option<Extension<IpDetails>>` when lookups may fail.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{AsyncIpInfo, IpInfoLayer};
+///
+/// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+/// let layer = IpInfoLayer::new(ipinfo, Default::default());
+/// ```
+#[derive(Clone)]
+pub struct IpInfoLayer {
+    client: AsyncIpInfo,
+    config: MiddlewareConfig,
+}
+
+impl IpInfoLayer {
+    /// Construct a new layer performing its lookups with a client.
+    pub fn new(client: AsyncIpInfo, config: MiddlewareConfig) -> Self {
+        Self { client, config }
+    }
+}
+
+impl<S> Layer<S> for IpInfoLayer {
+    type Service = IpInfoService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        IpInfoService {
+            inner,
+            client: self.client.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// The service wrapped by an [`IpInfoLayer`].
+#[derive(Clone)]
+pub struct IpInfoService<S> {
+    inner: S,
+    client: AsyncIpInfo,
+    config: MiddlewareConfig,
+}
+
+impl<S, B> Service<Request<B>> for IpInfoService<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // Call the instance that was polled ready, leaving a fresh clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let client = self.client.clone();
+        let ip = client_ip(&request, self.config.trusted_proxies);
+
+        Box::pin(async move {
+            if let Some(ip) = ip {
+                match client.lookup_addr(ip).await {
+                    Ok(details) => {
+                        request.extensions_mut().insert(details);
+                    }
+                    Err(_e) => {
+                        trace_event!(warn, ip = %ip, error = %_e, "client ip lookup failed");
+                    }
+                }
+            }
+            inner.call(request).await
+        })
+    }
+}
+
+/// Get the client IP of a request.
+pub(crate) fn client_ip<B>(request: &Request<B>, trusted_proxies: usize) -> Option<IpAddr> {
+    let headers = request.headers();
+    let extensions = request.extensions();
+    let peer = extensions
+        .get::<SocketAddr>()
+        .map(SocketAddr::ip)
+        .or_else(|| extensions.get::<IpAddr>().copied());
+
+    forwarded::client_ip(
+        trusted_proxies,
+        |name| {
+            let values = headers.get_all(name).iter();
+            values.filter_map(|value| value.to_str().ok())
+        },
+        peer,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{convert::Infallible, sync::Arc};
+
+    use crate::{
+        testing::{details, MockTransport},
+        IpDetails, IpInfoConfig,
+    };
+
+    fn request(headers: &[(&str, &str)]) -> Request<()> {
+        let mut request = Request::builder();
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut request = request.body(()).unwrap();
+        request
+            .extensions_mut()
+            .insert(SocketAddr::from(([8, 8, 8, 8], 443)));
+        request
+    }
+
+    #[test]
+    fn client_ip_respects_forwarded_headers() {
+        let ip = |headers: &[(&str, &str)], proxies| client_ip(&request(headers), proxies);
+
+        assert_eq!(ip(&[], 1), Some("8.8.8.8".parse().unwrap()));
+        assert_eq!(
+            ip(&[("x-forwarded-for", "1.1.1.1, 10.0.0.1")], 0),
+            Some("8.8.8.8".parse().unwrap())
+        );
+        assert_eq!(
+            ip(&[("x-forwarded-for", "1.1.1.1, 10.0.0.1")], 2),
+            Some("1.1.1.1".parse().unwrap())
+        );
+        assert_eq!(
+            ip(
+                &[("forwarded", r#"proto=http;for="[2001:db8::1]:4711""#)],
+                1
+            ),
+            Some("2001:db8::1".parse().unwrap())
+        );
+        assert_eq!(
+            ip(&[("forwarded", "for=4.2.2.4:80, for=10.0.0.1")], 2),
+            Some("4.2.2.4".parse().unwrap())
+        );
+        assert_eq!(
+            ip(&[("x-real-ip", "9.9.9.9")], 1),
+            Some("9.9.9.9".parse().unwrap())
+        );
+        assert_eq!(client_ip(&Request::new(()), 0), None);
+    }
+
+    #[test]
+    fn client_ip_skips_forged_entries() {
+        // The client forged "1.1.1.1", and the proxy appended 4.2.2.4 as a repeated header
+        let request = request(&[
+            ("x-forwarded-for", "1.1.1.1"),
+            ("x-forwarded-for", "4.2.2.4"),
+        ]);
+
+        assert_eq!(client_ip(&request, 1), Some("4.2.2.4".parse().unwrap()));
+    }
+
+    #[derive(Clone)]
+    struct Handler;
+
+    impl Service<Request<()>> for Handler {
+        type Response = Option<IpDetails>;
+        type Error = Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Infallible>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, request: Request<()>) -> Self::Future {
+            std::future::ready(Ok(request.extensions().get::<IpDetails>().cloned()))
+        }
+    }
+
+    #[tokio::test]
+    async fn details_are_inserted_into_extensions() {
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new().with_details(details("8.8.8.8", "US")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let mut service = IpInfoLayer::new(ipinfo, Default::default()).layer(Handler);
+
+        let details = service.call(request(&[])).await.unwrap();
+        assert_eq!(details.expect("should be inserted").country, "US");
+
+        let details = service.call(Request::new(())).await.unwrap();
+        assert!(details.is_none());
+    }
+}
@@ -0,0 +1,736 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Offline lookups in the downloadable IPinfo databases, in the MMDB format.
+
+use std::{collections::HashMap, fmt, net::IpAddr, path::Path};
+
+use crate::{IpDetails, IpError};
+
+use maxminddb::{MaxMindDbError, Reader};
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
+use serde_json::{Map, Value};
+
+/// The maximum nesting of the maps and arrays of a record.
+///
+/// The reader stops at 512 levels, as libmaxminddb does, which is still enough for a cyclic
+/// record to exhaust the stack of a debug build. IPinfo records are flat.
+const MAX_RECORD_DEPTH: usize = 32;
+
+/// The metadata of an MMDB database.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DatabaseMetadata {
+    /// The type of the database. (i.e., ipinfo country_asn.mmdb)
+    pub database_type: String,
+
+    /// The IP version of the search tree, 4 or 6. IPv6 databases also contain IPv4 addresses.
+    pub ip_version: u16,
+
+    /// The number of nodes of the search tree.
+    pub node_count: u32,
+
+    /// The size in bits of the records of the search tree: 24, 28 or 32.
+    pub record_size: u16,
+
+    /// When the database was built, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub build_epoch: u64,
+
+    /// The description of the database, keyed by language.
+    #[serde(default)]
+    pub description: HashMap<String, String>,
+}
+
+/// The origin AS of an IP address, from the IPinfo ASN databases.
+///
+/// Both the `asn.mmdb` (`name` and `domain`) and the `country_asn.mmdb` (`as_name` and
+/// `as_domain`) schemas are supported. Fields missing from the record default to empty strings.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct AsnRecord {
+    /// The AS number. (i.e., AS15169)
+    #[serde(default)]
+    pub asn: String,
+
+    /// The name of the entity that owns the AS.
+    #[serde(default, alias = "as_name")]
+    pub name: String,
+
+    /// The domain of the entity that owns the AS.
+    #[serde(default, alias = "as_domain")]
+    pub domain: String,
+
+    /// The entity type that owns the AS. (i.e., business, education, hosting, isp)
+    #[serde(default, rename = "type")]
+    pub asn_type: String,
+
+    /// The network of the IP address announced by the AS, if in the record.
+    #[serde(default)]
+    pub network: Option<String>,
+}
+
+/// A downloadable IPinfo database, such as `country.mmdb`, `country_asn.mmdb`, `asn.mmdb` or
+/// `privacy.mmdb`, answering lookups without network access.
+///
+/// The whole file is read in memory, so lookups are cheap and the database can be shared between
+/// threads. Fails with a `DatabaseError` if the file can't be read or is malformed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{Database, LiteDetails};
+///
+/// let db = Database::open("country_asn.mmdb").expect("should open");
+/// let ip = "8.8.8.8".parse().unwrap();
+///
+/// let details = db.lookup_details(ip).expect("should decode");
+/// let lite: Option<LiteDetails> = db.lookup(ip).expect("should decode");
+/// ```
+pub struct Database {
+    reader: Reader<Vec<u8>>,
+    metadata: DatabaseMetadata,
+}
+
+impl Database {
+    /// Read a database file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, IpError> {
+        let path = path.as_ref();
+        let reader = Reader::open_readfile(path)
+            .map_err(|e| err!(DatabaseError, "cannot read {}: {}", path.display(), e))?;
+
+        Ok(Self::with_reader(reader))
+    }
+
+    /// Read a database from its content.
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Self, IpError> {
+        let reader = Reader::from_source(buf).map_err(invalid_database)?;
+
+        Ok(Self::with_reader(reader))
+    }
+
+    fn with_reader(reader: Reader<Vec<u8>>) -> Self {
+        let metadata = reader.metadata();
+        let metadata = DatabaseMetadata {
+            database_type: metadata.database_type.clone(),
+            ip_version: metadata.ip_version,
+            node_count: metadata.node_count,
+            record_size: metadata.record_size,
+            build_epoch: metadata.build_epoch,
+            description: metadata.description.clone().into_iter().collect(),
+        };
+
+        Self { reader, metadata }
+    }
+
+    /// Get the metadata of the database.
+    pub fn metadata(&self) -> &DatabaseMetadata {
+        &self.metadata
+    }
+
+    /// Get the untyped record of an IP address, if any.
+    pub fn lookup_value(&self, ip: IpAddr) -> Result<Option<Value>, IpError> {
+        self.reader
+            .lookup(ip)
+            .and_then(|result| result.decode::<Record>())
+            .map(|record| record.map(|record| record.0))
+            .map_err(invalid_database)
+    }
+
+    /// Get the record of an IP address, if any, deserialized into a type matching the schema
+    /// of the database, such as [`LiteDetails`](crate::LiteDetails) for `country_asn.mmdb`.
+    ///
+    /// An `ip` field is added to records which don't have one.
+    pub fn lookup<T: DeserializeOwned>(&self, ip: IpAddr) -> Result<Option<T>, IpError> {
+        self.lookup_value(ip)?
+            .map(|mut value| {
+                if let Value::Object(record) = &mut value {
+                    record.entry("ip").or_insert_with(|| ip.to_string().into());
+                }
+                serde_json::from_value(value)
+                    .map_err(|e| err!(DatabaseError, "invalid record for {}: {}", ip, e))
+            })
+            .transpose()
+    }
+
+    /// Get the details of an IP address, if any, converting the fields of the IPinfo databases
+    /// to those of the API.
+    ///
+    /// The IP details are not enriched with country data, see
+    /// [`CountryData::enrich`](crate::CountryData::enrich). Fields unknown to `IpDetails` are
+    /// kept in `extra`.
+    pub fn lookup_details(&self, ip: IpAddr) -> Result<Option<IpDetails>, IpError> {
+        let Some(Value::Object(record)) = self.lookup_value(ip)? else {
+            return Ok(None);
+        };
+
+        serde_json::from_value(Value::Object(to_api_fields(ip, record)))
+            .map(Some)
+            .map_err(|e| err!(DatabaseError, "invalid record for {}: {}", ip, e))
+    }
+
+    /// Get the origin AS of an IP address from an ASN database, without any network call.
+    ///
+    /// Returns `None` if the IP address isn't in the database or its record has no ASN, such as
+    /// in databases without ASN data.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use ipinfo::{Database, DatabaseKind, DatabaseManager, IpInfoConfig};
+    ///
+    /// let manager = DatabaseManager::new(IpInfoConfig {
+    ///     token: Some("my token".into()),
+    ///     ..Default::default()
+    /// })
+    /// .expect("should construct");
+    /// manager.download(&DatabaseKind::Asn, "asn.mmdb").expect("should download");
+    ///
+    /// let db = Database::open("asn.mmdb").expect("should open");
+    /// if let Some(record) = db.lookup_asn_offline("8.8.8.8".parse().unwrap()) {
+    ///     println!("{} ({})", record.asn, record.name);
+    /// }
+    /// ```
+    pub fn lookup_asn_offline(&self, ip: IpAddr) -> Option<AsnRecord> {
+        let record: AsnRecord = match self.lookup(ip) {
+            Ok(record) => record?,
+            Err(_e) => {
+                trace_event!(debug, %ip, error = %_e, "invalid ASN record");
+                return None;
+            }
+        };
+
+        (!record.asn.is_empty()).then_some(record)
+    }
+}
+
+/// Report a database which can't be read or a record which can't be decoded.
+fn invalid_database(e: MaxMindDbError) -> IpError {
+    err!(DatabaseError, "{}", e)
+}
+
+/// An untyped record, failing to decode beyond [`MAX_RECORD_DEPTH`] levels of nesting.
+struct Record(Value);
+
+impl<'de> Deserialize<'de> for Record {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        RecordSeed(0).deserialize(deserializer).map(Record)
+    }
+}
+
+/// Decodes a value of a record nested at some depth.
+struct RecordSeed(usize);
+
+impl RecordSeed {
+    fn nested<E: de::Error>(&self) -> Result<Self, E> {
+        match self.0 < MAX_RECORD_DEPTH {
+            true => Ok(Self(self.0 + 1)),
+            false => Err(E::custom("record nested too deeply")),
+        }
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for RecordSeed {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for RecordSeed {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an MMDB value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u128<E>(self, v: u128) -> Result<Value, E> {
+        Ok(u64::try_from(v).map_or_else(|_| v.to_string().into(), Value::from))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(v.to_vec().into())
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut array = Vec::new();
+        while let Some(value) = seq.next_element_seed(self.nested()?)? {
+            array.push(value);
+        }
+        Ok(Value::Array(array))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut record = Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            record.insert(key, map.next_value_seed(self.nested()?)?);
+        }
+        Ok(Value::Object(record))
+    }
+}
+
+/// Convert the fields of an IPinfo database record to those of the API.
+fn to_api_fields(ip: IpAddr, mut record: Map<String, Value>) -> Map<String, Value> {
+    let mut fields = Map::new();
+
+    // The location databases name the country by code, the country databases by name
+    match take(&mut record, "country_code") {
+        Some(code) => {
+            fields.insert("country".to_owned(), code.into());
+            fields.insert(
+                "country_name".to_owned(),
+                take(&mut record, "country").into(),
+            );
+        }
+        None => {
+            fields.insert("country".to_owned(), take(&mut record, "country").into());
+        }
+    }
+    if let (Some(code), Some(name)) = (
+        take(&mut record, "continent_code"),
+        take(&mut record, "continent"),
+    ) {
+        fields.insert(
+            "continent".to_owned(),
+            serde_json::json!({ "code": code, "name": name }),
+        );
+    }
+    if let (Some(lat), Some(lon)) = (
+        take(&mut record, "latitude"),
+        take(&mut record, "longitude"),
+    ) {
+        fields.insert("loc".to_owned(), format!("{lat},{lon}").into());
+    }
+    for (from, to) in [
+        ("city", "city"),
+        ("region", "region"),
+        ("postal_code", "postal"),
+        ("timezone", "timezone"),
+    ] {
+        if let Some(value) = take(&mut record, from) {
+            fields.insert(to.to_owned(), value.into());
+        }
+    }
+
+    let asn = take(&mut record, "asn");
+    let name = take(&mut record, "as_name").or_else(|| take(&mut record, "name"));
+    if let Some(asn) = asn {
+        let domain = take(&mut record, "as_domain").or_else(|| take(&mut record, "domain"));
+        fields.insert(
+            "org".to_owned(),
+            format!("{} {}", asn, name.clone().unwrap_or_default())
+                .trim()
+                .into(),
+        );
+        fields.insert(
+            "asn".to_owned(),
+            serde_json::json!({
+                "asn": asn,
+                "name": name.unwrap_or_default(),
+                "domain": domain.unwrap_or_default(),
+                "route": take(&mut record, "route").unwrap_or_default(),
+                "type": take(&mut record, "type").unwrap_or_default(),
+            }),
+        );
+    }
+
+    let flags = ["vpn", "proxy", "tor", "relay", "hosting"];
+    if flags.iter().any(|flag| record.contains_key(*flag)) {
+        let mut privacy = Map::new();
+        for flag in flags {
+            let value = take(&mut record, flag).is_some_and(|v| v == "true" || v == "1");
+            privacy.insert(flag.to_owned(), value.into());
+        }
+        privacy.insert(
+            "service".to_owned(),
+            take(&mut record, "service").unwrap_or_default().into(),
+        );
+        fields.insert("privacy".to_owned(), Value::Object(privacy));
+    }
+
+    // The record may not tell its IP, and keep the other fields as extra ones
+    record.retain(|_, value| !value.is_null());
+    record.extend(fields);
+    record.insert("ip".to_owned(), ip.to_string().into());
+    record
+}
+
+/// Remove a field of a record, getting it as a string if not empty.
+fn take(record: &mut Map<String, Value>, key: &str) -> Option<String> {
+    match record.remove(key) {
+        Some(Value::String(s)) if !s.is_empty() => Some(s),
+        Some(Value::Null) | Some(Value::String(_)) | None => None,
+        Some(value) => Some(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use crate::IpErrorKind;
+
+    /// The marker preceding the metadata at the end of the file.
+    const METADATA_MARKER: &[u8] = b"\xAB\xCD\xEFMaxMind.com";
+
+    /// The size of the zeroed separator between the search tree and the data section.
+    const DATA_SEPARATOR_SIZE: usize = 16;
+
+    /// Encode the control byte(s) of a value of a type and size.
+    fn encode_ctrl(kind: u8, size: usize, out: &mut Vec<u8>) {
+        let (size, extra) = if size < 29 {
+            (size, None)
+        } else {
+            (29, Some(size - 29))
+        };
+        if kind < 8 {
+            out.push((kind << 5) | size as u8);
+        } else {
+            out.push(size as u8);
+            out.push(kind - 7);
+        }
+        out.extend(extra.map(|extra| extra as u8));
+    }
+
+    /// Encode an unsigned integer of a type (5 for u16, 6 for u32, 9 for u64).
+    fn encode_uint(kind: u8, n: u64, out: &mut Vec<u8>) {
+        let size = [(5, 2), (6, 4), (9, 8)]
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .unwrap()
+            .1;
+        encode_ctrl(kind, size, out);
+        out.extend(&n.to_be_bytes()[8 - size..]);
+    }
+
+    /// Encode a value in the MMDB data section format.
+    fn encode(value: &Value, out: &mut Vec<u8>) {
+        match value {
+            Value::String(s) => {
+                encode_ctrl(2, s.len(), out);
+                out.extend(s.as_bytes());
+            }
+            Value::Bool(b) => encode_ctrl(14, *b as usize, out),
+            Value::Number(n) if n.is_u64() => encode_uint(6, n.as_u64().unwrap(), out),
+            Value::Number(n) => {
+                encode_ctrl(3, 8, out);
+                out.extend(n.as_f64().unwrap().to_be_bytes());
+            }
+            Value::Object(map) => {
+                encode_ctrl(7, map.len(), out);
+                for (key, value) in map {
+                    encode(&Value::from(key.as_str()), out);
+                    encode(value, out);
+                }
+            }
+            Value::Array(array) => {
+                encode_ctrl(11, array.len(), out);
+                array.iter().for_each(|value| encode(value, out));
+            }
+            Value::Null => unreachable!("null values can't be encoded"),
+        }
+    }
+
+    /// Encode the metadata section of an IPv6 database, with its marker.
+    fn encode_metadata(node_count: usize, record_size: u16, out: &mut Vec<u8>) {
+        out.extend(METADATA_MARKER);
+        encode_ctrl(7, 9, out);
+        for (key, kind, n) in [
+            ("binary_format_major_version", 5, 2),
+            ("binary_format_minor_version", 5, 0),
+            ("ip_version", 5, 6),
+            ("node_count", 6, node_count as u64),
+            ("record_size", 5, u64::from(record_size)),
+            ("build_epoch", 9, 1_700_000_000),
+        ] {
+            encode(&Value::from(key), out);
+            encode_uint(kind, n, out);
+        }
+        encode(&Value::from("database_type"), out);
+        encode(&Value::from("ipinfo test.mmdb"), out);
+        encode(&Value::from("description"), out);
+        encode(&serde_json::json!({ "en": "test" }), out);
+        encode(&Value::from("languages"), out);
+        encode(&serde_json::json!(["en"]), out);
+    }
+
+    #[derive(Clone, Copy)]
+    enum Link {
+        Empty,
+        Node(usize),
+        Data(usize),
+    }
+
+    /// Build an IPv6 database mapping networks (such as `8.8.8.0/24`) to records.
+    pub(crate) fn database(record_size: u16, records: &[(&str, Value)]) -> Vec<u8> {
+        let mut nodes = vec![[Link::Empty; 2]];
+        let mut data = Vec::new();
+
+        for (network, value) in records {
+            let (ip, len) = network.split_once('/').unwrap();
+            let (bits, len) = match ip.parse::<IpAddr>().unwrap() {
+                IpAddr::V4(ip) => (
+                    u128::from(u32::from(ip)),
+                    len.parse::<usize>().unwrap() + 96,
+                ),
+                IpAddr::V6(ip) => (u128::from(ip), len.parse().unwrap()),
+            };
+            let link = Link::Data(data.len());
+            encode(value, &mut data);
+
+            let mut node = 0;
+            for i in 0..len {
+                let bit = ((bits >> (127 - i)) & 1) as usize;
+                if i == len - 1 {
+                    nodes[node][bit] = link;
+                } else if let Link::Node(next) = nodes[node][bit] {
+                    node = next;
+                } else {
+                    nodes.push([Link::Empty; 2]);
+                    nodes[node][bit] = Link::Node(nodes.len() - 1);
+                    node = nodes.len() - 1;
+                }
+            }
+        }
+
+        let count = nodes.len();
+        let record = |link: Link| match link {
+            Link::Empty => count,
+            Link::Node(node) => node,
+            Link::Data(offset) => count + DATA_SEPARATOR_SIZE + offset,
+        };
+        let mut buf = Vec::new();
+        for [left, right] in nodes {
+            let (left, right) = (record(left) as u32, record(right) as u32);
+            match record_size {
+                24 => {
+                    buf.extend(&left.to_be_bytes()[1..]);
+                    buf.extend(&right.to_be_bytes()[1..]);
+                }
+                28 => {
+                    buf.extend(&left.to_be_bytes()[1..]);
+                    buf.push((((left >> 24) & 0xf) << 4) as u8 | ((right >> 24) & 0xf) as u8);
+                    buf.extend(&right.to_be_bytes()[1..]);
+                }
+                _ => {
+                    buf.extend(left.to_be_bytes());
+                    buf.extend(right.to_be_bytes());
+                }
+            }
+        }
+        buf.extend([0; DATA_SEPARATOR_SIZE]);
+        buf.extend(data);
+        encode_metadata(count, record_size, &mut buf);
+
+        buf
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn records_are_looked_up_for_every_record_size() {
+        for record_size in [24, 28, 32] {
+            let db = Database::from_bytes(database(
+                record_size,
+                &[
+                    ("8.8.8.0/24", serde_json::json!({ "country": "US" })),
+                    ("2606:4700::/32", serde_json::json!({ "country": "AU" })),
+                ],
+            ))
+            .expect("should read");
+
+            assert_eq!(db.metadata().record_size, record_size);
+            assert_eq!(db.metadata().database_type, "ipinfo test.mmdb");
+            let record = db.lookup_value(ip("8.8.8.8")).unwrap().unwrap();
+            assert_eq!(record["country"], "US");
+            let record = db.lookup_value(ip("2606:4700::1111")).unwrap().unwrap();
+            assert_eq!(record["country"], "AU");
+            assert_eq!(db.lookup_value(ip("8.8.4.4")).unwrap(), None);
+            assert_eq!(db.lookup_value(ip("::1")).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn records_are_converted_to_details() {
+        let db = Database::from_bytes(database(
+            24,
+            &[(
+                "8.8.8.0/24",
+                serde_json::json!({
+                    "country": "United States",
+                    "country_code": "US",
+                    "continent": "North America",
+                    "continent_code": "NA",
+                    "city": "Mountain View",
+                    "latitude": 37.4056,
+                    "longitude": -122.0775,
+                    "asn": "AS15169",
+                    "as_name": "Google LLC",
+                    "as_domain": "google.com",
+                    "hosting": "true",
+                    "vpn": "",
+                    "network": "8.8.8.0/24",
+                }),
+            )],
+        ))
+        .expect("should read");
+
+        let details = db.lookup_details(ip("8.8.8.8")).unwrap().unwrap();
+
+        assert_eq!(details.ip, "8.8.8.8");
+        assert_eq!(details.country, "US");
+        assert_eq!(details.country_name.as_deref(), Some("United States"));
+        assert_eq!(details.continent.as_ref().unwrap().name, "North America");
+        assert_eq!(details.city, "Mountain View");
+        assert_eq!(details.coordinates(), Some((37.4056, -122.0775)));
+        assert_eq!(details.org.as_deref(), Some("AS15169 Google LLC"));
+        assert_eq!(details.asn.as_ref().unwrap().domain, "google.com");
+        let privacy = details.privacy.as_ref().unwrap();
+        assert!(privacy.hosting);
+        assert!(!privacy.vpn);
+        assert_eq!(details.extra["network"], "8.8.8.0/24");
+
+        let lite: crate::LiteDetails = db.lookup(ip("8.8.8.8")).unwrap().unwrap();
+        assert_eq!(lite.ip, "8.8.8.8");
+        assert_eq!(lite.as_name, "Google LLC");
+    }
+
+    #[test]
+    fn asn_records_are_looked_up() {
+        let db = Database::from_bytes(database(
+            24,
+            &[
+                (
+                    "8.8.8.0/24",
+                    serde_json::json!({
+                        "asn": "AS15169",
+                        "name": "Google LLC",
+                        "domain": "google.com",
+                        "type": "hosting",
+                        "network": "8.8.8.0/24",
+                    }),
+                ),
+                (
+                    "1.1.1.0/24",
+                    serde_json::json!({ "asn": "AS13335", "as_name": "Cloudflare, Inc." }),
+                ),
+                ("9.9.9.0/24", serde_json::json!({ "country": "US" })),
+            ],
+        ))
+        .expect("should read");
+
+        let record = db.lookup_asn_offline(ip("8.8.8.8")).unwrap();
+        assert_eq!(record.asn, "AS15169");
+        assert_eq!(record.asn_type, "hosting");
+        assert_eq!(record.network.as_deref(), Some("8.8.8.0/24"));
+        let record = db.lookup_asn_offline(ip("1.1.1.1")).unwrap();
+        assert_eq!(record.name, "Cloudflare, Inc.");
+        assert_eq!(record.domain, "");
+        assert_eq!(db.lookup_asn_offline(ip("9.9.9.9")), None);
+        assert_eq!(db.lookup_asn_offline(ip("4.2.2.4")), None);
+    }
+
+    /// Replace the only occurrence of some bytes of a database.
+    fn patch(buf: &mut [u8], from: &[u8], to: &[u8]) {
+        let mut positions = buf.windows(from.len()).enumerate();
+        let (start, _) = positions.find(|(_, window)| *window == from).unwrap();
+        assert!(positions.all(|(_, window)| window != from));
+        buf[start..start + to.len()].copy_from_slice(to);
+    }
+
+    #[test]
+    fn malformed_databases_are_rejected() {
+        let metadata = |record_size: u16| {
+            let mut buf = Vec::new();
+            encode_metadata(10, record_size, &mut buf);
+            Database::from_bytes(buf).err().unwrap()
+        };
+
+        assert_eq!(metadata(20).kind(), IpErrorKind::DatabaseError);
+        assert_eq!(metadata(24).kind(), IpErrorKind::DatabaseError);
+
+        let err = Database::from_bytes(b"not a database".to_vec())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), IpErrorKind::DatabaseError);
+        let err = Database::open("/nonexistent/country.mmdb").err().unwrap();
+        assert_eq!(err.kind(), IpErrorKind::DatabaseError);
+    }
+
+    #[test]
+    fn malformed_records_fail_without_panicking() {
+        // The record of 8.8.8.0/24 is the first value of the data section: a map of one string
+        let record = [0xe1, 0x41, b'k', 0x41, b'v'];
+        let valid = database(24, &[("8.8.8.0/24", serde_json::json!({ "k": "v" }))]);
+        let lookup = |buf: Vec<u8>| {
+            Database::from_bytes(buf)
+                .and_then(|db| db.lookup_value(ip("8.8.8.8")))
+                .err()
+                .unwrap()
+        };
+
+        // An extended type overflowing the type numbers
+        let mut buf = valid.clone();
+        patch(&mut buf, &record, &[0x00, 0xff, 0, 0, 0]);
+        assert_eq!(lookup(buf).kind(), IpErrorKind::DatabaseError);
+
+        // A map whose value points back to the map itself
+        let mut buf = valid.clone();
+        patch(&mut buf, &record, &[0xe1, 0x41, b'k', 0x20, 0x00]);
+        assert_eq!(lookup(buf).kind(), IpErrorKind::DatabaseError);
+
+        // A search tree record pointing into the separator before the data section
+        let node_count = Database::from_bytes(valid.clone())
+            .unwrap()
+            .metadata()
+            .node_count as usize;
+        let data = (node_count + DATA_SEPARATOR_SIZE) as u32;
+        let separator = (node_count + 5) as u32;
+        let mut buf = valid;
+        patch(
+            &mut buf[..node_count * 6],
+            &data.to_be_bytes()[1..],
+            &separator.to_be_bytes()[1..],
+        );
+        assert_eq!(lookup(buf).kind(), IpErrorKind::DatabaseError);
+    }
+}
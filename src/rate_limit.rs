@@ -0,0 +1,70 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Rate limit information reported by API responses.
+
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+
+/// The rate limit state reported by the `X-RateLimit-*` headers of the last response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The number of requests allowed in the current window.
+    pub limit: Option<u64>,
+
+    /// The number of requests left in the current window.
+    pub remaining: Option<u64>,
+
+    /// How long until the current window resets.
+    pub reset: Option<Duration>,
+}
+
+impl RateLimit {
+    /// Parse the rate limit headers of a response, if it has any.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header =
+            |name: &str| -> Option<u64> { headers.get(name)?.to_str().ok()?.trim().parse().ok() };
+
+        let rate_limit = Self {
+            limit: header("x-ratelimit-limit"),
+            remaining: header("x-ratelimit-remaining"),
+            reset: header("x-ratelimit-reset").map(Duration::from_secs),
+        };
+        (rate_limit != Self::default()).then_some(rate_limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_headers_are_parsed() {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-RateLimit-Limit", "1000".parse().unwrap());
+        headers.insert("X-RateLimit-Remaining", "42".parse().unwrap());
+        headers.insert("X-RateLimit-Reset", "60".parse().unwrap());
+
+        assert_eq!(
+            RateLimit::from_headers(&headers),
+            Some(RateLimit {
+                limit: Some(1000),
+                remaining: Some(42),
+                reset: Some(Duration::from_secs(60)),
+            })
+        );
+        assert_eq!(RateLimit::from_headers(&HeaderMap::new()), None);
+    }
+}
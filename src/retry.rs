@@ -0,0 +1,230 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Retry policy for transient HTTP failures.
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    time::Duration,
+};
+
+use crate::{clock, IpError};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+/// Retry policy configuration, applied to every HTTP request.
+///
+/// Delays grow exponentially from `base_delay` and are capped at `max_delay`.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts per request, including the first one. (default: 1 attempt)
+    pub max_attempts: u32,
+
+    /// The delay before the first retry. (default: 100 milliseconds)
+    pub base_delay: Duration,
+
+    /// The maximum delay between two attempts. (default: 5 seconds)
+    pub max_delay: Duration,
+
+    /// Whether to randomize each delay between zero and its computed value. (default: true)
+    pub jitter: bool,
+
+    /// Whether to retry connection errors and timeouts. (default: true)
+    pub retry_network_errors: bool,
+
+    /// Whether to retry 5xx responses. (default: true)
+    pub retry_server_errors: bool,
+
+    /// Whether to retry 429 responses, waiting as asked by their `Retry-After` header when
+    /// present. (default: false)
+    pub retry_rate_limited: bool,
+
+    /// The maximum `Retry-After` wait accepted before giving up on a 429 response.
+    /// (default: 60 seconds)
+    pub max_rate_limit_wait: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            retry_network_errors: true,
+            retry_server_errors: true,
+            retry_rate_limited: false,
+            max_rate_limit_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Get the delay to wait after a failed attempt (starting at 1) before retrying.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use ipinfo::RetryPolicy;
+    ///
+    /// let policy = RetryPolicy { jitter: false, ..Default::default() };
+    /// assert_eq!(policy.delay(1), Duration::from_millis(100));
+    /// assert_eq!(policy.delay(3), Duration::from_millis(400));
+    /// ```
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+
+        if self.jitter {
+            let random = RandomState::new().build_hasher().finish();
+            delay.mul_f64((random as f64) / (u64::MAX as f64))
+        } else {
+            delay
+        }
+    }
+
+    /// Classify an HTTP client error as transient or permanent.
+    pub(crate) fn classify(&self, error: IpError) -> Failure {
+        let server = error
+            .status()
+            .is_some_and(|status| status.is_server_error());
+        let network = error.is_unavailable() && error.status().is_none();
+
+        Failure {
+            retryable: (network && self.retry_network_errors)
+                || (server && self.retry_server_errors),
+            unavailable: error.is_unavailable(),
+            error,
+            wait: None,
+        }
+    }
+
+    /// Classify a 429 response according to its `Retry-After` header.
+    pub(crate) fn classify_rate_limited(&self, headers: &HeaderMap) -> Failure {
+        let retry_after = parse_retry_after(headers);
+
+        Failure {
+            error: IpError::rate_limited(retry_after),
+            retryable: self.retry_rate_limited
+                && retry_after.is_none_or(|wait| wait <= self.max_rate_limit_wait),
+            unavailable: false,
+            wait: retry_after,
+        }
+    }
+}
+
+/// Parse the `Retry-After` header, either in seconds or as an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            Some(date.duration_since(clock::now()).unwrap_or_default())
+        }
+    }
+}
+
+/// The failure of a single request attempt.
+pub(crate) struct Failure {
+    pub(crate) error: IpError,
+
+    /// Whether the failure is worth retrying.
+    pub(crate) retryable: bool,
+
+    /// Whether the failure indicates the API is unavailable. (network error or 5xx response)
+    pub(crate) unavailable: bool,
+
+    /// The delay requested by the API before retrying.
+    pub(crate) wait: Option<Duration>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_policy_defaults_reasonable() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.max_attempts, 1);
+        assert!(policy.jitter);
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max() {
+        let policy = RetryPolicy {
+            jitter: false,
+            max_delay: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(4), Duration::from_millis(800));
+        assert_eq!(policy.delay(5), Duration::from_secs(1));
+        assert_eq!(policy.delay(100), Duration::from_secs(1));
+    }
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, retry_after.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_is_parsed() {
+        assert_eq!(
+            parse_retry_after(&headers("30")),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after(&headers("soon")), None);
+        assert_eq!(parse_retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn rate_limited_retries_only_when_enabled() {
+        let policy = RetryPolicy::default();
+        let failure = policy.classify_rate_limited(&headers("1"));
+        assert!(!failure.retryable);
+        assert_eq!(failure.error.retry_after(), Some(Duration::from_secs(1)));
+
+        let policy = RetryPolicy {
+            retry_rate_limited: true,
+            ..Default::default()
+        };
+        let failure = policy.classify_rate_limited(&headers("1"));
+        assert!(failure.retryable);
+        assert_eq!(failure.wait, Some(Duration::from_secs(1)));
+        assert!(!policy.classify_rate_limited(&headers("3600")).retryable);
+        assert!(policy.classify_rate_limited(&HeaderMap::new()).retryable);
+    }
+
+    #[test]
+    fn jittered_delay_is_bounded() {
+        let policy = RetryPolicy::default();
+
+        for attempt in 1..10 {
+            assert!(policy.delay(attempt) <= policy.max_delay);
+        }
+    }
+}
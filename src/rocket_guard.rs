@@ -0,0 +1,176 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Rocket fairing sharing a client, and request guard looking up the client IP of requests.
+
+use crate::{forwarded, AsyncIpInfo, IpDetails, IpError, MiddlewareConfig};
+
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Status,
+    request::{FromRequest, Outcome, Request},
+    Build, Rocket,
+};
+
+/// A [`Fairing`] managing a client shared by the [`IpDetails`] request guards of every request.
+///
+/// With the fairing attached, handlers take the details of the client IP as an `IpDetails`
+/// argument, failing the request if the lookup fails, or as an `Option<IpDetails>`. Without
+/// trusted proxies, the client IP is the remote address of the connection.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{AsyncIpInfo, IpDetails, IpInfoFairing};
+///
+/// #[rocket::get("/country")]
+/// fn country(details: Option<IpDetails>) -> String {
+///     details.map(|details| details.country).unwrap_or_default()
+/// }
+///
+/// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+/// let rocket = rocket::build()
+///     .attach(IpInfoFairing::new(ipinfo, Default::default()))
+///     .mount("/", rocket::routes![country]);
+/// ```
+pub struct IpInfoFairing {
+    state: IpInfoState,
+}
+
+impl IpInfoFairing {
+    /// Construct a new fairing managing a client.
+    pub fn new(client: AsyncIpInfo, config: MiddlewareConfig) -> Self {
+        Self {
+            state: IpInfoState { client, config },
+        }
+    }
+}
+
+/// The state managed by an [`IpInfoFairing`].
+#[derive(Clone)]
+struct IpInfoState {
+    client: AsyncIpInfo,
+    config: MiddlewareConfig,
+}
+
+#[rocket::async_trait]
+impl Fairing for IpInfoFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "IPinfo",
+            kind: Kind::Ignite,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        Ok(rocket.manage(self.state.clone()))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IpDetails {
+    type Error = IpError;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, IpError> {
+        let Some(state) = request.rocket().state::<IpInfoState>() else {
+            let e = err!(InvalidConfigError, "IpInfoFairing is not attached");
+            return Outcome::Error((Status::InternalServerError, e));
+        };
+
+        let headers = request.headers();
+        let ip = forwarded::client_ip(
+            state.config.trusted_proxies,
+            |name| headers.get(name),
+            request.remote().map(|remote| remote.ip()),
+        );
+        let Some(ip) = ip else {
+            let e = err!(InvalidIpError, "the request has no client IP");
+            return Outcome::Error((Status::BadRequest, e));
+        };
+
+        match state.client.lookup_addr(ip).await {
+            Ok(details) => Outcome::Success(details),
+            Err(e) => {
+                trace_event!(warn, ip = %ip, error = %e, "client ip lookup failed");
+                Outcome::Error((Status::InternalServerError, e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{net::SocketAddr, sync::Arc};
+
+    use crate::{
+        testing::{details, MockTransport},
+        IpInfoConfig,
+    };
+
+    use rocket::local::asynchronous::Client;
+
+    #[rocket::get("/")]
+    fn ip(details: IpDetails) -> String {
+        details.ip
+    }
+
+    /// Get the client IP guarded by a rocket, from a request forwarded for 1.1.1.1 by 8.8.8.8.
+    async fn get(fairing: Option<IpInfoFairing>) -> (Status, Option<String>) {
+        let mut rocket = rocket::build().mount("/", rocket::routes![ip]);
+        if let Some(fairing) = fairing {
+            rocket = rocket.attach(fairing);
+        }
+        let client = Client::untracked(rocket).await.expect("should launch");
+
+        let response = client
+            .get("/")
+            .remote(SocketAddr::from(([8, 8, 8, 8], 443)))
+            .header(rocket::http::Header::new("X-Forwarded-For", "1.1.1.1"))
+            .dispatch()
+            .await;
+        (response.status(), response.into_string().await)
+    }
+
+    #[rocket::async_test]
+    async fn details_of_the_client_ip_are_guarded() {
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new()
+                    .with_details(details("8.8.8.8", "US"))
+                    .with_details(details("1.1.1.1", "US")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let fairing = |trusted_proxies| {
+            let config = MiddlewareConfig {
+                trusted_proxies,
+                ..Default::default()
+            };
+            Some(IpInfoFairing::new(ipinfo.clone(), config))
+        };
+
+        let (status, ip) = get(fairing(0)).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(ip.as_deref(), Some("8.8.8.8"));
+        let (status, ip) = get(fairing(1)).await;
+        assert_eq!(status, Status::Ok);
+        assert_eq!(ip.as_deref(), Some("1.1.1.1"));
+
+        let (status, _) = get(None).await;
+        assert_eq!(status, Status::InternalServerError);
+    }
+}
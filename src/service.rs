@@ -0,0 +1,129 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! `tower::Service` implementation of the async client.
+
+use std::{
+    collections::HashMap,
+    task::{Context, Poll},
+};
+
+use crate::{AsyncIpInfo, IpDetails, IpError};
+
+use futures::future::BoxFuture;
+use tower_service::Service;
+
+/// A lookup of one or more IP addresses, the request of the [`Service`] implementation of
+/// [`AsyncIpInfo`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LookupRequest {
+    /// The IP addresses to lookup.
+    pub ips: Vec<String>,
+}
+
+impl From<&str> for LookupRequest {
+    fn from(ip: &str) -> Self {
+        Self {
+            ips: vec![ip.to_owned()],
+        }
+    }
+}
+
+impl From<Vec<String>> for LookupRequest {
+    fn from(ips: Vec<String>) -> Self {
+        Self { ips }
+    }
+}
+
+impl From<&[&str]> for LookupRequest {
+    fn from(ips: &[&str]) -> Self {
+        Self {
+            ips: ips.iter().map(|ip| (*ip).to_owned()).collect(),
+        }
+    }
+}
+
+/// Lookups as a [`Service`], so they can be wrapped in tower middleware such as timeouts,
+/// retries, rate limits or load shedding.
+///
+/// Each call is [`AsyncIpInfo::lookup`], using the client's cache. To leave retries to tower
+/// middleware, keep [`RetryPolicy::max_attempts`](crate::RetryPolicy::max_attempts) at 1.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{AsyncIpInfo, LookupRequest};
+/// use tower_service::Service;
+///
+/// # async fn run() {
+/// let mut ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+/// let res = ipinfo.call(LookupRequest::from("8.8.8.8")).await.expect("should run");
+/// # }
+/// ```
+impl Service<LookupRequest> for AsyncIpInfo {
+    type Response = HashMap<String, IpDetails>;
+    type Error = IpError;
+    type Future = BoxFuture<'static, Result<Self::Response, IpError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), IpError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: LookupRequest) -> Self::Future {
+        let client = self.clone();
+        Box::pin(async move {
+            let ips: Vec<&str> = request.ips.iter().map(String::as_str).collect();
+            client.lookup(&ips).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::{
+        testing::{details, MockTransport},
+        IpInfoConfig,
+    };
+
+    #[tokio::test]
+    async fn lookups_are_served() {
+        let transport = Arc::new(MockTransport::new().with_details(details("8.8.8.8", "US")));
+        let mut ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(transport.clone()),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        futures::future::poll_fn(|cx| ipinfo.poll_ready(cx))
+            .await
+            .expect("should be ready");
+        let details = ipinfo
+            .call(LookupRequest::from("8.8.8.8"))
+            .await
+            .expect("should lookup");
+        assert_eq!(details["8.8.8.8"].country, "US");
+        assert_eq!(transport.requests()[0].url, "https://ipinfo.io/batch");
+
+        let error = ipinfo
+            .call(LookupRequest::from("not an ip"))
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), crate::IpErrorKind::InvalidIpError);
+    }
+}
@@ -0,0 +1,194 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Local aggregation of lookup results.
+
+use std::collections::HashMap;
+
+use crate::IpDetails;
+
+use serde::Serialize;
+
+/// Counts of lookup results by country, ASN, company and privacy type.
+///
+/// Results missing a value, such as bogons or responses without the ASN or company data of
+/// higher plans, are left out of the matching counts.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Summary {
+    /// The number of IP addresses summarized.
+    pub total: usize,
+
+    /// The number of bogon IP addresses.
+    pub bogons: usize,
+
+    /// The number of IP addresses by country code.
+    pub countries: HashMap<String, usize>,
+
+    /// The number of IP addresses by AS number, taken from the ASN details or else from the
+    /// organization. (i.e., AS15169)
+    pub asns: HashMap<String, usize>,
+
+    /// The number of IP addresses by company name.
+    pub companies: HashMap<String, usize>,
+
+    /// The number of IP addresses by privacy type. (vpn, proxy, tor, relay and hosting)
+    pub privacy: HashMap<String, usize>,
+}
+
+impl Summary {
+    /// Get the entries of a count sorted by decreasing count, then by key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// use ipinfo::Summary;
+    ///
+    /// let countries = HashMap::from([("US".to_owned(), 1), ("AU".to_owned(), 2)]);
+    /// assert_eq!(Summary::top(&countries), [("AU", 2), ("US", 1)]);
+    /// ```
+    pub fn top(counts: &HashMap<String, usize>) -> Vec<(&str, usize)> {
+        let mut top: Vec<(&str, usize)> = counts.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top
+    }
+}
+
+/// Summarize lookup results, counting them by country, ASN, company and privacy type.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{summarize, IpInfo};
+///
+/// let ipinfo = IpInfo::new(Default::default()).expect("should construct");
+/// let details = ipinfo.lookup(&["8.8.8.8", "1.1.1.1"]).expect("should lookup");
+///
+/// let summary = summarize(&details);
+/// println!("{} IPs in {} countries", summary.total, summary.countries.len());
+/// ```
+pub fn summarize(details: &HashMap<String, IpDetails>) -> Summary {
+    let mut summary = Summary::default();
+
+    for details in details.values() {
+        summary.total += 1;
+
+        if details.bogon == Some(true) {
+            summary.bogons += 1;
+        }
+        if !details.country.is_empty() {
+            count(&mut summary.countries, &details.country);
+        }
+        if let Some(asn) = asn(details) {
+            count(&mut summary.asns, asn);
+        }
+        if let Some(company) = details.company.as_ref().filter(|c| !c.name.is_empty()) {
+            count(&mut summary.companies, &company.name);
+        }
+        if let Some(privacy) = &details.privacy {
+            let types = [
+                ("vpn", privacy.vpn),
+                ("proxy", privacy.proxy),
+                ("tor", privacy.tor),
+                ("relay", privacy.relay),
+                ("hosting", privacy.hosting),
+            ];
+            for (name, _) in types.iter().filter(|(_, flag)| *flag) {
+                count(&mut summary.privacy, name);
+            }
+        }
+    }
+
+    summary
+}
+
+/// Get the AS number of IP details, from the ASN details or the organization.
+fn asn(details: &IpDetails) -> Option<&str> {
+    match &details.asn {
+        Some(asn) if !asn.asn.is_empty() => Some(&asn.asn),
+        _ => details
+            .org
+            .as_deref()?
+            .split_whitespace()
+            .next()
+            .filter(|asn| asn.starts_with("AS")),
+    }
+}
+
+fn count(counts: &mut HashMap<String, usize>, key: &str) {
+    *counts.entry(key.to_owned()).or_default() += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture as details;
+
+    #[test]
+    fn results_are_counted() {
+        let results = HashMap::from([
+            (
+                "8.8.8.8".to_owned(),
+                details(serde_json::json!({
+                    "ip": "8.8.8.8",
+                    "country": "US",
+                    "org": "AS15169 Google LLC",
+                    "privacy": { "hosting": true },
+                })),
+            ),
+            (
+                "8.8.4.4".to_owned(),
+                details(serde_json::json!({
+                    "ip": "8.8.4.4",
+                    "country": "US",
+                    "asn": {
+                        "asn": "AS15169",
+                        "name": "Google LLC",
+                        "domain": "google.com",
+                        "route": "8.8.4.0/24",
+                        "type": "hosting"
+                    },
+                    "company": { "name": "Google LLC" },
+                    "privacy": { "vpn": true, "hosting": true },
+                })),
+            ),
+            (
+                "1.1.1.1".to_owned(),
+                details(serde_json::json!({ "ip": "1.1.1.1", "country": "AU" })),
+            ),
+            (
+                "10.0.0.1".to_owned(),
+                details(serde_json::json!({ "ip": "10.0.0.1", "bogon": true })),
+            ),
+        ]);
+
+        let summary = summarize(&results);
+
+        assert_eq!(summary.total, 4);
+        assert_eq!(summary.bogons, 1);
+        assert_eq!(Summary::top(&summary.countries), [("US", 2), ("AU", 1)]);
+        assert_eq!(summary.asns, HashMap::from([("AS15169".to_owned(), 2)]));
+        assert_eq!(
+            summary.companies,
+            HashMap::from([("Google LLC".to_owned(), 1)])
+        );
+        assert_eq!(Summary::top(&summary.privacy), [("hosting", 2), ("vpn", 1)]);
+    }
+
+    #[test]
+    fn empty_results_are_summarized() {
+        assert_eq!(summarize(&HashMap::new()), Summary::default());
+    }
+}
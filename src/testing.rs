@@ -0,0 +1,314 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Test utilities for unit testing code using the clients without a token or network access.
+//!
+//! [`MockTransport`] answers requests from canned [`IpDetails`], such as the [`google_dns`] and
+//! [`cloudflare_dns`] fixtures, or from responses built with [`MockResponse`].
+//!
+//! # Examples
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use ipinfo::{
+//!     testing::{google_dns, MockTransport},
+//!     IpInfo, IpInfoConfig,
+//! };
+//!
+//! let transport = Arc::new(MockTransport::new().with_details(google_dns()));
+//! let ipinfo = IpInfo::new(IpInfoConfig {
+//!     transport: Some(transport.clone()),
+//!     ..Default::default()
+//! })
+//! .expect("should construct");
+//!
+//! let details = ipinfo.lookup(&["8.8.8.8"]).expect("should lookup");
+//! assert_eq!(details["8.8.8.8"].country_name, Some("United States".to_owned()));
+//! assert_eq!(transport.requests().len(), 1);
+//! ```
+
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    AsyncTransport, HttpRequest, HttpResponse, IpDetails, IpError, Transport, TransportFuture,
+};
+
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE},
+    Method, StatusCode,
+};
+use serde::Serialize;
+use serde_json::json;
+
+/// Get the details of `8.8.8.8`, Google's public DNS resolver.
+pub fn google_dns() -> IpDetails {
+    fixture(json!({
+        "ip": "8.8.8.8",
+        "hostname": "dns.google",
+        "city": "Mountain View",
+        "region": "California",
+        "country": "US",
+        "loc": "37.4056,-122.0775",
+        "org": "AS15169 Google LLC",
+        "postal": "94043",
+        "timezone": "America/Los_Angeles",
+        "anycast": true,
+    }))
+}
+
+/// Get the details of `1.1.1.1`, Cloudflare's public DNS resolver.
+pub fn cloudflare_dns() -> IpDetails {
+    fixture(json!({
+        "ip": "1.1.1.1",
+        "hostname": "one.one.one.one",
+        "city": "Brisbane",
+        "region": "Queensland",
+        "country": "AU",
+        "loc": "-27.4820,153.0136",
+        "org": "AS13335 Cloudflare, Inc.",
+        "postal": "9010",
+        "timezone": "Australia/Brisbane",
+        "anycast": true,
+    }))
+}
+
+/// Get minimal details of an IP address located in a country, such as `US`.
+pub fn details(ip: &str, country: &str) -> IpDetails {
+    fixture(json!({ "ip": ip, "country": country }))
+}
+
+/// Get the details of `8.8.8.8` with some fields added or replaced.
+#[cfg(test)]
+pub(crate) fn google_dns_with(fields: serde_json::Value) -> IpDetails {
+    let mut value = serde_json::to_value(google_dns()).expect("fixture should serialize");
+    value
+        .as_object_mut()
+        .expect("fixture should be an object")
+        .extend(
+            fields
+                .as_object()
+                .expect("fields should be an object")
+                .clone(),
+        );
+    fixture(value)
+}
+
+/// Parse details from their JSON fields.
+pub(crate) fn fixture(value: serde_json::Value) -> IpDetails {
+    serde_json::from_value(value).expect("fixture should parse")
+}
+
+/// Builder of a synthetic HTTP response.
+///
+/// # Examples
+///
+/// ```
+/// use ipinfo::testing::MockResponse;
+///
+/// let response = MockResponse::new(429).header("retry-after", "1").build();
+/// assert_eq!(response.status, 429);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    response: HttpResponse,
+}
+
+impl MockResponse {
+    /// Start building an empty response with a status code.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the status code isn't between 100 and 999.
+    pub fn new(status: u16) -> Self {
+        Self {
+            response: HttpResponse {
+                status: StatusCode::from_u16(status).expect("status should be valid"),
+                headers: HeaderMap::new(),
+                body: String::new(),
+            },
+        }
+    }
+
+    /// Start building a 200 response with a JSON body.
+    pub fn json(body: &impl Serialize) -> Self {
+        Self::new(200)
+            .header(CONTENT_TYPE.as_str(), "application/json")
+            .body(&serde_json::to_string(body).expect("body should serialize"))
+    }
+
+    /// Add a header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the name or value isn't a valid header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.response.headers.append(
+            HeaderName::try_from(name).expect("header name should be valid"),
+            HeaderValue::try_from(value).expect("header value should be valid"),
+        );
+        self
+    }
+
+    /// Set the body.
+    pub fn body(mut self, body: &str) -> Self {
+        self.response.body = body.to_owned();
+        self
+    }
+
+    /// Build the response.
+    pub fn build(self) -> HttpResponse {
+        self.response
+    }
+}
+
+/// Fake transport answering requests without network access, for both clients.
+///
+/// Single and batch lookups of the IPs registered with
+/// [`with_details`](MockTransport::with_details) are answered with their details, and
+/// requests to paths registered with [`with_response`](MockTransport::with_response) with
+/// their response. Any other request gets a 404 response.
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    details: HashMap<String, IpDetails>,
+    responses: HashMap<String, HttpResponse>,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl MockTransport {
+    /// Construct a new transport answering every request with a 404 response.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the lookups of an IP address with its details.
+    pub fn with_details(mut self, details: IpDetails) -> Self {
+        self.details.insert(details.ip.clone(), details);
+        self
+    }
+
+    /// Answer the requests to a path, such as `/AS7922/json`, with a response.
+    pub fn with_response(mut self, path: &str, response: MockResponse) -> Self {
+        self.responses.insert(path.to_owned(), response.build());
+        self
+    }
+
+    /// Get the requests received so far, in order.
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Answer a request, recording it.
+    fn respond(&self, request: HttpRequest) -> HttpResponse {
+        let path = reqwest::Url::parse(&request.url)
+            .map(|url| url.path().to_owned())
+            .unwrap_or_default();
+        self.requests.lock().unwrap().push(request.clone());
+
+        if let Some(response) = self.responses.get(&path) {
+            return response.clone();
+        }
+
+        if request.method == Method::POST && path == "/batch" {
+            let ips: Vec<String> = request
+                .body
+                .and_then(|body| serde_json::from_str(&body).ok())
+                .unwrap_or_default();
+            let found: HashMap<&String, &IpDetails> = ips
+                .iter()
+                .filter_map(|ip| Some((ip, self.details.get(ip)?)))
+                .collect();
+            return MockResponse::json(&found).build();
+        }
+
+        match self.details.get(path.trim_start_matches('/')) {
+            Some(details) => MockResponse::json(details).build(),
+            None => MockResponse::new(404).body("not found").build(),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, IpError> {
+        Ok(self.respond(request))
+    }
+}
+
+impl AsyncTransport for MockTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move { Ok(self.respond(request)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IpErrorKind, IpInfo, IpInfoConfig};
+
+    use std::sync::Arc;
+
+    fn ipinfo(transport: MockTransport) -> IpInfo {
+        IpInfo::new(IpInfoConfig {
+            transport: Some(Arc::new(transport)),
+            ..Default::default()
+        })
+        .expect("should construct")
+    }
+
+    #[test]
+    fn registered_details_are_served() {
+        let ipinfo = ipinfo(
+            MockTransport::new()
+                .with_details(google_dns())
+                .with_details(cloudflare_dns()),
+        );
+
+        let details = ipinfo
+            .lookup(&["8.8.8.8", "1.1.1.1"])
+            .expect("should lookup");
+        assert_eq!(details["1.1.1.1"].city, "Brisbane");
+        assert_eq!(
+            ipinfo.lookup_one("8.8.8.8").expect("should lookup").org,
+            Some("AS15169 Google LLC".to_owned())
+        );
+
+        let err = ipinfo.lookup_one("4.2.2.4").unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn registered_responses_are_served() {
+        let ipinfo = ipinfo(
+            MockTransport::new().with_response("/8.8.8.8", MockResponse::new(403).body("denied")),
+        );
+
+        let err = ipinfo.lookup_one("8.8.8.8").unwrap_err();
+        assert_eq!(err.kind(), IpErrorKind::UnauthorizedError);
+        assert_eq!(err.body(), Some("denied"));
+    }
+
+    #[tokio::test]
+    async fn async_client_is_served() {
+        let ipinfo = crate::AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new().with_details(details("9.9.9.9", "CH")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+
+        let details = ipinfo.lookup_one("9.9.9.9").await.expect("should lookup");
+        assert_eq!(details.country_name, Some("Switzerland".to_owned()));
+    }
+}
@@ -0,0 +1,132 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! IPinfo access tokens.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use secrecy::{ExposeSecret, SecretString};
+
+/// An IPinfo access token, kept out of logs.
+///
+/// The token is stored as a [`SecretString`], redacted by `Debug` and doesn't implement
+/// `Display`, `Serialize` or `PartialEq`. Its own string is overwritten with zeros when dropped,
+/// but copies made from it are not: the `Authorization` header values of requests are only
+/// marked sensitive, which keeps them out of `Debug` output.
+///
+/// # Examples
+///
+/// ```
+/// use ipinfo::SecretToken;
+///
+/// let token = SecretToken::from("my token");
+/// assert_eq!(format!("{token:?}"), "SecretToken([REDACTED])");
+/// assert_eq!(token.expose_secret(), "my token");
+/// ```
+#[derive(Clone)]
+pub struct SecretToken(SecretString);
+
+impl SecretToken {
+    /// Construct a new token.
+    pub fn new(token: String) -> Self {
+        Self(SecretString::from(token))
+    }
+
+    /// Get the token itself.
+    pub fn expose_secret(&self) -> &str {
+        self.0.expose_secret()
+    }
+}
+
+impl From<String> for SecretToken {
+    fn from(token: String) -> Self {
+        Self::new(token)
+    }
+}
+
+impl From<&str> for SecretToken {
+    fn from(token: &str) -> Self {
+        Self::new(token.to_owned())
+    }
+}
+
+impl fmt::Debug for SecretToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecretToken([REDACTED])")
+    }
+}
+
+/// Tokens used in turn, one per request.
+#[derive(Debug, Default)]
+pub(crate) struct TokenPool {
+    tokens: Vec<SecretToken>,
+    next: AtomicUsize,
+}
+
+impl TokenPool {
+    /// Construct a new pool using tokens in order.
+    pub(crate) fn new(tokens: Vec<SecretToken>) -> Self {
+        Self {
+            tokens,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the number of tokens.
+    pub(crate) fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Check whether the pool has no tokens.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    /// Get the token of the next request, round-robin, if any.
+    pub(crate) fn rotate(&self) -> Option<&SecretToken> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+
+        let next = self.next.fetch_add(1, Ordering::Relaxed);
+        Some(&self.tokens[next % self.tokens.len()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pool_rotates_tokens() {
+        let pool = TokenPool::new(vec!["a".into(), "b".into()]);
+
+        let tokens: Vec<&str> = (0..3)
+            .map(|_| pool.rotate().unwrap().expose_secret())
+            .collect();
+        assert_eq!(tokens, ["a", "b", "a"]);
+        assert!(TokenPool::default().rotate().is_none());
+    }
+
+    #[test]
+    fn token_is_redacted() {
+        let token = SecretToken::from("secret".to_owned());
+
+        assert!(!format!("{token:?}").contains("secret"));
+        assert_eq!(token.clone().expose_secret(), "secret");
+    }
+}
@@ -0,0 +1,178 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Pluggable HTTP transport of API requests.
+
+use crate::IpError;
+
+use reqwest::{header::HeaderMap, Method, StatusCode};
+
+/// An HTTP request to the API, with its headers and token already set.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    /// The request method.
+    pub method: Method,
+
+    /// The request URL, including its query string.
+    pub url: String,
+
+    /// The request headers.
+    pub headers: HeaderMap,
+
+    /// The JSON request body, if any.
+    pub body: Option<String>,
+}
+
+/// An HTTP response of the API.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// The response status code.
+    pub status: StatusCode,
+
+    /// The response headers.
+    pub headers: HeaderMap,
+
+    /// The response body.
+    pub body: String,
+}
+
+/// The HTTP layer of the blocking client, sending requests and acquiring their responses.
+///
+/// Responses of any status should be returned as they are, since the client handles error
+/// statuses, rate limits and retries itself. Failures to get a response at all, such as
+/// connection errors, should be returned as errors marked with
+/// [`IpError::mark_unavailable`] so they are retried and counted by the circuit breaker.
+///
+/// The `timeout`, proxy and TLS settings of [`IpInfoConfig`](crate::IpInfoConfig) only apply
+/// to the default reqwest transport.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use ipinfo::{HttpRequest, HttpResponse, IpError, IpInfo, IpInfoConfig, Transport};
+///
+/// struct Mock;
+///
+/// impl Transport for Mock {
+///     fn send(&self, _request: HttpRequest) -> Result<HttpResponse, IpError> {
+///         Ok(HttpResponse {
+///             status: reqwest::StatusCode::OK,
+///             headers: Default::default(),
+///             body: r#"{"ip": "8.8.8.8"}"#.to_owned(),
+///         })
+///     }
+/// }
+///
+/// let ipinfo = IpInfo::new(IpInfoConfig {
+///     transport: Some(Arc::new(Mock)),
+///     ..Default::default()
+/// })
+/// .expect("should construct");
+/// assert_eq!(ipinfo.lookup_one("8.8.8.8").expect("should lookup").ip, "8.8.8.8");
+/// ```
+pub trait Transport: Send + Sync {
+    /// Send a request and acquire its response.
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, IpError>;
+}
+
+/// The future of a response sent by an [`AsyncTransport`].
+///
+/// It is `Send`, except on WASM where the futures of the browser's fetch API aren't.
+#[cfg(not(target_arch = "wasm32"))]
+pub type TransportFuture<'a> = futures::future::BoxFuture<'a, Result<HttpResponse, IpError>>;
+
+/// The future of a response sent by an [`AsyncTransport`].
+///
+/// It is `Send`, except on WASM where the futures of the browser's fetch API aren't.
+#[cfg(target_arch = "wasm32")]
+pub type TransportFuture<'a> = futures::future::LocalBoxFuture<'a, Result<HttpResponse, IpError>>;
+
+/// The HTTP layer of the async client, sending requests and acquiring their responses.
+///
+/// Errors and responses are handled as for [`Transport`].
+pub trait AsyncTransport: Send + Sync {
+    /// Send a request and acquire its response.
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_>;
+}
+
+/// Transport of the blocking client using reqwest, used by default.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::blocking::Client,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ReqwestTransport {
+    /// Construct a new transport sending requests with a reqwest client.
+    pub fn new(client: reqwest::blocking::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Transport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, IpError> {
+        let mut builder = self
+            .client
+            .request(request.method, request.url)
+            .headers(request.headers);
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+
+        let response = builder.send()?;
+        Ok(HttpResponse {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body: response.text()?,
+        })
+    }
+}
+
+/// Transport of the async client using reqwest, used by default.
+#[derive(Debug, Clone)]
+pub struct AsyncReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl AsyncReqwestTransport {
+    /// Construct a new transport sending requests with a reqwest client.
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl AsyncTransport for AsyncReqwestTransport {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+            let mut builder = self
+                .client
+                .request(request.method, request.url)
+                .headers(request.headers);
+            if let Some(body) = request.body {
+                builder = builder.body(body);
+            }
+
+            let response = builder.send().await?;
+            Ok(HttpResponse {
+                status: response.status(),
+                headers: response.headers().clone(),
+                body: response.text().await?,
+            })
+        })
+    }
+}
@@ -0,0 +1,113 @@
+//   Copyright 2019 IPinfo library developers
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Warp filter extracting the details of the client IP of requests.
+
+use std::{convert::Infallible, net::SocketAddr};
+
+use crate::{forwarded, AsyncIpInfo, IpDetails, MiddlewareConfig};
+
+use warp::{http::HeaderMap, Filter};
+
+/// A warp filter extracting the [`IpDetails`] of the client IP of every request.
+///
+/// Without trusted proxies, the client IP is the remote address of the connection.
+/// Lookups go through the client's cache, and failed lookups extract `None` rather than
+/// rejecting the request.
+///
+/// # Examples
+///
+/// ```no_run
+/// use ipinfo::{ip_details_filter, AsyncIpInfo, IpDetails};
+/// use warp::Filter;
+///
+/// let ipinfo = AsyncIpInfo::new(Default::default()).expect("should construct");
+/// let route = warp::path("country")
+///     .and(ip_details_filter(ipinfo, Default::default()))
+///     .map(|details: Option<IpDetails>| details.map(|d| d.country).unwrap_or_default());
+/// ```
+pub fn ip_details_filter(
+    client: AsyncIpInfo,
+    config: MiddlewareConfig,
+) -> impl Filter<Extract = (Option<IpDetails>,), Error = Infallible> + Clone {
+    warp::addr::remote()
+        .and(warp::header::headers_cloned())
+        .then(move |remote: Option<SocketAddr>, headers: HeaderMap| {
+            let client = client.clone();
+            let ip = forwarded::client_ip(
+                config.trusted_proxies,
+                |name| {
+                    let values = headers.get_all(name).iter();
+                    values.filter_map(|value| value.to_str().ok())
+                },
+                remote.map(|remote| remote.ip()),
+            );
+
+            async move {
+                let ip = ip?;
+                match client.lookup_addr(ip).await {
+                    Ok(details) => Some(details),
+                    Err(_e) => {
+                        trace_event!(warn, ip = %ip, error = %_e, "client ip lookup failed");
+                        None
+                    }
+                }
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::{
+        testing::{details, MockTransport},
+        IpInfoConfig,
+    };
+
+    #[tokio::test]
+    async fn details_of_the_client_ip_are_extracted() {
+        let ipinfo = AsyncIpInfo::new(IpInfoConfig {
+            async_transport: Some(Arc::new(
+                MockTransport::new()
+                    .with_details(details("8.8.8.8", "US"))
+                    .with_details(details("1.1.1.1", "US")),
+            )),
+            ..Default::default()
+        })
+        .expect("should construct");
+        let filter = |trusted_proxies| {
+            let config = MiddlewareConfig {
+                trusted_proxies,
+                ..Default::default()
+            };
+            ip_details_filter(ipinfo.clone(), config)
+        };
+        let request = || {
+            warp::test::request()
+                .remote_addr(SocketAddr::from(([8, 8, 8, 8], 443)))
+                .header("x-forwarded-for", "1.1.1.1")
+        };
+
+        let details = request().filter(&filter(0)).await.unwrap();
+        assert_eq!(details.expect("should be extracted").ip, "8.8.8.8");
+        let details = request().filter(&filter(1)).await.unwrap();
+        assert_eq!(details.expect("should be extracted").ip, "1.1.1.1");
+
+        let details = warp::test::request().filter(&filter(0)).await.unwrap();
+        assert!(details.is_none());
+    }
+}